@@ -1,21 +1,86 @@
+use crate::tensor::dtype::Float;
 use crate::tensor::Tensor;
-use anyhow::Result;
+use crate::tensor::index::{IndexTensor, IntTensor};
+use anyhow::{bail, Result};
 
-pub fn mse_loss(predictions: Tensor, targets: Tensor) -> Result<Tensor> {
+pub fn mse_loss<T: Float>(predictions: Tensor<T>, targets: Tensor<T>) -> Result<Tensor<T>> {
     // MSE = 1 / n * sum((y_i - y_hat_i) ^ 2)
     let diff = predictions.sub(&targets)?;
-    let squared = diff.pow(2.0)?;
+    let squared = diff.pow(T::from_f64(2.0))?;
     Ok(squared.mean()?)
 }
 
-pub fn bce_loss(predictions: &Tensor, targets: &Tensor) -> Result<Tensor> {
-    let left = predictions.log(1.0_f64.exp())?.multiply(&targets)?;
-    let one = Tensor::vector(vec![1.0]);
-    let right = one.sub(&predictions)?.log(1.0_f64.exp())?.multiply(&one.sub(&targets)?)?;
-    let negative_one = Tensor::scalar(-1.0);
+pub fn bce_loss<T: Float>(predictions: &Tensor<T>, targets: &Tensor<T>) -> Result<Tensor<T>> {
+    let left = predictions.log(T::one().exp())?.multiply(&targets)?;
+    let one = Tensor::scalar(T::one());
+    let right = one.sub(&predictions)?.log(T::one().exp())?.multiply(&one.sub(&targets)?)?;
+    let negative_one = Tensor::scalar(-T::one());
     Ok(left.add(&right)?.mean()?.multiply(&negative_one)?)
 }
 
-pub fn cross_entropy_loss(prediction: &[Tensor], targets: &[Tensor]) -> Tensor{
-    todo!()
-}
\ No newline at end of file
+/// Mean negative log-likelihood of the `targets` classes under softmax(logits).
+/// `logits` is either a vector of class scores with a scalar target, or a
+/// `[batch, classes]` matrix with one target per row.
+pub fn cross_entropy_loss<T: Float>(logits: &Tensor<T>, targets: &IntTensor) -> Result<Tensor<T>> {
+    let (log_probs, index) = match targets {
+        IndexTensor::Scalar(target) => (logits.log_softmax()?, IntTensor::vector(vec![*target])),
+        // `log_softmax` normalizes columns, so each sample becomes one.
+        IndexTensor::Vector1D(targets) => (logits.t()?.log_softmax()?, IntTensor::matrix(vec![targets.clone()])),
+        IndexTensor::Matrix2D(_) => bail!("cross_entropy_loss targets must be a scalar or a vector"),
+    };
+    let negative_one = Tensor::scalar(-T::one());
+    log_probs.gather(0, &index)?.mean()?.multiply(&negative_one)
+}
+
+#[test]
+fn cross_entropy_loss_matches_softmax_minus_one_hot() -> Result<()> {
+    use crate::nn::Module;
+    use crate::nn::activation::Softmax;
+
+    // Two samples (rows) over three classes.
+    let logits: Tensor = Tensor::matrix(vec![vec![1.0, 2.0, 0.5], vec![0.0, 0.5, -1.0]]);
+    let targets = IntTensor::vector(vec![1, 0]);
+    let loss = cross_entropy_loss(&logits, &targets)?;
+
+    let probs = Softmax.forward(&logits)?.to_matrix()?;
+    let expected = -(probs[0][1].ln() + probs[1][0].ln()) / 2.0;
+    approx::assert_abs_diff_eq!(loss.item()?, expected, epsilon = 1e-12);
+
+    loss.backward()?;
+    let grad = logits.grad().unwrap().to_matrix()?;
+    for (sample, row) in grad.iter().enumerate() {
+        for (class, g) in row.iter().enumerate() {
+            let one_hot = if targets.flatten()[sample] == class as i64 { 1.0 } else { 0.0 };
+            approx::assert_abs_diff_eq!(*g, (probs[sample][class] - one_hot) / 2.0, epsilon = 1e-9);
+        }
+    }
+
+    let single = cross_entropy_loss(&Tensor::vector(vec![0.0, 0.0]), &IntTensor::scalar(1))?;
+    approx::assert_abs_diff_eq!(single.item()?, 2.0_f64.ln(), epsilon = 1e-12);
+
+    // A confidently wrong prediction: the softmax of the target underflows to zero.
+    let confident = Tensor::vector(vec![0.0, 800.0]);
+    let loss = cross_entropy_loss(&confident, &IntTensor::scalar(0))?;
+    assert_eq!(loss.item()?, 800.0);
+    loss.backward()?;
+    assert_eq!(confident.grad().unwrap().to_vec()?, vec![-1.0, 1.0]);
+
+    assert!(cross_entropy_loss(&logits, &IntTensor::matrix(vec![vec![1], vec![0]])).is_err());
+    Ok(())
+}
+#[test]
+fn bce_loss_takes_a_batch_of_rows() -> Result<()> {
+    let predictions: Tensor = Tensor::matrix(vec![vec![0.8], vec![0.3]]);
+    let targets = Tensor::matrix(vec![vec![1.0], vec![0.0]]);
+    let loss = bce_loss(&predictions, &targets)?;
+    approx::assert_abs_diff_eq!(loss.item()?, -(0.8_f64.ln() + 0.7_f64.ln()) / 2.0, epsilon = 1e-12);
+
+    loss.backward()?;
+    let grad = predictions.grad().unwrap().to_matrix()?;
+    approx::assert_abs_diff_eq!(grad[0][0], -1.0 / 0.8 / 2.0, epsilon = 1e-12);
+    approx::assert_abs_diff_eq!(grad[1][0], 1.0 / 0.7 / 2.0, epsilon = 1e-12);
+
+    let single = bce_loss(&Tensor::vector(vec![0.8]), &Tensor::vector(vec![1.0]))?;
+    approx::assert_abs_diff_eq!(single.item()?, -0.8_f64.ln(), epsilon = 1e-12);
+    Ok(())
+}
@@ -3,7 +3,9 @@ mod nn;
 mod loss_fn;
 
 use tensor::Tensor;
-use nn::Layer;
+use nn::Module;
+use nn::activation::{Sigmoid, Tanh};
+use nn::sequential::Sequential;
 use nn::Optimizer;
 use nn::optimizer::SGD;
 use crate::loss_fn::bce_loss;
@@ -11,8 +13,11 @@ use rayon::prelude::*;
 use anyhow::Result;
 
 fn test_xor() -> Result<()>{
-    let layer1 = nn::layer::Linear::new(2, 4);
-    let layer2 = nn::layer::Linear::new(4, 1);
+    let model = Sequential::new()
+        .add(nn::layer::Linear::new(2, 4))
+        .add(Tanh)
+        .add(nn::layer::Linear::new(4, 1))
+        .add(Sigmoid);
     let inputs = vec![
         Tensor::vector(vec![1.0, 1.0]),
         Tensor::vector(vec![0.0, 1.0]),
@@ -29,26 +34,23 @@ fn test_xor() -> Result<()>{
 
 
     let optimizer = SGD::new(0.1);
+    let params = model.parameters();
 
     for _ in 0..100_000 {
         for (_i, (input, target)) in inputs.iter().zip(&targets).enumerate() {
-            optimizer.zero_grad(&layer1.parameters());
-            optimizer.zero_grad(&layer2.parameters());
-            let hidden = layer1.forward(&input)?.tanh()?;
-            let outputs = layer2.forward(&hidden)?.sigmoid()?;
+            optimizer.zero_grad(&params);
+            let outputs = model.forward(&input)?;
 
             let loss = bce_loss(&outputs, &target)?;
             loss.backward()?;
-            optimizer.step(&layer1.parameters());
-            optimizer.step(&layer2.parameters());
+            optimizer.step(&params);
         }
     }
 
 
     for (_i, (input, _target)) in inputs.iter().zip(&targets).enumerate() {
-        let hidden = layer1.forward(&input)?.tanh()?;
-        let outputs = layer2.forward(&hidden)?.sigmoid()?;
-        println!("Updated output: {:?}", outputs.data.borrow().value);
+        let outputs = model.forward(&input)?;
+        println!("Updated output: {:?}", outputs.value());
     }
 
     Ok(())
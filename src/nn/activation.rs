@@ -0,0 +1,65 @@
+// Parameter-free modules wrapping the elementwise activations of `Tensor`, so
+// they can be chained in a `Sequential`.
+
+use crate::tensor::Tensor;
+use crate::tensor::dtype::Float;
+use super::Module;
+use anyhow::Result;
+
+pub struct Tanh;
+
+pub struct Sigmoid;
+
+pub struct ReLU;
+
+/// Softmax over the classes: the whole vector, or each row of a
+/// `[batch, classes]` matrix such as the output of a batched `Linear`.
+pub struct Softmax;
+
+impl<T: Float> Module<T> for Tanh {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.tanh()
+    }
+}
+
+impl<T: Float> Module<T> for Sigmoid {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.sigmoid()
+    }
+}
+
+impl<T: Float> Module<T> for ReLU {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.relu()
+    }
+}
+
+impl<T: Float> Module<T> for Softmax {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        match inputs.shape().len() {
+            // `Tensor::softmax` normalizes the columns of a matrix.
+            2 => inputs.t()?.softmax()?.t(),
+            _ => inputs.softmax(),
+        }
+    }
+}
+
+#[test]
+fn linear_softmax_batch_matches_samples() -> Result<()> {
+    use super::layer::Linear;
+    use super::sequential::Sequential;
+
+    let model = Sequential::new().add(Linear::new(3, 4)).add(Softmax);
+    let samples = [vec![1.0, -0.5, 2.0], vec![0.3, 0.0, -1.2]];
+    let outputs = model.forward(&Tensor::matrix(samples.to_vec()))?;
+    assert_eq!(outputs.shape(), vec![2, 4]);
+
+    for (sample, row) in samples.iter().zip(outputs.to_matrix()?) {
+        approx::assert_abs_diff_eq!(row.iter().sum::<f64>(), 1.0, epsilon = 1e-12);
+        let output = model.forward(&Tensor::vector(sample.clone()))?;
+        for (actual, expected) in row.iter().zip(output.to_vec()?) {
+            approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::tensor::Tensor;
+use crate::tensor::dtype::Float;
+use super::Module;
+use anyhow::Result;
+
+/// Zeroes each element with probability `p` while training and scales the
+/// survivors by `1 / (1 - p)`, so eval mode can pass inputs through unchanged.
+pub struct Dropout<T: Float = f64> {
+    p: f64,
+    rng: RefCell<StdRng>,
+    training: Cell<bool>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Float> Dropout<T> {
+    pub fn new(p: f64) -> Self {
+        Self::from_rng(p, StdRng::from_os_rng())
+    }
+
+    /// Same masks, in the same order, for every run with the same `seed`.
+    pub fn with_seed(p: f64, seed: u64) -> Self {
+        Self::from_rng(p, StdRng::seed_from_u64(seed))
+    }
+
+    fn from_rng(p: f64, rng: StdRng) -> Self {
+        assert!((0.0..=1.0).contains(&p), "Dropout probability must be in [0, 1], got {}", p);
+        Self { p, rng: RefCell::new(rng), training: Cell::new(true), _marker: PhantomData }
+    }
+}
+
+impl<T: Float> Module<T> for Dropout<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        if !self.training.get() || self.p == 0.0 {
+            return Ok(inputs.clone());
+        }
+
+        // The mask is a constant, so the gradient is routed through it as is.
+        let scale = if self.p < 1.0 { T::from_f64(1.0 / (1.0 - self.p)) } else { T::zero() };
+        let mut rng = self.rng.borrow_mut();
+        let mask: Vec<T> = (0..inputs.numel())
+            .map(|_| if rng.random::<f64>() < self.p { T::zero() } else { scale })
+            .collect();
+        inputs.multiply(&Tensor::from_flat(&inputs.shape(), &mask))
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+    }
+}
+
+#[test]
+fn dropout_masks_in_training_and_passes_through_in_eval() -> Result<()> {
+    let dropout = Dropout::with_seed(0.25, 7);
+    let x: Tensor = Tensor::matrix(vec![vec![2.0; 40]; 50]);
+    let y = dropout.forward(&x)?;
+    y.sum()?.backward()?;
+
+    let values = y.value().flatten();
+    let dropped = values.iter().filter(|v| **v == 0.0).count();
+    assert!(values.iter().all(|v| *v == 0.0 || approx::abs_diff_eq!(*v, 2.0 / 0.75, epsilon = 1e-12)));
+    assert!((400..600).contains(&dropped), "dropped {} of 2000", dropped);
+    // The gradient of each element is its mask entry.
+    for (g, v) in x.grad().unwrap().value().flatten().iter().zip(&values) {
+        approx::assert_abs_diff_eq!(*g, v / 2.0, epsilon = 1e-12);
+    }
+
+    let replay = Dropout::with_seed(0.25, 7).forward(&x)?;
+    assert_eq!(replay.value().flatten(), values);
+
+    dropout.eval();
+    assert_eq!(dropout.forward(&x)?.value().flatten(), x.value().flatten());
+
+    let single: super::Dropout<f32> = super::Dropout::with_seed(0.5, 7);
+    single.eval();
+    assert_eq!(single.forward(&Tensor::vector(vec![1.5f32, -2.0]))?.to_vec()?, vec![1.5, -2.0]);
+    Ok(())
+}
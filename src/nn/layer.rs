@@ -1,24 +1,442 @@
+use std::cell::Cell;
+use rand::Rng;
 use crate::tensor::Tensor;
+use crate::tensor::dtype::Float;
+use crate::tensor::ops::conv1d::ConvOptions;
+use crate::tensor::ops::batch_norm::channel_layout;
+use crate::tensor::ops::norm::moments;
+use crate::tensor::value::TensorValue;
 use super::parameter::{Parameter2D};
-use super::Layer;
-use anyhow::Result;
+use super::Module;
+use anyhow::{bail, Result};
 
-pub struct Linear {
-    params: Parameter2D,
+pub struct Linear<T: Float = f64> {
+    pub(crate) params: Parameter2D<T>,
 }
 
-impl Layer for Linear {
-    fn new(input_size: usize, output_size: usize) -> Self {
+impl<T: Float> Linear<T> {
+    pub fn new(input_size: usize, output_size: usize) -> Self {
         Self {
             params: Parameter2D::new(input_size, output_size),
         }
     }
+}
 
-    fn parameters(&self) -> Vec<&Tensor> {
-        self.params.parameters()
+impl<T: Float> Module<T> for Linear<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        self.params.forward(inputs)
     }
 
-    fn forward(&self, inputs: &Tensor) -> Result<Tensor> {
-        self.params.forward(inputs)
+    // The weights are named as if they lived on the layer itself.
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        self.params.own_parameters()
+    }
+}
+
+/// Convolution over `[batch, in_channels, length]` inputs.
+pub struct Conv1d<T: Float = f64> {
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
+    pub options: ConvOptions,
+}
+
+impl<T: Float> Conv1d<T> {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize, options: ConvOptions) -> Self {
+        let mut rng = rand::rng();
+        let group_in = in_channels / options.groups;
+        let fan_in = group_in * kernel_size;
+        let fan_out = out_channels / options.groups * kernel_size;
+        let xavier_std = (2.0 / (fan_in + fan_out) as f64).sqrt();
+
+        let weights = Tensor::tensor3d(
+            (0..out_channels).map(|_| {
+                (0..group_in).map(|_| {
+                    (0..kernel_size).map(|_| T::from_f64((rng.random::<f64>() - 0.5) * 2.0 * xavier_std)).collect()
+                }).collect()
+            }).collect()
+        );
+        let bias = Tensor::vector(
+            (0..out_channels).map(|_| T::from_f64((rng.random::<f64>() - 0.5) * 0.01)).collect()
+        );
+
+        Self { weights, bias, options }
+    }
+}
+
+impl<T: Float> Module<T> for Conv1d<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.conv1d(&self.weights, Some(&self.bias), self.options)
+    }
+
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
+    }
+}
+
+/// Convolution over `[batch, in_channels, height, width]` inputs.
+pub struct Conv2d<T: Float = f64> {
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
+    pub options: ConvOptions,
+}
+
+impl<T: Float> Conv2d<T> {
+    /// `kernel_size` is `(height, width)`.
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: (usize, usize), options: ConvOptions) -> Self {
+        let mut rng = rand::rng();
+        let (kernel_h, kernel_w) = kernel_size;
+        let group_in = in_channels / options.groups;
+        let fan_in = group_in * kernel_h * kernel_w;
+        let fan_out = out_channels / options.groups * kernel_h * kernel_w;
+        let xavier_std = (2.0 / (fan_in + fan_out) as f64).sqrt();
+
+        let weights = Tensor::tensor4d(
+            (0..out_channels).map(|_| {
+                (0..group_in).map(|_| {
+                    (0..kernel_h).map(|_| {
+                        (0..kernel_w).map(|_| T::from_f64((rng.random::<f64>() - 0.5) * 2.0 * xavier_std)).collect()
+                    }).collect()
+                }).collect()
+            }).collect()
+        );
+        let bias = Tensor::vector(
+            (0..out_channels).map(|_| T::from_f64((rng.random::<f64>() - 0.5) * 0.01)).collect()
+        );
+
+        Self { weights, bias, options }
+    }
+}
+
+impl<T: Float> Module<T> for Conv2d<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.conv2d(&self.weights, Some(&self.bias), self.options)
+    }
+
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
+    }
+}
+
+/// Batch normalization over dimension 1 of `[batch, channels]` and
+/// `[batch, channels, length]` inputs (`BatchNorm1d`) or `[batch, channels,
+/// height, width]` inputs (`BatchNorm2d`).
+///
+/// Training normalizes with the statistics of each batch and folds them into
+/// the running statistics, `running = (1 - momentum) * running + momentum * batch`
+/// (the variance unbiased); eval normalizes with the running statistics. These
+/// are buffers: part of `state_dict` but not of `parameters`.
+pub struct BatchNorm<T: Float, const SPATIAL: usize> {
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
+    pub running_mean: Tensor<T>,
+    pub running_var: Tensor<T>,
+    pub momentum: f64,
+    pub eps: f64,
+    training: Cell<bool>,
+}
+
+pub type BatchNorm1d<T = f64> = BatchNorm<T, 1>;
+
+pub type BatchNorm2d<T = f64> = BatchNorm<T, 2>;
+
+impl<T: Float, const SPATIAL: usize> BatchNorm<T, SPATIAL> {
+    pub fn new(num_features: usize) -> Self {
+        Self {
+            weights: Tensor::vector(vec![T::one(); num_features]),
+            bias: Tensor::vector(vec![T::zero(); num_features]),
+            running_mean: Tensor::vector(vec![T::zero(); num_features]),
+            running_var: Tensor::vector(vec![T::one(); num_features]),
+            momentum: 0.1,
+            eps: 1e-5,
+            training: Cell::new(true),
+        }
+    }
+
+    fn update_running_stats(&self, inputs: &Tensor<T>) -> Result<()> {
+        let shape = inputs.shape();
+        let (channels, channel_of) = channel_layout(&shape)?;
+        let count = inputs.numel() / channels;
+        let unbias = if count > 1 { count as f64 / (count - 1) as f64 } else { 1.0 };
+
+        let momentum = T::from_f64(self.momentum);
+        let keep = T::one() - momentum;
+        let batch = moments(&inputs.value().flatten(), channels, channel_of);
+        let mean = self.running_mean.value().flatten().iter().zip(&batch)
+            .map(|(running, (mean, _))| keep * *running + momentum * *mean)
+            .collect();
+        let var = self.running_var.value().flatten().iter().zip(&batch)
+            .map(|(running, (_, var))| keep * *running + momentum * *var * T::from_f64(unbias))
+            .collect();
+        self.running_mean.set_value(TensorValue::Vector1D(mean));
+        self.running_var.set_value(TensorValue::Vector1D(var));
+        Ok(())
+    }
+}
+
+impl<T: Float, const SPATIAL: usize> Module<T> for BatchNorm<T, SPATIAL> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        let rank = inputs.shape().len();
+        if !(SPATIAL == 1 && (rank == 2 || rank == 3) || SPATIAL == 2 && rank == 4) {
+            bail!("BatchNorm{}d got an input of shape {:?}", SPATIAL, inputs.shape());
+        }
+
+        let eps = T::from_f64(self.eps);
+        if self.training.get() {
+            // Normalize first, so a mismatched input leaves the running statistics alone.
+            let outputs = inputs.batch_norm(&self.weights, &self.bias, None, eps)?;
+            self.update_running_stats(inputs)?;
+            Ok(outputs)
+        } else {
+            inputs.batch_norm(&self.weights, &self.bias, Some((&self.running_mean, &self.running_var)), eps)
+        }
+    }
+
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
+    }
+
+    fn own_buffers(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("running_mean", &self.running_mean), ("running_var", &self.running_var)]
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+    }
+}
+
+/// Normalizes every sample over its trailing `normalized_shape` dimensions,
+/// with a learnable gain and bias per normalized element.
+pub struct LayerNorm<T: Float = f64> {
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
+    pub eps: f64,
+}
+
+impl<T: Float> LayerNorm<T> {
+    pub fn new(normalized_shape: &[usize]) -> Self {
+        let numel = normalized_shape.iter().product();
+        Self {
+            weights: Tensor::from_flat(normalized_shape, &vec![T::one(); numel]),
+            bias: Tensor::from_flat(normalized_shape, &vec![T::zero(); numel]),
+            eps: 1e-5,
+        }
     }
-}
\ No newline at end of file
+}
+
+impl<T: Float> Module<T> for LayerNorm<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.layer_norm(&self.weights, &self.bias, T::from_f64(self.eps))
+    }
+
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
+    }
+}
+
+/// Normalizes `groups` blocks of the channels of `[batch, channels, ...]`
+/// inputs per sample, with a learnable gain and bias per channel.
+pub struct GroupNorm<T: Float = f64> {
+    pub groups: usize,
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
+    pub eps: f64,
+}
+
+impl<T: Float> GroupNorm<T> {
+    pub fn new(groups: usize, channels: usize) -> Self {
+        assert!(groups > 0 && channels.is_multiple_of(groups), "GroupNorm channels ({}) must be divisible by groups ({})", channels, groups);
+        Self {
+            groups,
+            weights: Tensor::vector(vec![T::one(); channels]),
+            bias: Tensor::vector(vec![T::zero(); channels]),
+            eps: 1e-5,
+        }
+    }
+}
+
+impl<T: Float> Module<T> for GroupNorm<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.group_norm(self.groups, &self.weights, &self.bias, T::from_f64(self.eps))
+    }
+
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
+    }
+}
+
+#[test]
+fn linear_batch_matches_samples() -> Result<()> {
+    let layer: Linear = Linear::new(3, 2);
+    let samples = [vec![1.0, -0.5, 2.0], vec![0.3, 0.0, -1.2]];
+
+    let batch = Tensor::matrix(samples.to_vec());
+    let outputs = layer.forward(&batch)?;
+    assert_eq!(outputs.shape(), vec![2, 2]);
+    outputs.sum()?.backward()?;
+    let batch_grads: Vec<Vec<f64>> = layer.parameters().iter().map(|p| p.grad().unwrap().value().flatten()).collect();
+
+    layer.parameters().iter().for_each(|p| p.zero_grad());
+    for (sample, row) in samples.iter().zip(outputs.to_matrix()?) {
+        let output = layer.forward(&Tensor::vector(sample.clone()))?;
+        for (actual, expected) in row.iter().zip(output.to_vec()?) {
+            approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+        }
+        output.sum()?.backward()?;
+    }
+    for (param, batch_grad) in layer.parameters().iter().zip(batch_grads) {
+        for (actual, expected) in param.grad().unwrap().value().flatten().iter().zip(batch_grad) {
+            approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+        }
+    }
+
+    let feature_maps = Tensor::from_flat(&[2, 1, 3], &[0.0; 6]);
+    assert!(layer.forward(&feature_maps).is_err());
+    Ok(())
+}
+
+#[test]
+fn conv1d_layer_feeds_linear_head() -> Result<()> {
+    use crate::nn::Optimizer;
+    use crate::nn::optimizer::SGD;
+
+    // Learn to tell rising series from falling ones.
+    let conv: Conv1d = Conv1d::new(1, 4, 3, ConvOptions { padding: 1, ..Default::default() });
+    let head: Linear = Linear::new(4 * 6, 1);
+    assert_eq!(conv.weights.shape(), vec![4, 1, 3]);
+    let names: Vec<&str> = conv.own_parameters().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["weights", "bias"]);
+
+    let inputs = Tensor::tensor3d(vec![
+        vec![vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0]],
+        vec![vec![1.0, 0.8, 0.6, 0.4, 0.2, 0.0]],
+    ]);
+    let targets = Tensor::matrix(vec![vec![1.0], vec![0.0]]);
+    let mut params = conv.parameters();
+    params.extend(head.parameters());
+    let optimizer = SGD::new(0.5);
+
+    let mut losses = vec![];
+    for _ in 0..50 {
+        optimizer.zero_grad(&params);
+        let features = conv.forward(&inputs)?.relu()?.reshape(&[2, 24])?;
+        let outputs = head.forward(&features)?.sigmoid()?;
+        let loss = crate::loss_fn::mse_loss(outputs, targets.clone())?;
+        loss.backward()?;
+        optimizer.step(&params);
+        losses.push(loss.item()?);
+    }
+    assert!(losses[49] < losses[0]);
+    Ok(())
+}
+
+#[test]
+fn conv2d_layer_learns_to_classify_stripes() -> Result<()> {
+    use crate::nn::Optimizer;
+    use crate::nn::optimizer::SGD;
+
+    // Horizontal stripes are class 1, vertical stripes class 0.
+    let stripes = |horizontal: bool| -> Vec<Vec<f64>> {
+        (0..4).map(|i| (0..4).map(|j| if (if horizontal { i } else { j }) % 2 == 0 { 1.0 } else { 0.0 }).collect()).collect()
+    };
+    let inputs = Tensor::tensor4d(vec![vec![stripes(true)], vec![stripes(false)]]);
+    let targets = Tensor::matrix(vec![vec![1.0], vec![0.0]]);
+
+    let conv: Conv2d = Conv2d::new(1, 2, (2, 2), ConvOptions { stride: 2, ..Default::default() });
+    let head: Linear = Linear::new(2 * 2 * 2, 1);
+    assert_eq!(conv.weights.shape(), vec![2, 1, 2, 2]);
+    let mut params = conv.parameters();
+    params.extend(head.parameters());
+    let optimizer = SGD::new(0.5);
+
+    let mut losses = vec![];
+    for _ in 0..50 {
+        optimizer.zero_grad(&params);
+        let features = conv.forward(&inputs)?.tanh()?.reshape(&[2, 8])?;
+        let outputs = head.forward(&features)?.sigmoid()?;
+        let loss = crate::loss_fn::mse_loss(outputs, targets.clone())?;
+        loss.backward()?;
+        optimizer.step(&params);
+        losses.push(loss.item()?);
+    }
+    assert!(losses[49] < losses[0]);
+    Ok(())
+}
+
+#[test]
+fn batch_norm_tracks_running_stats_outside_the_optimizer() -> Result<()> {
+    use crate::nn::Optimizer;
+    use crate::nn::optimizer::SGD;
+
+    let norm: BatchNorm1d = BatchNorm1d::new(2);
+    let names: Vec<String> = norm.state_dict().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["weights", "bias", "running_mean", "running_var"]);
+    assert_eq!(norm.parameters().len(), 2);
+
+    // Channel 0 has mean 2 and unbiased variance 4, channel 1 mean -1 and variance 1.
+    let x = Tensor::matrix(vec![vec![0.0, -2.0], vec![2.0, -1.0], vec![4.0, 0.0]]);
+    let y = norm.forward(&x)?;
+    approx::assert_abs_diff_eq!(y.to_matrix()?[2][0], 1.5_f64.sqrt(), epsilon = 1e-5);
+    y.multiply(&Tensor::matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]))?.sum()?.backward()?;
+    SGD::new(0.1).step(&norm.parameters());
+    for (actual, expected) in norm.running_mean.to_vec()?.iter().zip([0.2, -0.1]) {
+        approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+    }
+    for (actual, expected) in norm.running_var.to_vec()?.iter().zip([0.9 + 0.4, 0.9 + 0.1]) {
+        approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+    }
+    assert!(norm.running_mean.grad().is_none());
+
+    // Eval normalizes with the running statistics and leaves them alone.
+    norm.eval();
+    let y = norm.forward(&x)?;
+    let (weight, bias) = (norm.weights.to_vec()?, norm.bias.to_vec()?);
+    approx::assert_abs_diff_eq!(y.to_matrix()?[2][0], (4.0 - 0.2) / (1.3_f64 + 1e-5).sqrt() * weight[0] + bias[0], epsilon = 1e-12);
+    assert_eq!(norm.running_mean.to_vec()?, vec![0.2, -0.1]);
+
+    let restored: BatchNorm1d = BatchNorm1d::new(2);
+    restored.load_state_dict(&norm.state_dict())?;
+    restored.eval();
+    assert_eq!(restored.forward(&x)?.to_matrix()?, y.to_matrix()?);
+    Ok(())
+}
+
+#[test]
+fn batch_norm2d_normalizes_over_batch_and_space() -> Result<()> {
+    let norm: BatchNorm2d = BatchNorm2d::new(3);
+    let data: Vec<f64> = (0..2 * 3 * 2 * 2).map(|i| (i as f64 * 0.7).sin() * 3.0 + i as f64).collect();
+    let y = norm.forward(&Tensor::from_flat(&[2, 3, 2, 2], &data))?;
+    let stats = moments(&y.value().flatten(), 3, |i| (i / 4) % 3);
+    for (mean, var) in stats {
+        approx::assert_abs_diff_eq!(mean, 0.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(var, 1.0, epsilon = 1e-5);
+    }
+
+    let running_mean = norm.running_mean.value().flatten();
+    assert!(norm.forward(&Tensor::from_flat(&[2, 4, 1, 1], &[1.0; 8])).is_err());
+    assert!(norm.forward(&Tensor::from_flat(&[2, 3, 4], &[1.0; 24])).is_err());
+    assert_eq!(norm.running_mean.value().flatten(), running_mean);
+    Ok(())
+}
+
+#[test]
+fn layer_and_group_norm_do_not_depend_on_batch_size() -> Result<()> {
+    let samples = [vec![1.0, -0.5, 2.0, 0.3], vec![0.2, 0.0, -1.2, 4.0], vec![3.0, 3.5, 2.5, 3.1]];
+    let layer_norm: LayerNorm = LayerNorm::new(&[4]);
+    let group_norm: GroupNorm = GroupNorm::new(2, 4);
+    assert_eq!(group_norm.parameters().len(), 2);
+
+    for norm in [&layer_norm as &dyn Module, &group_norm] {
+        let batch = norm.forward(&Tensor::matrix(samples.to_vec()))?.to_matrix()?;
+        for (sample, row) in samples.iter().zip(batch) {
+            let single = norm.forward(&Tensor::matrix(vec![sample.clone()]))?.to_matrix()?;
+            assert_eq!(single[0], row);
+        }
+    }
+
+    // Built alongside Linear: a pre-norm block trains end to end.
+    let linear: Linear = Linear::new(4, 2);
+    let outputs = linear.forward(&layer_norm.forward(&Tensor::matrix(samples.to_vec()))?)?;
+    outputs.sum()?.backward()?;
+    assert!(layer_norm.parameters().iter().chain(linear.parameters().iter()).all(|param| param.grad().is_some()));
+    Ok(())
+}
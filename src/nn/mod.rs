@@ -1,18 +1,207 @@
 use crate::tensor::Tensor;
-use anyhow::Result;
+use crate::tensor::dtype::Float;
+use crate::tensor::value::TensorValue;
+use anyhow::{bail, Result};
 pub mod layer;
 pub mod parameter;
 pub mod optimizer;
+pub mod activation;
+pub mod sequential;
+pub mod pooling;
+pub mod dropout;
 
-pub trait Layer {
-    fn new(input_size: usize, output_size: usize) -> Self;
-    fn parameters(&self) -> Vec<&Tensor>;
-    fn forward(&self, inputs: &Tensor) -> Result<Tensor>;
+// Part of the `nn` API even though the binary itself does not build one.
+#[allow(unused_imports)]
+pub use dropout::Dropout;
 
+/// A building block of a model: a forward computation, the parameters it
+/// owns, and the modules nested inside it.
+pub trait Module<T: Float = f64> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>>;
+
+    /// Parameters held by this module itself, not by its children.
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![]
+    }
+
+    /// Directly nested modules, by name.
+    fn children(&self) -> Vec<(&str, &dyn Module<T>)> {
+        vec![]
+    }
+
+    /// Every parameter of this module and its descendants, named by their path
+    /// from here, e.g. `layer1.weights`.
+    fn named_parameters(&self) -> Vec<(String, &Tensor<T>)> {
+        let mut named: Vec<(String, &Tensor<T>)> = self.own_parameters().into_iter()
+            .map(|(name, param)| (name.to_string(), param))
+            .collect();
+        for (prefix, child) in self.children() {
+            named.extend(child.named_parameters().into_iter()
+                .map(|(name, param)| (format!("{}.{}", prefix, name), param)));
+        }
+        named
+    }
+
+    fn parameters(&self) -> Vec<&Tensor<T>> {
+        self.named_parameters().into_iter().map(|(_, param)| param).collect()
+    }
+
+    /// State held by this module itself that is not trained, e.g. running
+    /// statistics. Saved with the parameters but never given to an optimizer.
+    fn own_buffers(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![]
+    }
+
+    /// Every buffer of this module and its descendants, named like `named_parameters`.
+    fn named_buffers(&self) -> Vec<(String, &Tensor<T>)> {
+        let mut named: Vec<(String, &Tensor<T>)> = self.own_buffers().into_iter()
+            .map(|(name, buffer)| (name.to_string(), buffer))
+            .collect();
+        for (prefix, child) in self.children() {
+            named.extend(child.named_buffers().into_iter()
+                .map(|(name, buffer)| (format!("{}.{}", prefix, name), buffer)));
+        }
+        named
+    }
+
+    /// A copy of everything needed to restore this module: the parameters,
+    /// then the buffers.
+    fn state_dict(&self) -> Vec<(String, TensorValue<T>)> {
+        self.named_parameters().into_iter()
+            .chain(self.named_buffers())
+            .map(|(name, tensor)| (name, tensor.value()))
+            .collect()
+    }
+
+    /// Restores a `state_dict`. Every entry must match a parameter or buffer
+    /// of the same shape, and every parameter and buffer must be present.
+    fn load_state_dict(&self, state: &[(String, TensorValue<T>)]) -> Result<()> {
+        let targets: Vec<(String, &Tensor<T>)> = self.named_parameters().into_iter()
+            .chain(self.named_buffers())
+            .collect();
+        if let Some((name, _)) = state.iter().find(|(name, _)| !targets.iter().any(|(target, _)| target == name)) {
+            bail!("Unexpected entry {} in state dict", name);
+        }
+        for (name, tensor) in &targets {
+            let Some((_, value)) = state.iter().find(|(entry, _)| entry == name) else {
+                bail!("Missing entry {} in state dict", name);
+            };
+            if value.shape() != tensor.shape() {
+                bail!("State dict entry {} has shape {:?}, expected {:?}", name, value.shape(), tensor.shape());
+            }
+        }
+        for (name, tensor) in targets {
+            let (_, value) = state.iter().find(|(entry, _)| *entry == name).unwrap();
+            tensor.set_value(value.clone());
+        }
+        Ok(())
+    }
+
+    /// Switches this module and its descendants between training and
+    /// evaluation behaviour. Modules that behave differently in the two modes
+    /// override this and still forward the call to their children.
+    fn set_training(&self, training: bool) {
+        for (_, child) in self.children() {
+            child.set_training(training);
+        }
+    }
+
+    fn train(&self) {
+        self.set_training(true);
+    }
+
+    fn eval(&self) {
+        self.set_training(false);
+    }
 }
 
 pub trait Optimizer {
-    fn step(&self, params: &[&Tensor]);
+    fn step<T: Float>(&self, params: &[&Tensor<T>]);
+
+    fn zero_grad<T: Float>(&self, params: &[&Tensor<T>]);
+}
+
+#[cfg(test)]
+struct Probe {
+    inner: layer::Linear,
+    training: std::cell::Cell<bool>,
+}
 
-    fn zero_grad(&self, params: &[&Tensor]);
-}
\ No newline at end of file
+#[cfg(test)]
+impl Module for Probe {
+    fn forward(&self, inputs: &Tensor) -> Result<Tensor> {
+        self.inner.forward(inputs)
+    }
+
+    fn children(&self) -> Vec<(&str, &dyn Module)> {
+        vec![("inner", &self.inner)]
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+        for (_, child) in self.children() {
+            child.set_training(training);
+        }
+    }
+}
+
+#[cfg(test)]
+struct Pair {
+    layer1: Probe,
+    layer2: layer::Linear,
+}
+
+#[cfg(test)]
+impl Module for Pair {
+    fn forward(&self, inputs: &Tensor) -> Result<Tensor> {
+        self.layer2.forward(&self.layer1.forward(inputs)?.tanh()?)
+    }
+
+    fn children(&self) -> Vec<(&str, &dyn Module)> {
+        vec![("layer1", &self.layer1), ("layer2", &self.layer2)]
+    }
+}
+
+#[test]
+fn module_names_parameters_and_switches_mode() -> Result<()> {
+    let model = Pair {
+        layer1: Probe { inner: layer::Linear::new(2, 3), training: std::cell::Cell::new(true) },
+        layer2: layer::Linear::new(3, 1),
+    };
+    let names: Vec<String> = model.named_parameters().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["layer1.inner.weights", "layer1.inner.bias", "layer2.weights", "layer2.bias"]);
+    assert!(std::ptr::eq(model.parameters()[2], &model.layer2.params.weights));
+
+    model.eval();
+    assert!(!model.layer1.training.get());
+    model.train();
+    assert!(model.layer1.training.get());
+
+    assert_eq!(model.forward(&Tensor::vector(vec![1.0, -1.0]))?.shape(), vec![1]);
+    Ok(())
+}
+
+#[test]
+fn state_dict_round_trips_and_rejects_mismatches() -> Result<()> {
+    let source = Pair {
+        layer1: Probe { inner: layer::Linear::new(2, 3), training: std::cell::Cell::new(true) },
+        layer2: layer::Linear::new(3, 1),
+    };
+    let target = Pair {
+        layer1: Probe { inner: layer::Linear::new(2, 3), training: std::cell::Cell::new(true) },
+        layer2: layer::Linear::new(3, 1),
+    };
+    let state = source.state_dict();
+    target.load_state_dict(&state)?;
+    let x = Tensor::vector(vec![0.5, -2.0]);
+    assert_eq!(target.forward(&x)?.to_vec()?, source.forward(&x)?.to_vec()?);
+
+    let mut renamed = state.clone();
+    renamed[0].0 = "layer1.weights".to_string();
+    assert!(target.load_state_dict(&renamed).is_err());
+    let mut reshaped = state.clone();
+    reshaped[1].1 = TensorValue::Vector1D(vec![0.0; 4]);
+    assert!(target.load_state_dict(&reshaped).is_err());
+    assert!(target.load_state_dict(&state[1..]).is_err());
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use rayon::prelude::*;
 use crate::tensor::{Tensor};
 use crate::tensor::value::TensorValue;
+use crate::tensor::dtype::Float;
 use super::Optimizer;
 
 pub struct SGD {
@@ -14,41 +15,37 @@ impl SGD {
 }
 
 impl Optimizer for SGD {
-    fn step(&self, params: &[&Tensor]) {
+    fn step<T: Float>(&self, params: &[&Tensor<T>]) {
         for param in params {
-            let mut data = param.data.borrow_mut();
+            // 没有梯度的参数不更新
+            let grad = match param.grad() {
+                Some(grad) => grad.value(),
+                None => continue,
+            };
 
             // 计算梯度更新量
-            let update = match &data.grad {
-                TensorValue::Scalar(g) => TensorValue::Scalar(self.lr * g),
+            let update = match &grad {
+                TensorValue::Scalar(g) => TensorValue::Scalar(T::from_f64(self.lr) * *g),
                 TensorValue::Vector1D(v) => {
-                    TensorValue::Vector1D(v.iter().map(|g| self.lr * g).collect())
+                    TensorValue::Vector1D(v.iter().map(|g| T::from_f64(self.lr) * *g).collect())
                 }
                 TensorValue::Matrix2D(m) => {
                     TensorValue::Matrix2D(
                         m.iter().map(|row|
-                            row.iter().map(|g| self.lr * g).collect()
+                            row.iter().map(|g| T::from_f64(self.lr) * *g).collect()
                         ).collect()
                     )
                 }
-                TensorValue::Tensor3D(_) => todo!()
+                TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => grad.map(|g| T::from_f64(self.lr) * g),
             };
 
-            data.value = data.value.sub(&update);
+            param.set_value(param.value().sub(&update));
         }
     }
 
-    fn zero_grad(&self, params: &[&Tensor]) {
+    fn zero_grad<T: Float>(&self, params: &[&Tensor<T>]) {
         for param in params {
-            let mut data = param.data.borrow_mut();
-            data.grad = match &data.grad {
-                TensorValue::Scalar(_) => TensorValue::Scalar(0.0),
-                TensorValue::Vector1D(v) => TensorValue::Vector1D(vec![0.0; v.len()]),
-                TensorValue::Matrix2D(m) => {
-                    TensorValue::Matrix2D(vec![vec![0.0; m[0].len()]; m.len()])
-                }
-                TensorValue::Tensor3D(_) => todo!()
-            };
+            param.zero_grad();
         }
     }
 }
\ No newline at end of file
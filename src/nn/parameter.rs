@@ -1,41 +1,44 @@
 use rand::Rng;
 use crate::tensor::{Tensor};
-use super::Layer;
-use anyhow::Result;
+use crate::tensor::dtype::Float;
+use super::Module;
+use anyhow::{bail, Result};
 
-pub struct Parameter1D {
-    pub weights: Tensor,
-    pub bias: Tensor,
+pub struct Parameter1D<T: Float = f64> {
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
 }
 
-pub struct Parameter2D {
-    pub weights: Tensor,
-    pub bias: Tensor,
+pub struct Parameter2D<T: Float = f64> {
+    pub weights: Tensor<T>,
+    pub bias: Tensor<T>,
 }
 
-impl Layer for Parameter1D {
-    fn new(input_size: usize, output_size: usize) -> Self {
+impl<T: Float> Parameter1D<T> {
+    pub fn new(input_size: usize, output_size: usize) -> Self {
         assert_eq!(input_size, output_size, "input_size and output_size must be the same in Parameter1D.");
-        let weights = Tensor::vector(vec![rand::random::<f64>() * 0.1; input_size]);
-        let bias = Tensor::scalar(rand::random::<f64>() * 0.1);
+        let weights = Tensor::vector(vec![T::from_f64(rand::random::<f64>() * 0.1); input_size]);
+        let bias = Tensor::scalar(T::from_f64(rand::random::<f64>() * 0.1));
 
         Parameter1D{
             weights,
             bias,
         }
     }
+}
 
-    fn parameters(&self) -> Vec<&Tensor> {
-        vec![&self.weights, &self.bias]
+impl<T: Float> Module<T> for Parameter1D<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.multiply(&self.weights)?.add(&self.bias)
     }
 
-    fn forward(&self, inputs: &Tensor) -> Result<Tensor> {
-        inputs.multiply(&self.weights)?.add(&self.bias)
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
     }
 }
 
-impl Layer for Parameter2D {
-    fn new(input_size: usize, output_size: usize) -> Self {
+impl<T: Float> Parameter2D<T> {
+    pub fn new(input_size: usize, output_size: usize) -> Self {
         let mut rng = rand::rng();
         let xavier_std = (2.0 / (input_size as f64 + output_size as f64)).sqrt();
 
@@ -43,14 +46,14 @@ impl Layer for Parameter2D {
             (0..output_size).map(|_| {
                 (0..input_size).map(|_| {
                     let val: f64 = rng.random(); // [0,1) 随机数
-                    (val - 0.5) * 2.0 * xavier_std // 转换到 [-xavier_std, xavier_std]
+                    T::from_f64((val - 0.5) * 2.0 * xavier_std) // 转换到 [-xavier_std, xavier_std]
                 }).collect()
             }).collect()
         );
         let bias = Tensor::vector(
             (0..output_size).map(|_| {
                 let val: f64 = rng.random();
-                (val - 0.5) * 0.01 // [-0.005, 0.005]
+                T::from_f64((val - 0.5) * 0.01) // [-0.005, 0.005]
             }).collect()
         );
 
@@ -59,12 +62,20 @@ impl Layer for Parameter2D {
             bias,
         }
     }
+}
 
-    fn parameters(&self) -> Vec<&Tensor> {
-        vec![&self.weights, &self.bias]
+impl<T: Float> Module<T> for Parameter2D<T> {
+    /// A single `[input_size]` sample, or a `[batch, input_size]` batch with
+    /// one sample per row, giving `[batch, output_size]`.
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        match inputs.shape().len() {
+            1 => self.weights.matmul(inputs)?.add(&self.bias),
+            2 => inputs.matmul(&self.weights.t()?)?.add(&self.bias),
+            _ => bail!("Linear expects a vector or a [batch, features] matrix, got shape {:?}", inputs.shape()),
+        }
     }
 
-    fn forward(&self, inputs: &Tensor) -> Result<Tensor> {
-        self.weights.matmul(inputs)?.add(&self.bias)
+    fn own_parameters(&self) -> Vec<(&str, &Tensor<T>)> {
+        vec![("weights", &self.weights), ("bias", &self.bias)]
     }
 }
\ No newline at end of file
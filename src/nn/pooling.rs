@@ -0,0 +1,151 @@
+// Parameter-free modules wrapping the pooling ops of `Tensor`.
+
+use crate::tensor::Tensor;
+use crate::tensor::dtype::Float;
+use crate::tensor::ops::pool::Pooling;
+use super::Module;
+use anyhow::{bail, Result};
+
+pub struct MaxPool1d {
+    pub pooling: Pooling,
+}
+
+pub struct MaxPool2d {
+    pub pooling: Pooling,
+}
+
+pub struct AvgPool1d {
+    pub pooling: Pooling,
+}
+
+pub struct AvgPool2d {
+    pub pooling: Pooling,
+}
+
+/// Averages `[batch, channels, length]` inputs down to `output_size` positions.
+pub struct AdaptiveAvgPool1d {
+    pub output_size: usize,
+}
+
+/// Averages `[batch, channels, height, width]` inputs down to `output_size`.
+pub struct AdaptiveAvgPool2d {
+    pub output_size: (usize, usize),
+}
+
+/// Averages every channel of a `[batch, channels, ...]` input down to a
+/// single value, giving `[batch, channels]` ready for a `Linear` head.
+pub struct GlobalAvgPool;
+
+impl MaxPool1d {
+    pub fn new(pooling: Pooling) -> Self {
+        Self { pooling }
+    }
+}
+
+impl MaxPool2d {
+    pub fn new(pooling: Pooling) -> Self {
+        Self { pooling }
+    }
+}
+
+impl AvgPool1d {
+    pub fn new(pooling: Pooling) -> Self {
+        Self { pooling }
+    }
+}
+
+impl AvgPool2d {
+    pub fn new(pooling: Pooling) -> Self {
+        Self { pooling }
+    }
+}
+
+impl AdaptiveAvgPool1d {
+    pub fn new(output_size: usize) -> Self {
+        Self { output_size }
+    }
+}
+
+impl AdaptiveAvgPool2d {
+    pub fn new(output_size: (usize, usize)) -> Self {
+        Self { output_size }
+    }
+}
+
+impl<T: Float> Module<T> for MaxPool1d {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.max_pool1d(self.pooling.clone())
+    }
+}
+
+impl<T: Float> Module<T> for MaxPool2d {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.max_pool2d(self.pooling.clone())
+    }
+}
+
+impl<T: Float> Module<T> for AvgPool1d {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.avg_pool1d(self.pooling.clone())
+    }
+}
+
+impl<T: Float> Module<T> for AvgPool2d {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.avg_pool2d(self.pooling.clone())
+    }
+}
+
+impl<T: Float> Module<T> for AdaptiveAvgPool1d {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        inputs.avg_pool1d(Pooling::Adaptive(vec![self.output_size]))
+    }
+}
+
+impl<T: Float> Module<T> for AdaptiveAvgPool2d {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        let (height, width) = self.output_size;
+        inputs.avg_pool2d(Pooling::Adaptive(vec![height, width]))
+    }
+}
+
+impl<T: Float> Module<T> for GlobalAvgPool {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        let shape = inputs.shape();
+        let pooled = match shape.len() {
+            3 => inputs.avg_pool1d(Pooling::Adaptive(vec![1]))?,
+            4 => inputs.avg_pool2d(Pooling::Adaptive(vec![1, 1]))?,
+            _ => bail!("GlobalAvgPool expects a [batch, channels, ...] input with 1 or 2 spatial dimensions, got shape {:?}", shape),
+        };
+        pooled.reshape(&shape[..2])
+    }
+}
+
+#[test]
+fn conv_pool_stack_feeds_linear() -> Result<()> {
+    use crate::nn::activation::ReLU;
+    use crate::nn::layer::{Conv2d, Linear};
+    use crate::nn::sequential::Sequential;
+    use crate::tensor::ops::conv1d::ConvOptions;
+
+    let model: Sequential = Sequential::new()
+        .add(Conv2d::new(1, 4, (3, 3), ConvOptions { padding: 1, ..Default::default() }))
+        .add(ReLU)
+        .add(MaxPool2d::new(Pooling::window(2)))
+        .add(AdaptiveAvgPool2d::new((2, 1)))
+        .add(GlobalAvgPool)
+        .add(Linear::new(4, 3));
+
+    let image: Vec<Vec<f64>> = (0..6).map(|i| (0..6).map(|j| ((i * 6 + j) as f64 * 0.3).cos()).collect()).collect();
+    let inputs = Tensor::tensor4d(vec![vec![image.clone()], vec![image]]);
+    let outputs = model.forward(&inputs)?;
+    assert_eq!(outputs.shape(), vec![2, 3]);
+
+    outputs.sum()?.backward()?;
+    assert!(model.parameters().iter().all(|param| param.grad().is_some()));
+
+    let flat: Tensor = Tensor::matrix(vec![vec![1.0, 2.0]]);
+    assert!(GlobalAvgPool.forward(&flat).is_err());
+    assert!(model.forward(&Tensor::tensor4d(vec![vec![vec![vec![1.0]]]])).is_err());
+    Ok(())
+}
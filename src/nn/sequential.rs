@@ -0,0 +1,80 @@
+use crate::tensor::Tensor;
+use crate::tensor::dtype::Float;
+use super::Module;
+use anyhow::Result;
+
+/// Runs its modules one after the other. Children are named by their
+/// position, so parameters come out as `0.weights`, `2.bias`, ...
+pub struct Sequential<T: Float = f64> {
+    modules: Vec<(String, Box<dyn Module<T>>)>,
+}
+
+impl<T: Float> Sequential<T> {
+    pub fn new() -> Self {
+        Sequential { modules: vec![] }
+    }
+
+    pub fn add(mut self, module: impl Module<T> + 'static) -> Self {
+        let name = self.modules.len().to_string();
+        self.modules.push((name, Box::new(module)));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+impl<T: Float> Default for Sequential<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> Module<T> for Sequential<T> {
+    fn forward(&self, inputs: &Tensor<T>) -> Result<Tensor<T>> {
+        let mut outputs = inputs.clone();
+        for (_, module) in &self.modules {
+            outputs = module.forward(&outputs)?;
+        }
+        Ok(outputs)
+    }
+
+    fn children(&self) -> Vec<(&str, &dyn Module<T>)> {
+        self.modules.iter().map(|(name, module)| (name.as_str(), module.as_ref())).collect()
+    }
+}
+
+#[test]
+fn sequential_chains_modules_and_collects_parameters() -> Result<()> {
+    use super::activation::{Sigmoid, Tanh};
+    use super::layer::Linear;
+    use super::Optimizer;
+    use super::optimizer::SGD;
+
+    let model = Sequential::new()
+        .add(Linear::new(2, 4))
+        .add(Tanh)
+        .add(Linear::new(4, 1))
+        .add(Sigmoid);
+    let names: Vec<String> = model.named_parameters().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["0.weights", "0.bias", "2.weights", "2.bias"]);
+
+    let x = Tensor::vector(vec![1.0, 0.0]);
+    let params = model.parameters();
+    let hidden = params[0].matmul(&x)?.add(params[1])?.tanh()?;
+    let expected = params[2].matmul(&hidden)?.add(params[3])?.sigmoid()?;
+    let output = model.forward(&x)?;
+    assert_eq!(output.to_vec()?, expected.to_vec()?);
+
+    let optimizer = SGD::new(0.1);
+    let before = params[0].value().flatten();
+    crate::loss_fn::bce_loss(&output, &Tensor::vector(vec![1.0]))?.backward()?;
+    optimizer.step(&params);
+    assert_ne!(params[0].value().flatten(), before);
+    Ok(())
+}
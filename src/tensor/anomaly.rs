@@ -0,0 +1,105 @@
+// Anomaly detection: opt-in checks for NaN/Inf in op outputs and gradients,
+// reporting the op that produced them and where it was created.
+
+use crate::tensor::dtype::Float;
+use std::cell::Cell;
+use std::panic::Location;
+use super::value::TensorValue;
+use super::Tensor;
+use anyhow::{bail, Result};
+
+thread_local! {
+    static DETECT_ANOMALY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Turns anomaly detection on or off for the current thread. While it is on,
+/// every op records where it was created and fails if its output contains NaN
+/// or Inf, and `Tensor::backward` fails as soon as an op produces a NaN or Inf
+/// gradient. This slows everything down, so only enable it while debugging.
+pub fn set_detect_anomaly(enabled: bool) {
+    DETECT_ANOMALY.with(|flag| flag.set(enabled));
+}
+
+pub fn is_detect_anomaly_enabled() -> bool {
+    DETECT_ANOMALY.with(|flag| flag.get())
+}
+
+/// Called by every op on its freshly built result.
+#[track_caller]
+pub fn check_forward<T: Float>(result: &Tensor<T>) -> Result<()> {
+    if is_detect_anomaly_enabled() {
+        result.data.borrow_mut().created_at = Some(Location::caller());
+        let data = result.data.borrow();
+        if data.value.flatten().iter().any(|x| !x.is_finite()) {
+            bail!("{:?} produced NaN/Inf in its output\n{}", data.operation, describe(result));
+        }
+    }
+    // Inside `forward_ad::jvp` the tangent is pushed along here as well.
+    super::forward_ad::propagate(result)
+}
+
+/// Called by `ops::_backward` with the input gradients `tensor`'s op produced.
+pub fn check_backward<T: Float>(tensor: &Tensor<T>, input_grads: &[TensorValue<T>]) -> Result<()> {
+    if !is_detect_anomaly_enabled() {
+        return Ok(());
+    }
+
+    for (i, grad) in input_grads.iter().enumerate() {
+        if grad.flatten().iter().any(|x| !x.is_finite()) {
+            bail!(
+                "{:?} backward produced NaN/Inf in the gradient of input {}\n{}",
+                tensor.data.borrow().operation, i, describe(tensor)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn describe<T: Float>(tensor: &Tensor<T>) -> String {
+    let data = tensor.data.borrow();
+    let input_shapes: Vec<Vec<usize>> = data.dependencies.iter()
+        .map(|dep| dep.shape())
+        .collect();
+    let created_at = match data.created_at {
+        Some(location) => location.to_string(),
+        None => "unknown (created before anomaly detection was enabled)".to_string(),
+    };
+    format!("  input shapes: {:?}\n  created at: {}", input_shapes, created_at)
+}
+
+#[test]
+fn anomaly_reports_forward_nan() -> Result<()> {
+    set_detect_anomaly(true);
+    let x = Tensor::vector(vec![1.0, 0.0]);
+    let line = line!() + 1;
+    let error = x.log(2.0).unwrap_err().to_string();
+    set_detect_anomaly(false);
+
+    assert!(error.starts_with("Log(2.0) produced NaN/Inf in its output"), "{}", error);
+    assert!(error.contains("input shapes: [[2]]"), "{}", error);
+    assert!(error.contains(&format!("created at: {}:{}", file!(), line)), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn anomaly_reports_backward_nan() -> Result<()> {
+    set_detect_anomaly(true);
+    // 1 / x is still finite here, but its gradient -1 / x^2 overflows.
+    let x = Tensor::vector(vec![1e-200, 2.0]);
+    let loss = Tensor::scalar(1.0).div(&x)?.sum()?;
+    let error = loss.backward().unwrap_err().to_string();
+    set_detect_anomaly(false);
+
+    assert!(error.starts_with("Div backward produced NaN/Inf in the gradient of input 1"), "{}", error);
+    assert!(error.contains("input shapes: [[], [2]]"), "{}", error);
+    assert!(error.contains(file!()), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn anomaly_detection_is_off_by_default() -> Result<()> {
+    let x = Tensor::vector(vec![1.0, 0.0]);
+    let y = x.log(2.0)?;
+    assert!(y.data.borrow().created_at.is_none());
+    Ok(())
+}
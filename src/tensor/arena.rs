@@ -0,0 +1,212 @@
+// Node storage. The nodes of every graph of one element type live in the slots
+// of a thread-local arena, and a `Tensor` holds a counted `NodeRef` to its
+// slot. A slot whose last `NodeRef` is dropped goes on a free list and is
+// reused by the next node, so building a graph does not go through the
+// allocator once per node. Each slot also has room for a mark, which a `Tape`
+// sets on the nodes it records instead of keeping a map keyed by node.
+//
+// Slots are never handed back to the allocator: an arena is as large as the
+// most nodes its thread ever had alive at once.
+
+use crate::tensor::dtype::{DType, Float};
+use std::any::Any;
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::fmt;
+use std::ptr::NonNull;
+use super::node::NodeData;
+use super::operation::Operation;
+use super::value::TensorValue;
+
+struct Slot<T: Float> {
+    strong: Cell<usize>,
+    // Bumped when the slot is freed, so that a `WeakNodeRef` to the old node
+    // does not see the next one.
+    generation: Cell<u32>,
+    // The tape that marked the slot last and the node's position on it.
+    tape: Cell<u64>,
+    position: Cell<usize>,
+    data: RefCell<NodeData<T>>,
+}
+
+impl<T: Float> Slot<T> {
+    fn new() -> Self {
+        Slot {
+            strong: Cell::new(0),
+            generation: Cell::new(0),
+            tape: Cell::new(0),
+            position: Cell::new(0),
+            data: RefCell::new(empty()),
+        }
+    }
+}
+
+struct Arena<T: Float> {
+    free: RefCell<Vec<NonNull<Slot<T>>>>,
+    // Size of the next chunk; each chunk is twice the size of the last.
+    chunk: Cell<usize>,
+}
+
+const FIRST_CHUNK: usize = 256;
+
+thread_local! {
+    static F32: Arena<f32> = Arena::new();
+    static F64: Arena<f64> = Arena::new();
+}
+
+impl<T: Float> Arena<T> {
+    fn new() -> Self {
+        Arena { free: RefCell::new(Vec::new()), chunk: Cell::new(FIRST_CHUNK) }
+    }
+
+    fn alloc(&self) -> NonNull<Slot<T>> {
+        let mut free = self.free.borrow_mut();
+        if free.is_empty() {
+            let size = self.chunk.replace(self.chunk.get() * 2);
+            let chunk: &'static [Slot<T>] = Vec::from_iter((0..size).map(|_| Slot::new())).leak();
+            free.extend(chunk.iter().rev().map(NonNull::from));
+        }
+        free.pop().unwrap()
+    }
+}
+
+/// Runs `f` on this thread's arena for `T`, or returns `None` once the arena
+/// has been torn down at thread exit.
+fn with_arena<T: Float, R>(f: impl FnOnce(&Arena<T>) -> R) -> Option<R> {
+    fn typed<U: Float, T: Float>(arena: &Arena<U>) -> &Arena<T> {
+        (arena as &dyn Any).downcast_ref().expect("Arena of the wrong element type")
+    }
+    match T::DTYPE {
+        DType::F32 => F32.try_with(|arena| f(typed(arena))).ok(),
+        DType::F64 => F64.try_with(|arena| f(typed(arena))).ok(),
+    }
+}
+
+/// What a free slot holds: nothing that owns memory.
+fn empty<T: Float>() -> NodeData<T> {
+    NodeData {
+        value: TensorValue::Scalar(T::zero()),
+        grad: None,
+        retain_grad: false,
+        grad_graph: None,
+        operation: Operation::None,
+        dependencies: vec![],
+        hooks: vec![],
+        created_at: None,
+        tangent: None,
+    }
+}
+
+/// A counted reference to a node in the arena, like an `Rc<RefCell<NodeData>>`.
+pub struct NodeRef<T: Float = f64> {
+    slot: NonNull<Slot<T>>,
+}
+
+impl<T: Float> NodeRef<T> {
+    pub fn new(data: NodeData<T>) -> Self {
+        // Only fails while the thread is exiting; a node made then is leaked.
+        let slot = with_arena(|arena: &Arena<T>| arena.alloc())
+            .unwrap_or_else(|| NonNull::from(Box::leak(Box::new(Slot::new()))));
+        let node = NodeRef { slot };
+        node.slot().strong.set(1);
+        *node.slot().data.borrow_mut() = data;
+        node
+    }
+
+    fn slot(&self) -> &Slot<T> {
+        // Slots live as long as the thread and this reference keeps it in use.
+        unsafe { self.slot.as_ref() }
+    }
+
+    pub fn borrow(&self) -> Ref<'_, NodeData<T>> {
+        self.slot().data.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, NodeData<T>> {
+        self.slot().data.borrow_mut()
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.slot == other.slot
+    }
+
+    pub fn downgrade(&self) -> WeakNodeRef<T> {
+        WeakNodeRef { slot: self.slot, generation: self.slot().generation.get() }
+    }
+
+    /// The position this node was given by the tape `tape`, if that was the
+    /// last tape to mark it.
+    pub(crate) fn position_on(&self, tape: u64) -> Option<usize> {
+        let slot = self.slot();
+        (slot.tape.get() == tape).then(|| slot.position.get())
+    }
+
+    pub(crate) fn mark(&self, tape: u64, position: usize) {
+        self.slot().tape.set(tape);
+        self.slot().position.set(position);
+    }
+}
+
+impl<T: Float> Clone for NodeRef<T> {
+    fn clone(&self) -> Self {
+        let strong = &self.slot().strong;
+        strong.set(strong.get() + 1);
+        NodeRef { slot: self.slot }
+    }
+}
+
+impl<T: Float> Drop for NodeRef<T> {
+    fn drop(&mut self) {
+        let slot = self.slot();
+        slot.strong.set(slot.strong.get() - 1);
+        if slot.strong.get() > 0 {
+            return;
+        }
+        let data = slot.data.replace(empty());
+        slot.generation.set(slot.generation.get().wrapping_add(1));
+        slot.tape.set(0);
+        with_arena(|arena: &Arena<T>| arena.free.borrow_mut().push(self.slot));
+        // Dropping the node may free its dependencies in turn, so the free list
+        // must not be borrowed by then.
+        drop(data);
+    }
+}
+
+impl<T: Float> fmt::Debug for NodeRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.slot().data.try_borrow() {
+            Ok(data) => data.fmt(f),
+            Err(_) => f.write_str("<borrowed>"),
+        }
+    }
+}
+
+/// A reference that does not keep its node alive, like a `Weak`.
+pub struct WeakNodeRef<T: Float = f64> {
+    slot: NonNull<Slot<T>>,
+    generation: u32,
+}
+
+impl<T: Float> WeakNodeRef<T> {
+    pub fn upgrade(&self) -> Option<NodeRef<T>> {
+        let slot = unsafe { self.slot.as_ref() };
+        if slot.generation.get() != self.generation || slot.strong.get() == 0 {
+            return None;
+        }
+        slot.strong.set(slot.strong.get() + 1);
+        Some(NodeRef { slot: self.slot })
+    }
+}
+
+#[test]
+fn arena_reuses_freed_slots() {
+    let a = NodeRef::<f64>::new(empty());
+    let slot = a.slot;
+    let weak = a.downgrade();
+    assert!(weak.upgrade().is_some_and(|b| b.ptr_eq(&a)));
+
+    drop(a);
+    assert!(weak.upgrade().is_none());
+    let b = NodeRef::<f64>::new(empty());
+    assert_eq!(b.slot, slot);
+    assert!(weak.upgrade().is_none());
+}
@@ -1,41 +1,175 @@
+use crate::tensor::dtype::Float;
 use crate::tensor::value::TensorValue;
-use super::{Tensor, NodeData, ops};
+use crate::tensor::operation::Operation;
+use super::tape::Tape;
+use super::{Tensor, ops};
 use anyhow::Result;
 
-impl Tensor {
+impl<T: Float> Tensor<T> {
     pub fn backward(&self) -> Result<()>{
-        {
-            let mut data = self.data.borrow_mut();
-            data.grad = match &data.value {
-                TensorValue::Scalar(_) => TensorValue::Scalar(1.0),
-                TensorValue::Vector1D(v) => TensorValue::Vector1D(vec![1.0; v.len()]),
-                TensorValue::Matrix2D(m ) => {
-                    TensorValue::Matrix2D(vec![vec![1.0; m[0].len()]; m.len()])
-                }
-                TensorValue::Tensor3D(t) => todo!()
-            };
-        }
+        let seed = self.data.borrow().value.ones_like();
+        self.backward_with_grad(seed)
+    }
+
+    /// Keeps this tensor's gradient after backward. Only leaves keep theirs by
+    /// default; intermediate gradients are dropped once they have been
+    /// propagated. Unlike a leaf's, a retained gradient is not accumulated: it
+    /// holds the gradient from the latest backward.
+    pub fn retain_grad(&self) {
+        self.data.borrow_mut().retain_grad = true;
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.data.borrow().operation, Operation::None)
+    }
 
-        let mut nodes = Vec::new();
-        let mut visited = std::collections::HashSet::new();
-        self._build_topo(&mut nodes, &mut visited);
-        for node in nodes.iter().rev() {
-            ops::_backward(node)?;
+    /// Backward seeded with `grad` (the gradient of some loss with respect to
+    /// this tensor) instead of ones.
+    pub fn backward_with_grad(&self, grad: TensorValue<T>) -> Result<()>{
+        assert_eq!(self.shape(), grad.shape(), "Seed gradient shape mismatch");
+
+        let tape = self._build_topo();
+        tape.backward(tape.len() - 1, grad)
+    }
+
+    /// Like `backward`, but the gradients are built out of tensor ops, so they can
+    /// be differentiated again (gradient penalties, Hessian-vector products, ...).
+    ///
+    /// Leaf gradients are accumulated into `grad` as usual, and their
+    /// differentiable counterpart into `grad_graph`. Note that a gradient graph
+    /// usually refers back to the leaf it is stored on, so the leaf will not be
+    /// freed until `grad_graph` is cleared.
+    pub fn backward_create_graph(&self) -> Result<()>{
+        let seed = Tensor::from_value(self.data.borrow().value.ones_like());
+        let (tape, grads) = build_grad_graph(std::slice::from_ref(self), &[seed])?;
+
+        for (node, grad) in tape.nodes().iter().zip(grads) {
+            let grad = match grad {
+                Some(grad) if node.is_leaf() => grad,
+                _ => continue,
+            };
+            let grad_value = grad.value();
+            let mut data = node.data.borrow_mut();
+            data.add_grad(&grad_value)?;
+            data.grad_graph = Some(match data.grad_graph.take() {
+                Some(prev) => prev.add(&grad)?,
+                None => grad,
+            });
         }
         Ok(())
     }
 
-    fn _build_topo(&self, nodes: &mut Vec<Tensor>, visited: &mut std::collections::HashSet<*const NodeData>){
-        let ptr = self.data.as_ptr() as *const NodeData;
-        if visited.contains(&ptr){
-            return ;
-        }
-        visited.insert(ptr);
+    /// The differentiable gradient left by `backward_create_graph`, if any.
+    pub fn grad_graph(&self) -> Option<Tensor<T>> {
+        self.data.borrow().grad_graph.clone()
+    }
+}
 
-        for dep in &self.data.borrow().dependencies {
-            dep._build_topo(nodes, visited);
+type GradGraph<T> = (Tape<T>, Vec<Option<Tensor<T>>>);
+
+/// Runs reverse mode from `outputs`, seeded with `seeds`, building every
+/// gradient as a tensor. Returns the tape of visited nodes and the gradient
+/// reaching each of them, indexed like the tape.
+pub(crate) fn build_grad_graph<T: Float>(outputs: &[Tensor<T>], seeds: &[Tensor<T>]) -> Result<GradGraph<T>>{
+    let tape = Tape::record(outputs);
+    let mut grads: Vec<Option<Tensor<T>>> = vec![None; tape.len()];
+
+    for (output, seed) in outputs.iter().zip(seeds) {
+        let index = tape.index_of(output).expect("output missing from its own tape");
+        accumulate(&mut grads[index], seed.clone())?;
+    }
+
+    for index in (0..tape.len()).rev() {
+        let grad = match &grads[index] {
+            Some(grad) => grad.clone(),
+            None => continue,
+        };
+        let input_grads = ops::_backward_graph(&tape.nodes()[index], &grad)?;
+        for (&dep, dep_grad) in tape.dependencies(index).iter().zip(input_grads) {
+            accumulate(&mut grads[dep], dep_grad)?;
         }
+    }
+    Ok((tape, grads))
+}
+
+fn accumulate<T: Float>(slot: &mut Option<Tensor<T>>, grad: Tensor<T>) -> Result<()>{
+    *slot = Some(match slot.take() {
+        Some(prev) => prev.add(&grad)?,
+        None => grad,
+    });
+    Ok(())
+}
+
+#[test]
+fn backward_create_graph_second_order_works() -> Result<()>{
+    // y = x^3, dy/dx = 3x^2, d2y/dx2 = 6x
+    let x = Tensor::scalar(2.0);
+    let y = x.pow(3.0)?;
+    y.backward_create_graph()?;
 
-        nodes.push(self.clone());
+    let dy_dx = x.grad_graph().expect("missing grad graph");
+    assert_eq!(dy_dx.to_scalar()?, 12.0);
+
+    x.data.borrow_mut().grad = Some(TensorValue::Scalar(0.0));
+    dy_dx.backward()?;
+    let x_grad = match &x.grad().unwrap().value() {
+        TensorValue::Scalar(s) => *s,
+        _ => panic!("Error!")
+    };
+    assert_eq!(x_grad, 12.0);
+    Ok(())
+}
+
+#[test]
+fn backward_create_graph_gradient_penalty_works() -> Result<()>{
+    // penalty = sum((d/dx sum(sigmoid(w * x)))^2), differentiated w.r.t. w
+    let w = Tensor::vector(vec![0.5, -1.0, 2.0]);
+    let x = Tensor::vector(vec![1.0, 0.3, -0.2]);
+    let out = w.multiply(&x)?.sigmoid()?.sum()?;
+    out.backward_create_graph()?;
+
+    let grad_x = x.grad_graph().expect("missing grad graph");
+    let penalty = grad_x.pow(2.0)?.sum()?;
+    w.data.borrow_mut().grad = Some(TensorValue::Vector1D(vec![0.0; 3]));
+    penalty.backward()?;
+
+    // grad_x_i = w_i * s_i * (1 - s_i), so
+    // d(grad_x_i^2)/dw_i = 2 grad_x_i * (s_i (1 - s_i) + w_i x_i s_i (1 - s_i)(1 - 2 s_i))
+    let w_grad = match &w.grad().unwrap().value() {
+        TensorValue::Vector1D(v) => v.clone(),
+        _ => panic!("Error!")
+    };
+    for (i, (w_i, x_i)) in [0.5_f64, -1.0, 2.0].iter().zip([1.0, 0.3, -0.2]).enumerate() {
+        let s = 1.0 / (1.0 + (-w_i * x_i).exp());
+        let ds = s * (1.0 - s);
+        let grad_x_i = w_i * ds;
+        let expected = 2.0 * grad_x_i * (ds + w_i * x_i * ds * (1.0 - 2.0 * s));
+        approx::assert_abs_diff_eq!(w_grad[i], expected, epsilon = 1e-10);
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+#[test]
+fn backward_keeps_grads_only_on_leaves_and_retained() -> Result<()>{
+    let x = Tensor::vector(vec![1.0, 2.0]);
+    let w = Tensor::vector(vec![3.0, -1.0]);
+    let hidden = x.multiply(&w)?;
+    let activated = hidden.tanh()?;
+    activated.retain_grad();
+    let loss = activated.sum()?;
+    loss.backward()?;
+
+    assert!(x.is_leaf() && !hidden.is_leaf());
+    assert!(hidden.grad().is_none());
+    assert!(loss.grad().is_none());
+    assert_eq!(activated.grad().unwrap().value().flatten(), vec![1.0, 1.0]);
+    assert_eq!(x.grad().unwrap().value().flatten().len(), 2);
+
+    // Retained gradients are reset rather than propagated twice.
+    let w_grad = w.grad().unwrap().value().flatten();
+    loss.backward()?;
+    let doubled: Vec<f64> = w_grad.iter().map(|g| 2.0 * g).collect();
+    assert_eq!(w.grad().unwrap().value().flatten(), doubled);
+    assert_eq!(activated.grad().unwrap().value().flatten(), vec![1.0, 1.0]);
+    Ok(())
+}
@@ -0,0 +1,161 @@
+// Functional autograd: gradients are returned as new tensors instead of being
+// accumulated into the `grad` buffers of the leaves.
+
+use crate::tensor::dtype::Float;
+use anyhow::{bail, Result};
+use super::autodiff::build_grad_graph;
+use super::value::TensorValue;
+use super::Tensor;
+
+/// Gradients of the sum of `outputs` with respect to each of `inputs`.
+///
+/// Nothing is written to `grad`. The returned gradients are built out of tensor
+/// ops, so they can be passed to `grad` again for higher-order derivatives. An
+/// input that does not take part in any output gets a zero gradient.
+pub fn grad<T: Float>(outputs: &[Tensor<T>], inputs: &[Tensor<T>]) -> Result<Vec<Tensor<T>>> {
+    let grad_outputs: Vec<Tensor<T>> = outputs.iter()
+        .map(|output| Tensor::from_value(output.data.borrow().value.ones_like()))
+        .collect();
+    grad_with(outputs, &grad_outputs, inputs)
+}
+
+/// Like `grad`, but each output's gradient is seeded with the matching tensor of
+/// `grad_outputs` instead of ones, i.e. a vector-Jacobian product.
+pub fn grad_with<T: Float>(outputs: &[Tensor<T>], grad_outputs: &[Tensor<T>], inputs: &[Tensor<T>]) -> Result<Vec<Tensor<T>>> {
+    if outputs.len() != grad_outputs.len() {
+        bail!("Expected {} grad_outputs, got {}", outputs.len(), grad_outputs.len());
+    }
+
+    let (tape, grads) = build_grad_graph(outputs, grad_outputs)?;
+    let results = inputs.iter()
+        .map(|input| match tape.index_of(input).and_then(|index| grads[index].clone()) {
+            Some(grad) => grad,
+            None => Tensor::from_value(TensorValue::full(&input.shape(), T::zero())),
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Jacobian of `f` at `x`, for scalar or vector inputs and outputs.
+///
+/// The result has the output shape followed by the input shape: a gradient
+/// vector for a scalar-valued `f`, an `[outputs, inputs]` matrix for a
+/// vector-valued one.
+pub fn jacobian<T: Float, F>(f: F, x: &Tensor<T>) -> Result<Tensor<T>>
+where
+    F: Fn(&Tensor<T>) -> Result<Tensor<T>>,
+{
+    let input = Tensor::from_value(x.value());
+    let output = f(&input)?;
+
+    let input_shape = input.shape();
+    let output_shape = output.shape();
+    if input_shape.len() > 1 || output_shape.len() > 1 {
+        bail!(
+            "jacobian only supports scalar and vector tensors, got input {:?} and output {:?}",
+            input_shape, output_shape
+        );
+    }
+
+    let num_outputs = output_shape.iter().product::<usize>();
+    let mut rows = Vec::with_capacity(num_outputs);
+    for i in 0..num_outputs {
+        let seed = match output_shape.len() {
+            0 => TensorValue::Scalar(T::one()),
+            _ => TensorValue::Vector1D((0..num_outputs).map(|j| if i == j { T::one() } else { T::zero() }).collect()),
+        };
+        let row = grad_with(std::slice::from_ref(&output), &[Tensor::from_value(seed)], std::slice::from_ref(&input))?;
+        rows.push(row[0].value().flatten());
+    }
+
+    let value = match (output_shape.len(), input_shape.len()) {
+        (0, 0) => TensorValue::Scalar(rows[0][0]),
+        (0, _) => TensorValue::Vector1D(rows.remove(0)),
+        (_, 0) => TensorValue::Vector1D(rows.into_iter().map(|row| row[0]).collect()),
+        _ => TensorValue::Matrix2D(rows),
+    };
+    Ok(Tensor::from_value(value))
+}
+
+/// Hessian of the scalar-valued `f` at `x`, as the Jacobian of its gradient.
+pub fn hessian<T: Float, F>(f: F, x: &Tensor<T>) -> Result<Tensor<T>>
+where
+    F: Fn(&Tensor<T>) -> Result<Tensor<T>>,
+{
+    jacobian(|input| {
+        let output = f(input)?;
+        if !output.shape().is_empty() {
+            bail!("hessian requires a scalar-valued function");
+        }
+        Ok(grad(&[output], std::slice::from_ref(input))?.remove(0))
+    }, x)
+}
+
+#[test]
+fn grad_leaves_grad_buffers_untouched() -> Result<()> {
+    let x = Tensor::vector(vec![1.0, 2.0]);
+    let w = Tensor::vector(vec![3.0, -1.0]);
+    let unused = Tensor::scalar(5.0);
+    let y = x.multiply(&w)?.sum()?;
+
+    let grads = grad(&[y], &[x.clone(), w.clone(), unused])?;
+    assert_eq!(grads[0].to_vec()?, vec![3.0, -1.0]);
+    assert_eq!(grads[1].to_vec()?, vec![1.0, 2.0]);
+    assert_eq!(grads[2].to_scalar()?, 0.0);
+
+    assert!(x.grad().is_none());
+    assert!(w.grad().is_none());
+    Ok(())
+}
+
+#[test]
+fn jacobian_of_tanh_layer_works() -> Result<()> {
+    let weights: Tensor = Tensor::matrix(vec![
+        vec![1.0, 2.0, 3.0],
+        vec![-1.0, 0.5, 0.0],
+    ]);
+    let x = Tensor::vector(vec![0.1, 0.2, 0.3]);
+    let jac = jacobian(|x| weights.matmul(x)?.tanh(), &x)?;
+
+    let pre_activation = weights.matmul(&x)?.to_vec()?;
+    let expected: Vec<Vec<f64>> = weights.to_matrix()?.iter().zip(pre_activation)
+        .map(|(row, z)| row.iter().map(|w| w * (1.0 - z.tanh().powi(2))).collect())
+        .collect();
+
+    for (row_actual, row_expected) in jac.to_matrix()?.iter().zip(expected) {
+        for (val_actual, val_expected) in row_actual.iter().zip(row_expected) {
+            approx::assert_abs_diff_eq!(*val_actual, val_expected, epsilon = 1e-12);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn hessian_of_cubic_is_diagonal() -> Result<()> {
+    let x = Tensor::vector(vec![1.0, -2.0, 0.5]);
+    let hess = hessian(|x| x.pow(3.0)?.sum(), &x)?;
+
+    assert_eq!(hess.to_matrix()?, vec![
+        vec![6.0, 0.0, 0.0],
+        vec![0.0, -12.0, 0.0],
+        vec![0.0, 0.0, 3.0],
+    ]);
+    Ok(())
+}
+
+#[test]
+fn hessian_of_quadratic_form_works() -> Result<()> {
+    // f(x) = x^T A x, H = A + A^T
+    let a = Tensor::matrix(vec![
+        vec![1.0, 2.0],
+        vec![0.0, 3.0],
+    ]);
+    let x = Tensor::vector(vec![0.7, -0.4]);
+    let hess = hessian(|x| x.multiply(&a.matmul(x)?)?.sum(), &x)?;
+
+    assert_eq!(hess.to_matrix()?, vec![
+        vec![2.0, 2.0],
+        vec![2.0, 6.0],
+    ]);
+    Ok(())
+}
@@ -0,0 +1,162 @@
+// Gradient checkpointing: trade compute for memory by recomputing a segment's
+// intermediates during backward instead of keeping them alive.
+
+use crate::tensor::dtype::Float;
+use std::fmt;
+use std::rc::Rc;
+use super::forward_ad::jvp;
+use super::function::Function;
+use super::value::TensorValue;
+use super::Tensor;
+use anyhow::{bail, Result};
+
+struct Checkpoint<F> {
+    segment: F,
+}
+
+impl<F> fmt::Debug for Checkpoint<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Checkpoint")
+    }
+}
+
+impl<T: Float, F> Function<T> for Checkpoint<F>
+where
+    F: Fn(&Tensor<T>) -> Result<Tensor<T>>,
+{
+    fn forward(&self, inputs: &[TensorValue<T>]) -> Result<TensorValue<T>> {
+        // The segment's graph is dropped as soon as its output value is read.
+        let input = Tensor::from_value(inputs[0].clone());
+        let output = (self.segment)(&input)?;
+        let value = output.value();
+        Ok(value)
+    }
+
+    fn backward(&self, inputs: &[TensorValue<T>], _output: &TensorValue<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>> {
+        let input = Tensor::from_value(inputs[0].clone());
+        let output = (self.segment)(&input)?;
+        // Parameters captured by the segment get their gradients from here.
+        output.backward_with_grad(grad.clone())?;
+        let input_grad = input.data.borrow().grad.clone()
+            .unwrap_or_else(|| TensorValue::full(&inputs[0].shape(), T::zero()));
+        Ok(vec![input_grad])
+    }
+
+    fn backward_graph(&self, _inputs: &[Tensor<T>], _output: &Tensor<T>, _grad: &Tensor<T>) -> Result<Option<Vec<Tensor<T>>>> {
+        // The captured parameters are not inputs of the node, so a gradient
+        // graph would leave them out and `backward` would write their `grad`.
+        bail!("Cannot build a gradient graph through a checkpoint")
+    }
+
+    fn jvp(&self, inputs: &[TensorValue<T>], _output: &TensorValue<T>, tangents: &[TensorValue<T>]) -> Result<TensorValue<T>> {
+        let input = Tensor::from_value(inputs[0].clone());
+        let (_, tangent) = jvp(&self.segment, &input, &Tensor::from_value(tangents[0].clone()))?;
+        let value = tangent.value();
+        Ok(value)
+    }
+}
+
+/// Runs `segment` on `input` without keeping its intermediate values for
+/// backward; `Tensor::backward` runs the segment again to recompute them.
+///
+/// Parameters the segment uses receive their gradients as usual, so capture
+/// them by value (tensors are cheap to clone and share their storage). The
+/// segment must be deterministic, and is only differentiable once:
+/// `backward_create_graph` and `autograd::grad` fail on a checkpoint.
+pub fn checkpoint<T: Float, F>(segment: F, input: &Tensor<T>) -> Result<Tensor<T>>
+where
+    F: Fn(&Tensor<T>) -> Result<Tensor<T>> + 'static,
+{
+    Tensor::custom(Rc::new(Checkpoint { segment }), std::slice::from_ref(input))
+}
+
+#[cfg(test)]
+fn mlp_parameters() -> Vec<Tensor> {
+    (0..3).flat_map(|layer| {
+        let offset = layer as f64;
+        vec![
+            Tensor::matrix((0..3).map(|r| {
+                (0..3).map(|c| ((r * 3 + c) as f64 + offset).sin() * 0.8).collect()
+            }).collect()),
+            Tensor::vector((0..3).map(|r| (r as f64 - offset) * 0.1).collect()),
+        ]
+    }).collect()
+}
+
+#[cfg(test)]
+fn mlp_block(params: &[Tensor], x: &Tensor) -> Result<Tensor> {
+    params[0].matmul(x)?.add(&params[1])?.tanh()
+}
+
+#[test]
+fn checkpoint_gradients_match_plain_backward() -> Result<()> {
+    let x_values = vec![0.4, -0.3, 0.9];
+
+    let plain = mlp_parameters();
+    let plain_x = Tensor::vector(x_values.clone());
+    let mut hidden = plain_x.clone();
+    for block in plain.chunks(2) {
+        hidden = mlp_block(block, &hidden)?;
+    }
+    hidden.pow(2.0)?.sum()?.backward()?;
+
+    let checkpointed = mlp_parameters();
+    let checkpointed_x = Tensor::vector(x_values);
+    let mut hidden = checkpointed_x.clone();
+    for block in checkpointed.chunks(2) {
+        let block = block.to_vec();
+        hidden = checkpoint(move |x| mlp_block(&block, x), &hidden)?;
+    }
+    // Only the checkpoint outputs are kept: one node per block plus the input.
+    assert_eq!(hidden.data.borrow().dependencies[0].data.borrow().dependencies.len(), 1);
+    hidden.pow(2.0)?.sum()?.backward()?;
+
+    assert_eq!(plain_x.grad().unwrap().value().flatten(), checkpointed_x.grad().unwrap().value().flatten());
+    for (plain_param, checkpointed_param) in plain.iter().zip(&checkpointed) {
+        assert_eq!(plain_param.grad().unwrap().value().flatten(), checkpointed_param.grad().unwrap().value().flatten());
+    }
+    Ok(())
+}
+
+#[test]
+fn checkpoint_passes_gradcheck() -> Result<()> {
+    let params = mlp_parameters();
+    super::gradcheck::gradcheck(
+        move |x| {
+            let block = params[..2].to_vec();
+            checkpoint(move |x| mlp_block(&block, x)?.sigmoid(), &x[0])
+        },
+        &[Tensor::vector(vec![0.1, 0.2, -0.5])],
+        1e-6,
+        1e-5,
+    )
+}
+
+#[test]
+fn checkpoint_supports_jvp() -> Result<()> {
+    let params = mlp_parameters();
+    let block = params[..2].to_vec();
+    let x = Tensor::vector(vec![0.1, 0.2, -0.5]);
+    let v = Tensor::vector(vec![1.0, 0.0, 2.0]);
+
+    let (_, expected) = jvp(|x| mlp_block(&block, x), &x, &v)?;
+    let (_, actual) = jvp(|x| {
+        let block = block.clone();
+        checkpoint(move |x| mlp_block(&block, x), x)
+    }, &x, &v)?;
+    assert_eq!(actual.to_vec()?, expected.to_vec()?);
+    Ok(())
+}
+
+#[test]
+fn checkpoint_rejects_gradient_graphs() -> Result<()> {
+    let params = mlp_parameters();
+    let block = params[..2].to_vec();
+    let x = Tensor::vector(vec![0.1, 0.2, -0.5]);
+    let y = checkpoint(move |x| mlp_block(&block, x), &x)?.sum()?;
+
+    assert!(super::autograd::grad(std::slice::from_ref(&y), std::slice::from_ref(&x)).is_err());
+    assert!(y.backward_create_graph().is_err());
+    assert!(params[0].grad().is_none());
+    Ok(())
+}
@@ -0,0 +1,86 @@
+// Graphviz export of the computation graph, for inspecting failing models.
+
+use crate::tensor::dtype::Float;
+use std::fmt::Write as _;
+use std::path::Path;
+use super::Tensor;
+use anyhow::Result;
+
+impl<T: Float> Tensor<T> {
+    /// Renders the graph leading to this tensor in DOT format. Each node is
+    /// labelled with its operation name, value shape and grad shape (`none` if no
+    /// gradient is stored); nodes whose grad shape differs from their value
+    /// shape are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let tape = self._build_topo();
+
+        let mut dot = String::from("digraph {\n    node [shape=box];\n");
+        for (i, node) in tape.nodes().iter().enumerate() {
+            let data = node.data.borrow();
+            let value_shape = data.value.shape();
+            let grad_shape = data.grad.as_ref().map(|grad| grad.shape());
+            let grad_label = match &grad_shape {
+                Some(shape) => format!("{:?}", shape),
+                None => "none".to_string(),
+            };
+            let label = format!("{}\\nvalue {:?}\\ngrad {}", data.operation.name(), value_shape, grad_label)
+                .replace('"', "\\\"");
+            let mismatch = grad_shape.is_some_and(|shape| shape != value_shape);
+            let color = if mismatch { ", color=red" } else { "" };
+            writeln!(dot, "    n{} [label=\"{}\"{}];", i, label, color).unwrap();
+
+            for dep in tape.dependencies(i) {
+                writeln!(dot, "    n{} -> n{};", dep, i).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Writes `to_dot` to `path`, e.g. for `dot -Tsvg graph.dot -o graph.svg`.
+    pub fn save_dot(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_dot())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn to_dot_works() -> Result<()> {
+    let weights = Tensor::matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+    let x = Tensor::vector(vec![1.0, -1.0]);
+    let loss = weights.matmul(&x)?.pow(2.0)?.sum()?;
+    loss.backward()?;
+
+    let dot = loss.to_dot();
+    assert_eq!(dot, "digraph {
+    node [shape=box];
+    n0 [label=\"None\\nvalue [3, 2]\\ngrad [3, 2]\"];
+    n1 [label=\"None\\nvalue [2]\\ngrad [2]\"];
+    n2 [label=\"Matmul\\nvalue [3]\\ngrad none\"];
+    n0 -> n2;
+    n1 -> n2;
+    n3 [label=\"Pow\\nvalue [3]\\ngrad none\"];
+    n2 -> n3;
+    n4 [label=\"Sum\\nvalue []\\ngrad none\"];
+    n3 -> n4;
+}
+");
+    Ok(())
+}
+
+#[test]
+fn to_dot_labels_index_ops_by_name() -> Result<()> {
+    let x = Tensor::vector((0..64).map(|i| i as f64).collect());
+    let picked = x.gather(0, &super::index::IndexTensor::Vector1D((0..64).rev().collect()))?;
+    assert!(picked.to_dot().contains("n1 [label=\"Gather\\nvalue [64]\\ngrad none\"];"));
+    Ok(())
+}
+
+#[test]
+fn to_dot_highlights_grad_shape_mismatch() -> Result<()> {
+    let x = Tensor::vector(vec![1.0, 2.0]);
+    x.data.borrow_mut().grad = Some(super::value::TensorValue::Scalar(0.0));
+
+    assert!(x.to_dot().contains("n0 [label=\"None\\nvalue [2]\\ngrad []\", color=red];"));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+// Element types. Tensors are generic over `Float`, implemented for `f32` and
+// `f64`; `f64` is the default, so `Tensor` alone means `Tensor<f64>`.
+
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{AddAssign, DivAssign, MulAssign, SubAssign};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DType {
+    F32,
+    F64,
+}
+
+pub trait Float:
+    num_traits::Float + Debug + Display + Default + Sum
+    + AddAssign + SubAssign + MulAssign + DivAssign + 'static
+{
+    const DTYPE: DType;
+
+    /// Converts a constant or hyperparameter, rounding for `f32`.
+    fn from_f64(x: f64) -> Self;
+
+    fn into_f64(self) -> f64;
+}
+
+impl Float for f32 {
+    const DTYPE: DType = DType::F32;
+
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Float for f64 {
+    const DTYPE: DType = DType::F64;
+
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn into_f64(self) -> f64 {
+        self
+    }
+}
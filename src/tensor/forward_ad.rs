@@ -0,0 +1,226 @@
+// Forward-mode differentiation. Inside `jvp` a tensor can carry a tangent
+// next to its value: every op computes the tangent of its output from those of
+// its inputs with its `jvp` rule as soon as the output value exists, and then
+// lets go of its inputs, so no graph is recorded. One call gives the
+// directional derivative along one input direction.
+
+use crate::tensor::dtype::Float;
+use std::cell::Cell;
+use super::value::TensorValue;
+use super::operation::Operation;
+use super::{ops, Tensor};
+use anyhow::{bail, Result};
+
+thread_local! {
+    // The innermost `jvp` call running, zero outside of one.
+    static ACTIVE: Cell<u64> = const { Cell::new(0) };
+    static NEXT: Cell<u64> = const { Cell::new(1) };
+}
+
+/// Evaluates `f` at `x` with tangent `v` carried along, i.e. computes the
+/// Jacobian-vector product `J_f(x) v`. Returns `(f(x), J_f(x) v)`.
+///
+/// No graph is recorded while `f` runs, so nothing inside it can be
+/// backpropagated and nothing is written to `grad`; parameters captured by
+/// `f` are treated as constants. `v` must have the shape of `x`.
+pub fn jvp<T: Float, F>(f: F, x: &Tensor<T>, v: &Tensor<T>) -> Result<(Tensor<T>, Tensor<T>)>
+where
+    F: Fn(&Tensor<T>) -> Result<Tensor<T>>,
+{
+    let input_value = x.value();
+    let tangent = v.value();
+    if input_value.shape() != tangent.shape() {
+        bail!("jvp tangent has shape {:?}, expected {:?}", tangent.shape(), input_value.shape());
+    }
+
+    // Tangents are tagged with their call, so a `jvp` nested in `f` does not
+    // mistake the outer tangents for its own.
+    let id = NEXT.with(|next| next.replace(next.get() + 1));
+    let input = Tensor::from_value(input_value);
+    input.data.borrow_mut().tangent = Some((id, tangent));
+
+    let outer = ACTIVE.with(|active| active.replace(id));
+    let output = f(&input);
+    ACTIVE.with(|active| active.set(outer));
+    let output = output?;
+
+    // An output that does not depend on `x` has no tangent.
+    let output_tangent = match output.data.borrow_mut().tangent.take() {
+        Some((owner, tangent)) if owner == id => tangent,
+        _ => TensorValue::full(&output.shape(), T::zero()),
+    };
+    Ok((output, Tensor::from_value(output_tangent)))
+}
+
+/// The tangent `tensor` carries for the `jvp` call `id`, if any.
+pub(crate) fn tangent<T: Float>(tensor: &Tensor<T>, id: u64) -> Option<TensorValue<T>> {
+    match &tensor.data.borrow().tangent {
+        Some((owner, tangent)) if *owner == id => Some(tangent.clone()),
+        _ => None,
+    }
+}
+
+/// Inside `jvp`, computes the tangent of the op output `result` and cuts it
+/// loose from its inputs. Called by every op through `anomaly::check_forward`.
+pub(crate) fn propagate<T: Float>(result: &Tensor<T>) -> Result<()> {
+    let id = ACTIVE.with(|active| active.get());
+    if id == 0 {
+        return Ok(());
+    }
+
+    let tangent = {
+        let data = result.data.borrow();
+        let carries = |dep: &Tensor<T>| matches!(&dep.data.borrow().tangent, Some((owner, _)) if *owner == id);
+        match &data.operation {
+            // The source of a cast is not one of its inputs.
+            Operation::Cast(source) => source.tangent(id),
+            _ if !data.dependencies.iter().any(carries) => None,
+            _ => {
+                let tangents: Vec<TensorValue<T>> = data.dependencies.iter()
+                    .map(|dep| tangent(dep, id).unwrap_or_else(|| TensorValue::full(&dep.shape(), T::zero())))
+                    .collect();
+                drop(data);
+                Some(ops::_jvp(result, &tangents)?)
+            }
+        }
+    };
+
+    let mut data = result.data.borrow_mut();
+    data.tangent = tangent.map(|tangent| (id, tangent));
+    data.dependencies.clear();
+    data.operation = Operation::None;
+    Ok(())
+}
+
+#[cfg(test)]
+fn check_jvp<F>(f: F, x: Tensor, v: Tensor) -> Result<()>
+where
+    F: Fn(&Tensor) -> Result<Tensor>,
+{
+    let eps = 1e-6;
+    let (_, tangent) = jvp(&f, &x, &v)?;
+
+    let x_value = x.value();
+    let v_value = v.value();
+    let plus = f(&Tensor::from_value(x_value.zip_map(&v_value, |x, v| x + eps * v)))?;
+    let minus = f(&Tensor::from_value(x_value.zip_map(&v_value, |x, v| x - eps * v)))?;
+    let expected: Vec<f64> = plus.value().flatten().iter()
+        .zip(minus.value().flatten())
+        .map(|(p, m)| (p - m) / (2.0 * eps))
+        .collect();
+
+    let actual = tangent.value().flatten();
+    assert_eq!(actual.len(), expected.len());
+    for (actual, expected) in actual.iter().zip(expected) {
+        approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-6);
+    }
+    Ok(())
+}
+
+#[test]
+fn jvp_matches_finite_differences() -> Result<()> {
+    let v = || Tensor::vector(vec![0.3, -1.0, 0.5]);
+    let x = || Tensor::vector(vec![0.4, 1.2, 2.0]);
+
+    check_jvp(|x| x.add(&Tensor::scalar(2.0)), x(), v())?;
+    check_jvp(|x| Tensor::vector(vec![1.0, 2.0, 3.0]).sub(x), x(), v())?;
+    check_jvp(|x| x.multiply(x), x(), v())?;
+    check_jvp(|x| Tensor::scalar(1.0).div(x), x(), v())?;
+    check_jvp(|x| x.log(2.0), x(), v())?;
+    check_jvp(|x| x.pow(3.0), x(), v())?;
+    check_jvp(|x| x.sub(&Tensor::scalar(1.0))?.relu(), x(), v())?;
+    check_jvp(|x| x.tanh(), x(), v())?;
+    check_jvp(|x| x.sigmoid(), x(), v())?;
+    check_jvp(|x| x.softmax(), x(), v())?;
+    check_jvp(|x| x.log_softmax(), x(), v())?;
+    check_jvp(|x| x.mean(), x(), v())?;
+    check_jvp(|x| x.unsqueeze(0)?.t()?.squeeze(1), x(), v())?;
+    check_jvp(|x| x.index_select(0, &[2, 0, 2]), x(), v())?;
+    check_jvp(|x| x.masked_fill(&super::index::BoolTensor::vector(vec![false, true, false]), 0.5), x(), v())?;
+    check_jvp(|x| x.scatter_add(0, &super::index::IntTensor::vector(vec![1, 1]), &x.index_select(0, &[0, 2])?), x(), v())?;
+    Ok(())
+}
+
+#[test]
+fn jvp_through_matrices() -> Result<()> {
+    let w = Tensor::matrix(vec![vec![0.5, -0.2], vec![0.1, 0.7], vec![-0.3, 0.4]]);
+    let b = Tensor::vector(vec![0.1, -0.1]);
+    let x = Tensor::matrix(vec![vec![1.0, 0.5, -0.5], vec![0.2, -1.0, 0.3]]);
+    let v = Tensor::matrix(vec![vec![0.1, 0.0, -0.2], vec![1.0, 0.5, 0.3]]);
+
+    check_jvp(|x| x.matmul(&w)?.sub(&b)?.tanh()?.softmax(), x.clone(), v.clone())?;
+    check_jvp(|x| x.matmul(&w)?.log_softmax(), x.clone(), v.clone())?;
+    check_jvp(|x| x.t()?.multiply(&x.t()?)?.sum(), x, v)
+}
+
+#[test]
+fn jvp_matches_jacobian() -> Result<()> {
+    let w = Tensor::matrix(vec![vec![0.5, -0.2, 0.3], vec![0.1, 0.7, -0.4]]);
+    let f = |x: &Tensor| w.matmul(x)?.sigmoid();
+    let x = Tensor::vector(vec![0.2, -0.4, 1.0]);
+    let v = Tensor::vector(vec![1.0, 0.5, -0.5]);
+
+    let (output, tangent) = jvp(f, &x, &v)?;
+    assert_eq!(output.to_vec()?, f(&x)?.to_vec()?);
+    let expected = super::autograd::jacobian(f, &x)?.matmul(&v)?;
+    for (actual, expected) in tangent.to_vec()?.iter().zip(expected.to_vec()?) {
+        approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+    }
+    // The parameters are constants here.
+    assert!(w.grad().is_none());
+    Ok(())
+}
+
+#[test]
+fn jvp_records_no_graph() -> Result<()> {
+    let w = Tensor::vector(vec![0.5, -0.2, 0.3]);
+    let x = Tensor::vector(vec![0.2, -0.4, 1.0]);
+    let (output, _) = jvp(|x| {
+        let hidden = x.multiply(&w)?.tanh()?;
+        assert!(hidden.is_leaf());
+        hidden.sum()
+    }, &x, &x)?;
+    assert!(output.is_leaf());
+    assert!(output.data.borrow().dependencies.is_empty());
+
+    // Outside `jvp` the same ops record their graph as usual.
+    assert!(!x.multiply(&w)?.is_leaf());
+    Ok(())
+}
+
+#[test]
+fn jvp_through_casts() -> Result<()> {
+    let x = Tensor::vector(vec![0.2, -0.4, 1.0]);
+    let v = Tensor::vector(vec![1.0, 0.5, -0.5]);
+    let (_, tangent) = jvp(|x| x.to_dtype::<f32>()?.tanh()?.to_dtype::<f64>(), &x, &v)?;
+    let (_, expected) = jvp(|x| x.tanh(), &x, &v)?;
+    for (actual, expected) in tangent.to_vec()?.iter().zip(expected.to_vec()?) {
+        approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-6);
+    }
+    Ok(())
+}
+
+#[test]
+fn jvp_through_batch_norm() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 0.5, -0.5], vec![0.2, -1.0, 0.3], vec![0.7, 0.1, -0.9]]);
+    let v = Tensor::matrix(vec![vec![0.1, 0.0, -0.2], vec![1.0, 0.5, 0.3], vec![-0.4, 0.2, 0.6]]);
+    let weight = Tensor::vector(vec![0.5, -1.2, 2.0]);
+    let bias = Tensor::vector(vec![0.1, 0.0, -0.3]);
+    let (mean, var) = (Tensor::vector(vec![0.1, 0.2, -0.3]), Tensor::vector(vec![0.5, 1.0, 2.0]));
+
+    check_jvp(|x| x.batch_norm(&weight, &bias, None, 1e-5)?.tanh(), x.clone(), v.clone())?;
+    check_jvp(|x| x.batch_norm(&weight, &bias, Some((&mean, &var)), 1e-5)?.tanh(), x.clone(), v.clone())?;
+    check_jvp(|w| x.batch_norm(w, &bias, None, 1e-5)?.tanh(), weight.clone(), bias.clone())
+}
+
+#[test]
+fn jvp_through_layer_and_group_norm() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 0.5, -0.5, 2.0], vec![0.2, -1.0, 0.3, 0.0]]);
+    let v = Tensor::matrix(vec![vec![0.1, 0.0, -0.2, 0.4], vec![1.0, 0.5, 0.3, -0.6]]);
+    let weight = Tensor::vector(vec![0.5, -1.2, 2.0, 1.0]);
+    let bias = Tensor::vector(vec![0.1, 0.0, -0.3, 0.2]);
+
+    check_jvp(|x| x.layer_norm(&weight, &bias, 1e-5)?.tanh(), x.clone(), v.clone())?;
+    check_jvp(|x| x.group_norm(2, &weight, &bias, 1e-5)?.tanh(), x.clone(), v.clone())?;
+    check_jvp(|w| x.group_norm(2, w, &bias, 1e-5)?.tanh(), weight.clone(), bias.clone())
+}
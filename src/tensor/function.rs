@@ -0,0 +1,36 @@
+// User-defined differentiable operations.
+//
+// A `Function` only deals with plain values. Applying it with `Tensor::custom`
+// records it in the graph as `Operation::Custom`, and `Tensor::backward` calls
+// back into it, just like the built-in ops under `tensor::ops`.
+
+use std::fmt::Debug;
+use super::dtype::Float;
+use super::value::TensorValue;
+use super::Tensor;
+use anyhow::{bail, Result};
+
+pub trait Function<T: Float = f64>: Debug {
+    /// Computes the output value from the input values.
+    fn forward(&self, inputs: &[TensorValue<T>]) -> Result<TensorValue<T>>;
+
+    /// Returns the gradient of each input, in order, given the input values,
+    /// the output value and the gradient flowing into the output. Each input
+    /// gradient must have the shape of its input.
+    fn backward(&self, inputs: &[TensorValue<T>], output: &TensorValue<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>;
+
+    /// Same as `backward`, but built out of tensor ops so that
+    /// `Tensor::backward_create_graph` can differentiate through it. Returning
+    /// `None` (the default) makes the values from `backward` act as constants,
+    /// i.e. the function is only differentiable once.
+    fn backward_graph(&self, _inputs: &[Tensor<T>], _output: &Tensor<T>, _grad: &Tensor<T>) -> Result<Option<Vec<Tensor<T>>>> {
+        Ok(None)
+    }
+
+    /// Returns the tangent of the output given the input values, the output
+    /// value and the tangent of each input, for `forward_ad::jvp`. Not
+    /// implemented by default.
+    fn jvp(&self, _inputs: &[TensorValue<T>], _output: &TensorValue<T>, _tangents: &[TensorValue<T>]) -> Result<TensorValue<T>> {
+        bail!("{:?} does not support forward-mode differentiation", self)
+    }
+}
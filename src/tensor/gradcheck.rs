@@ -0,0 +1,408 @@
+// Checks analytic gradients from `Tensor::backward` against central finite
+// differences.
+
+use anyhow::{bail, Result};
+use super::dtype::Float;
+#[cfg(test)]
+use super::index::{BoolTensor, IntTensor};
+use super::value::TensorValue;
+use super::Tensor;
+
+/// Compares the gradients `Tensor::backward` computes for `f` at `inputs` with
+/// central finite differences of step `eps`.
+///
+/// Non-scalar outputs are reduced with a fixed, non-uniform weighting before
+/// differentiating, so that ops like softmax (whose plain sum is constant) are
+/// still checked. An element fails when `|analytic - numeric| > tol * (1 + |numeric|)`.
+/// The caller's tensors are copied, so their `grad` buffers are left alone.
+/// For `f32` inputs pick a larger `eps` and `tol`, around `1e-2`.
+pub fn gradcheck<T: Float, F>(f: F, inputs: &[Tensor<T>], eps: f64, tol: f64) -> Result<()>
+where
+    F: Fn(&[Tensor<T>]) -> Result<Tensor<T>>,
+{
+    let values: Vec<TensorValue<T>> = inputs.iter()
+        .map(|input| input.value())
+        .collect();
+
+    let leaves: Vec<Tensor<T>> = values.iter().cloned().map(Tensor::from_value).collect();
+    let output = f(&leaves)?;
+    let weights = projection(&output.data.borrow().value);
+    let loss = output.multiply(&Tensor::from_value(weights.clone()))?.sum()?;
+    loss.backward()?;
+
+    for (i, (leaf, value)) in leaves.iter().zip(&values).enumerate() {
+        let analytic: Vec<f64> = match leaf.grad() {
+            Some(grad) => grad.value().flatten().into_iter().map(T::into_f64).collect(),
+            None => vec![0.0; leaf.numel()],
+        };
+        let shape = value.shape();
+        let mut data = value.flatten();
+
+        for k in 0..data.len() {
+            let original = data[k];
+            // The perturbed values as stored, which `f32` rounds.
+            let (up, down) = (T::from_f64(original.into_f64() + eps), T::from_f64(original.into_f64() - eps));
+            data[k] = up;
+            let plus = evaluate(&f, &values, i, TensorValue::from_flat(&shape, &data), &weights)?;
+            data[k] = down;
+            let minus = evaluate(&f, &values, i, TensorValue::from_flat(&shape, &data), &weights)?;
+            data[k] = original;
+
+            let numeric = (plus - minus) / (up - down).into_f64();
+            // Written so that a NaN on either side counts as a mismatch.
+            let close = (analytic[k] - numeric).abs() <= tol * (1.0 + numeric.abs());
+            if !close {
+                bail!(
+                    "Gradient mismatch for input {} (shape {:?}) at element {}: analytic {}, numeric {}",
+                    i, shape, k, analytic[k], numeric
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Weights used to reduce an output to a scalar loss.
+fn projection<T: Float>(output: &TensorValue<T>) -> TensorValue<T> {
+    let weights: Vec<T> = (0..output.flatten().len())
+        .map(|k| T::from_f64((k as f64 * 1.3 + 0.5).cos()))
+        .collect();
+    TensorValue::from_flat(&output.shape(), &weights)
+}
+
+fn evaluate<T: Float, F>(f: &F, values: &[TensorValue<T>], index: usize, perturbed: TensorValue<T>, weights: &TensorValue<T>) -> Result<f64>
+where
+    F: Fn(&[Tensor<T>]) -> Result<Tensor<T>>,
+{
+    let inputs: Vec<Tensor<T>> = values.iter().enumerate()
+        .map(|(j, value)| {
+            if j == index {
+                Tensor::from_value(perturbed.clone())
+            } else {
+                Tensor::from_value(value.clone())
+            }
+        })
+        .collect();
+    let output = f(&inputs)?.data.borrow().value.flatten();
+    Ok(output.iter().zip(weights.flatten()).map(|(o, w)| o.into_f64() * w.into_f64()).sum())
+}
+
+// Every `Operation` with a forward pass is checked on each rank it supports.
+// `Broadcast` has no forward yet; the convolutions, pooling and normalization
+// ops are checked next to their ops.
+
+#[cfg(test)]
+const EPS: f64 = 1e-6;
+#[cfg(test)]
+const TOL: f64 = 1e-5;
+
+#[cfg(test)]
+fn scalar(offset: f64) -> Tensor {
+    Tensor::scalar(offset.sin() + 0.1)
+}
+
+#[cfg(test)]
+fn vector(len: usize, offset: f64) -> Tensor {
+    Tensor::vector((0..len).map(|i| (i as f64 * 0.7 + offset).sin()).collect())
+}
+
+#[cfg(test)]
+fn matrix(rows: usize, cols: usize, offset: f64) -> Tensor {
+    Tensor::matrix(
+        (0..rows).map(|r| {
+            (0..cols).map(|c| ((r * cols + c) as f64 * 0.7 + offset).sin()).collect()
+        }).collect()
+    )
+}
+
+/// A rank-3 or rank-4 tensor of the given shape.
+#[cfg(test)]
+fn tensor(shape: &[usize], offset: f64) -> Tensor {
+    let numel: usize = shape.iter().product();
+    Tensor::from_flat(shape, &(0..numel).map(|i| (i as f64 * 0.7 + offset).sin()).collect::<Vec<_>>())
+}
+
+/// Shifts a tensor so that all of its elements are in [0.5, 2.5].
+#[cfg(test)]
+fn positive(tensor: Tensor) -> Tensor {
+    Tensor::from_value(tensor.data.borrow().value.map(|x| x + 1.5))
+}
+
+#[cfg(test)]
+fn check<F>(f: F, inputs: &[Tensor]) -> Result<()>
+where
+    F: Fn(&[Tensor]) -> Result<Tensor>,
+{
+    gradcheck(f, inputs, EPS, TOL)
+}
+
+#[test]
+fn gradcheck_none() -> Result<()> {
+    check(|x| Ok(x[0].clone()), &[scalar(0.3)])?;
+    check(|x| Ok(x[0].clone()), &[vector(3, 0.3)])?;
+    check(|x| Ok(x[0].clone()), &[matrix(2, 3, 0.3)])?;
+    check(|x| Ok(x[0].clone()), &[tensor(&[2, 3, 2], 0.3)])?;
+    check(|x| Ok(x[0].clone()), &[tensor(&[2, 2, 3, 2], 0.3)])
+}
+
+#[test]
+fn gradcheck_add() -> Result<()> {
+    let add = |x: &[Tensor]| x[0].add(&x[1]);
+    check(add, &[scalar(0.1), scalar(0.2)])?;
+    check(add, &[vector(3, 0.1), vector(3, 0.2)])?;
+    check(add, &[matrix(2, 3, 0.1), matrix(2, 3, 0.2)])?;
+    check(add, &[vector(3, 0.1), scalar(0.2)])?;
+    check(add, &[matrix(2, 3, 0.1), scalar(0.2)])?;
+    check(add, &[tensor(&[2, 3, 2], 0.1), tensor(&[2, 3, 2], 0.2)])?;
+    check(add, &[tensor(&[2, 2, 3, 2], 0.1), tensor(&[2, 2, 3, 2], 0.2)])
+}
+
+#[test]
+fn gradcheck_sub() -> Result<()> {
+    let sub = |x: &[Tensor]| x[0].sub(&x[1]);
+    check(sub, &[scalar(0.1), scalar(0.2)])?;
+    check(sub, &[vector(3, 0.1), vector(3, 0.2)])?;
+    check(sub, &[matrix(2, 3, 0.1), matrix(2, 3, 0.2)])?;
+    check(sub, &[scalar(0.1), vector(3, 0.2)])?;
+    check(sub, &[vector(3, 0.1), scalar(0.2)])?;
+    check(sub, &[scalar(0.1), matrix(2, 3, 0.2)])?;
+    check(sub, &[matrix(2, 3, 0.1), scalar(0.2)])?;
+    check(sub, &[matrix(2, 3, 0.1), vector(3, 0.2)])?;
+    check(sub, &[tensor(&[2, 3, 2], 0.1), tensor(&[2, 3, 2], 0.2)])?;
+    check(sub, &[scalar(0.1), tensor(&[2, 3, 2], 0.2)])?;
+    check(sub, &[tensor(&[2, 2, 3, 2], 0.1), tensor(&[2, 2, 3, 2], 0.2)])?;
+    check(sub, &[scalar(0.1), tensor(&[2, 2, 3, 2], 0.2)])
+}
+
+#[test]
+fn gradcheck_multiply() -> Result<()> {
+    let multiply = |x: &[Tensor]| x[0].multiply(&x[1]);
+    check(multiply, &[scalar(0.1), scalar(0.2)])?;
+    check(multiply, &[vector(3, 0.1), vector(3, 0.2)])?;
+    check(multiply, &[matrix(2, 3, 0.1), matrix(2, 3, 0.2)])?;
+    check(multiply, &[scalar(0.1), vector(3, 0.2)])?;
+    check(multiply, &[vector(3, 0.1), scalar(0.2)])?;
+    check(multiply, &[scalar(0.1), matrix(2, 3, 0.2)])?;
+    check(multiply, &[matrix(2, 3, 0.1), scalar(0.2)])?;
+    check(multiply, &[tensor(&[2, 3, 2], 0.1), tensor(&[2, 3, 2], 0.2)])?;
+    check(multiply, &[tensor(&[2, 3, 2], 0.1), scalar(0.2)])?;
+    check(multiply, &[tensor(&[2, 2, 3, 2], 0.1), tensor(&[2, 2, 3, 2], 0.2)])?;
+    check(multiply, &[tensor(&[2, 2, 3, 2], 0.1), scalar(0.2)])
+}
+
+#[test]
+fn gradcheck_div() -> Result<()> {
+    let div = |x: &[Tensor]| x[0].div(&x[1]);
+    check(div, &[scalar(0.1), positive(scalar(0.2))])?;
+    check(div, &[vector(3, 0.1), positive(vector(3, 0.2))])?;
+    check(div, &[matrix(2, 3, 0.1), positive(matrix(2, 3, 0.2))])?;
+    check(div, &[scalar(0.1), positive(vector(3, 0.2))])?;
+    check(div, &[vector(3, 0.1), positive(scalar(0.2))])?;
+    check(div, &[scalar(0.1), positive(matrix(2, 3, 0.2))])?;
+    check(div, &[matrix(2, 3, 0.1), positive(scalar(0.2))])?;
+    check(div, &[tensor(&[2, 3, 2], 0.1), positive(tensor(&[2, 3, 2], 0.2))])?;
+    check(div, &[tensor(&[2, 3, 2], 0.1), positive(scalar(0.2))])?;
+    check(div, &[tensor(&[2, 2, 3, 2], 0.1), positive(tensor(&[2, 2, 3, 2], 0.2))])?;
+    check(div, &[tensor(&[2, 2, 3, 2], 0.1), positive(scalar(0.2))])
+}
+
+#[test]
+fn gradcheck_matmul() -> Result<()> {
+    let matmul = |x: &[Tensor]| x[0].matmul(&x[1]);
+    check(matmul, &[matrix(2, 3, 0.1), vector(3, 0.2)])?;
+    check(matmul, &[matrix(2, 3, 0.1), matrix(3, 4, 0.2)])
+}
+
+#[test]
+fn gradcheck_sigmoid() -> Result<()> {
+    check(|x| x[0].sigmoid(), &[scalar(0.1)])?;
+    check(|x| x[0].sigmoid(), &[vector(3, 0.1)])?;
+    check(|x| x[0].sigmoid(), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].sigmoid(), &[tensor(&[2, 3, 2], 0.1)])?;
+    check(|x| x[0].sigmoid(), &[tensor(&[2, 2, 3, 2], 0.1)])
+}
+
+#[test]
+fn gradcheck_tanh() -> Result<()> {
+    check(|x| x[0].tanh(), &[scalar(0.1)])?;
+    check(|x| x[0].tanh(), &[vector(3, 0.1)])?;
+    check(|x| x[0].tanh(), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].tanh(), &[tensor(&[2, 3, 2], 0.1)])?;
+    check(|x| x[0].tanh(), &[tensor(&[2, 2, 3, 2], 0.1)])
+}
+
+#[test]
+fn gradcheck_relu() -> Result<()> {
+    // The test points stay clear of the kink at zero.
+    check(|x| x[0].relu(), &[scalar(0.1)])?;
+    check(|x| x[0].relu(), &[vector(3, 0.1)])?;
+    check(|x| x[0].relu(), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].relu(), &[tensor(&[2, 3, 2], 0.1)])?;
+    check(|x| x[0].relu(), &[tensor(&[2, 2, 3, 2], 0.1)])
+}
+
+#[test]
+fn gradcheck_mean() -> Result<()> {
+    check(|x| x[0].mean(), &[vector(3, 0.1)])?;
+    check(|x| x[0].mean(), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].mean(), &[tensor(&[2, 3, 2], 0.1)])?;
+    check(|x| x[0].mean(), &[tensor(&[2, 2, 3, 2], 0.1)])
+}
+
+#[test]
+fn gradcheck_sum() -> Result<()> {
+    check(|x| x[0].sum(), &[scalar(0.1)])?;
+    check(|x| x[0].sum(), &[vector(3, 0.1)])?;
+    check(|x| x[0].sum(), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].sum(), &[tensor(&[2, 3, 2], 0.1)])?;
+    check(|x| x[0].sum(), &[tensor(&[2, 2, 3, 2], 0.1)])
+}
+
+#[test]
+fn gradcheck_pow() -> Result<()> {
+    for exponent in [2.0, 3.0, 0.5, -1.0] {
+        let pow = move |x: &[Tensor]| x[0].pow(exponent);
+        check(pow, &[positive(scalar(0.1))])?;
+        check(pow, &[positive(vector(3, 0.1))])?;
+        check(pow, &[positive(matrix(2, 3, 0.1))])?;
+        check(pow, &[positive(tensor(&[2, 3, 2], 0.1))])?;
+        check(pow, &[positive(tensor(&[2, 2, 3, 2], 0.1))])?;
+    }
+    Ok(())
+}
+
+#[test]
+fn gradcheck_log() -> Result<()> {
+    for base in [std::f64::consts::E, 2.0, 10.0] {
+        let log = move |x: &[Tensor]| x[0].log(base);
+        check(log, &[positive(scalar(0.1))])?;
+        check(log, &[positive(vector(3, 0.1))])?;
+        check(log, &[positive(matrix(2, 3, 0.1))])?;
+        check(log, &[positive(tensor(&[2, 3, 2], 0.1))])?;
+        check(log, &[positive(tensor(&[2, 2, 3, 2], 0.1))])?;
+    }
+    Ok(())
+}
+
+#[test]
+fn gradcheck_softmax() -> Result<()> {
+    check(|x| x[0].softmax(), &[vector(4, 0.1)])?;
+    check(|x| x[0].softmax(), &[matrix(3, 2, 0.1)])
+}
+
+#[test]
+fn gradcheck_log_softmax() -> Result<()> {
+    check(|x| x[0].log_softmax(), &[vector(4, 0.1)])?;
+    check(|x| x[0].log_softmax(), &[matrix(3, 2, 0.1)])
+}
+
+#[test]
+fn gradcheck_t() -> Result<()> {
+    check(|x| x[0].t(), &[matrix(2, 3, 0.1)])
+}
+
+#[test]
+fn gradcheck_unsqueeze() -> Result<()> {
+    check(|x| x[0].unsqueeze(0), &[scalar(0.1)])?;
+    check(|x| x[0].unsqueeze(0), &[vector(3, 0.1)])?;
+    check(|x| x[0].unsqueeze(1), &[vector(3, 0.1)])
+}
+
+#[test]
+fn gradcheck_squeeze() -> Result<()> {
+    check(|x| x[0].squeeze(0), &[vector(1, 0.1)])?;
+    check(|x| x[0].squeeze(0), &[matrix(1, 3, 0.1)])?;
+    check(|x| x[0].squeeze(1), &[matrix(3, 1, 0.1)])
+}
+
+#[test]
+fn gradcheck_reshape() -> Result<()> {
+    check(|x| x[0].reshape(&[6]), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].reshape(&[3, 4]), &[tensor(&[2, 3, 2], 0.1)])?;
+    check(|x| x[0].reshape(&[2, 12]), &[tensor(&[2, 2, 3, 2], 0.1)])?;
+    check(|x| x[0].reshape(&[2, 2, 3, 2]), &[vector(24, 0.1)])
+}
+
+#[test]
+fn gradcheck_gather() -> Result<()> {
+    // Repeated indices make the gradient accumulate.
+    check(|x| x[0].gather(0, &IntTensor::vector(vec![2, 0, 2])), &[vector(3, 0.1)])?;
+    check(|x| x[0].gather(0, &IntTensor::matrix(vec![vec![1, 0, 1], vec![1, 1, 0]])), &[matrix(2, 3, 0.1)])?;
+    check(|x| x[0].gather(1, &IntTensor::matrix(vec![vec![2, 2], vec![0, 1]])), &[matrix(2, 3, 0.1)])
+}
+
+#[test]
+fn gradcheck_scatter_add() -> Result<()> {
+    let index = IntTensor::vector(vec![2, 0, 2]);
+    check(|x| x[0].scatter_add(0, &index, &x[1]), &[vector(3, 0.1), vector(3, 0.2)])?;
+    let index = IntTensor::matrix(vec![vec![1, 0, 1], vec![1, 1, 0]]);
+    check(|x| x[0].scatter_add(0, &index, &x[1]), &[matrix(2, 3, 0.1), matrix(2, 3, 0.2)])?;
+    let index = IntTensor::matrix(vec![vec![2, 2, 0], vec![0, 1, 1]]);
+    check(|x| x[0].scatter_add(1, &index, &x[1]), &[matrix(2, 3, 0.1), matrix(2, 3, 0.2)])
+}
+
+#[test]
+fn gradcheck_masked_fill() -> Result<()> {
+    let mask = BoolTensor::vector(vec![true, false, true]);
+    check(|x| x[0].masked_fill(&mask, -1.0), &[vector(3, 0.1)])?;
+    let mask = BoolTensor::matrix(vec![vec![false, true, false], vec![true, false, false]]);
+    check(|x| x[0].masked_fill(&mask, 0.5)?.tanh(), &[matrix(2, 3, 0.1)])
+}
+
+#[test]
+fn gradcheck_composite() -> Result<()> {
+    check(|x| {
+        let hidden = x[0].matmul(&x[2])?.tanh()?;
+        let outputs = x[1].matmul(&hidden)?.sigmoid()?;
+        outputs.log(std::f64::consts::E)?.mean()
+    }, &[matrix(4, 3, 0.1), matrix(2, 4, 0.2), vector(3, 0.3)])
+}
+
+#[test]
+fn gradcheck_second_order() -> Result<()> {
+    // Differentiating the gradient checks the `backward_graph` of each op.
+    use super::autograd::grad;
+    let first_order = |f: fn(&Tensor) -> Result<Tensor>| {
+        move |x: &[Tensor]| Ok(grad(&[f(&x[0])?], &[x[0].clone()])?.remove(0))
+    };
+
+    check(first_order(|x| x.sigmoid()?.sum()), &[vector(3, 0.1)])?;
+    check(first_order(|x| x.tanh()?.sum()), &[matrix(2, 3, 0.1)])?;
+    check(first_order(|x| x.pow(3.0)?.mean()), &[positive(vector(3, 0.1))])?;
+    check(first_order(|x| x.log(10.0)?.sum()), &[positive(vector(3, 0.1))])?;
+    check(first_order(|x| x.softmax()?.pow(2.0)?.sum()), &[matrix(3, 2, 0.1)])?;
+    check(first_order(|x| x.softmax()?.pow(2.0)?.sum()), &[vector(4, 0.1)])?;
+    check(first_order(|x| x.log_softmax()?.pow(2.0)?.sum()), &[matrix(3, 2, 0.1)])?;
+    check(first_order(|x| Tensor::scalar(1.0).div(x)?.sum()), &[positive(vector(3, 0.1))])?;
+    check(first_order(|x| x.matmul(&x.t()?)?.sum()), &[matrix(2, 3, 0.1)])?;
+    check(first_order(|x| x.unsqueeze(1)?.matmul(&x.unsqueeze(0)?)?.sum()), &[vector(3, 0.1)])?;
+    check(first_order(|x| x.sub(&x.mean()?)?.pow(2.0)?.sum()), &[vector(3, 0.1)])
+}
+
+#[test]
+fn gradcheck_f32() -> Result<()> {
+    // Single precision only resolves about three digits of a central difference.
+    let f32 = |tensor: Tensor| Tensor::<f32>::from_value(tensor.value().cast());
+    let check = |f: fn(&[Tensor<f32>]) -> Result<Tensor<f32>>, inputs: &[Tensor<f32>]| gradcheck(f, inputs, 1e-2, 1e-2);
+
+    check(|x| x[0].add(&x[1]), &[f32(matrix(2, 3, 0.1)), f32(vector(3, 0.2))])?;
+    check(|x| x[0].multiply(&x[1]), &[f32(matrix(2, 3, 0.1)), f32(scalar(0.2))])?;
+    check(|x| x[0].div(&x[1]), &[f32(vector(3, 0.1)), f32(positive(vector(3, 0.2)))])?;
+    check(|x| x[0].matmul(&x[1]), &[f32(matrix(2, 3, 0.1)), f32(matrix(3, 4, 0.2))])?;
+    check(|x| x[0].tanh()?.sigmoid(), &[f32(matrix(2, 3, 0.1))])?;
+    check(|x| x[0].pow(3.0)?.log(std::f32::consts::E), &[f32(positive(vector(3, 0.1)))])?;
+    check(|x| x[0].log_softmax(), &[f32(matrix(3, 2, 0.1))])?;
+    check(|x| x[0].softmax()?.mean(), &[f32(vector(4, 0.1))])
+}
+
+#[test]
+fn gradcheck_cast() -> Result<()> {
+    // The f32 stretch in the middle limits the precision of the whole.
+    gradcheck(
+        |x| x[0].multiply(&x[1])?.to_dtype::<f32>()?.tanh()?.to_dtype::<f64>()?.multiply(&x[1]),
+        &[matrix(2, 3, 0.1), vector(3, 0.2)],
+        1e-3,
+        1e-3,
+    )
+}
+
@@ -0,0 +1,135 @@
+// Gradient hooks, run by `ops::_backward` once a node's gradient is complete.
+
+use crate::tensor::dtype::Float;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::arena::WeakNodeRef;
+use super::value::TensorValue;
+use super::Tensor;
+use anyhow::{bail, Result};
+
+pub type HookFn<T> = dyn Fn(&TensorValue<T>) -> Option<TensorValue<T>>;
+
+static NEXT_HOOK_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone)]
+pub struct GradHook<T: Float = f64> {
+    id: usize,
+    hook: Rc<HookFn<T>>,
+}
+
+impl<T: Float> fmt::Debug for GradHook<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GradHook({})", self.id)
+    }
+}
+
+/// Returned by `Tensor::register_hook`; removes the hook again.
+pub struct HookHandle<T: Float = f64> {
+    id: usize,
+    node: WeakNodeRef<T>,
+}
+
+impl<T: Float> HookHandle<T> {
+    pub fn remove(self) {
+        if let Some(node) = self.node.upgrade() {
+            node.borrow_mut().hooks.retain(|hook| hook.id != self.id);
+        }
+    }
+}
+
+impl<T: Float> Tensor<T> {
+    /// Registers a hook that `Tensor::backward` calls with this tensor's gradient
+    /// once it is complete, before it is propagated any further. Returning
+    /// `Some(grad)` replaces the gradient (it must keep its shape); returning
+    /// `None` leaves it as is. Hooks run in registration order, each one seeing
+    /// the result of the previous.
+    ///
+    /// On a leaf the hook sees this backward pass's gradient, before it is
+    /// accumulated into `grad`.
+    pub fn register_hook<F>(&self, hook: F) -> HookHandle<T>
+    where
+        F: Fn(&TensorValue<T>) -> Option<TensorValue<T>> + 'static,
+    {
+        let id = NEXT_HOOK_ID.fetch_add(1, Ordering::Relaxed);
+        self.data.borrow_mut().hooks.push(GradHook { id, hook: Rc::new(hook) });
+        HookHandle { id, node: self.data.downgrade() }
+    }
+}
+
+/// Runs the hooks of `tensor` on its complete gradient, returning the gradient
+/// to propagate.
+pub fn run_hooks<T: Float>(tensor: &Tensor<T>, mut grad: TensorValue<T>) -> Result<TensorValue<T>>{
+    let hooks = tensor.data.borrow().hooks.clone();
+    for hook in hooks {
+        if let Some(new_grad) = (hook.hook)(&grad) {
+            if new_grad.shape() != grad.shape() {
+                bail!(
+                    "Gradient hook changed the gradient shape from {:?} to {:?}",
+                    grad.shape(), new_grad.shape()
+                );
+            }
+            grad = new_grad;
+        }
+    }
+    Ok(grad)
+}
+
+#[test]
+fn hook_can_log_and_reverse_gradients() -> Result<()>{
+    let x = Tensor::vector(vec![1.0, 2.0]);
+    let w = Tensor::vector(vec![3.0, 4.0]);
+    let hidden = x.multiply(&w)?;
+
+    let seen = Rc::new(std::cell::RefCell::new(vec![]));
+    let seen_in_hook = seen.clone();
+    hidden.register_hook(move |grad| {
+        seen_in_hook.borrow_mut().push(grad.clone());
+        None
+    });
+    // Gradient reversal: flip the sign of everything flowing into `x`'s branch.
+    hidden.register_hook(|grad| Some(grad.map(|g| -g)));
+
+    hidden.pow(2.0)?.sum()?.backward()?;
+
+    let logged = match &seen.borrow()[0] {
+        TensorValue::Vector1D(v) => v.clone(),
+        _ => panic!("Error!")
+    };
+    assert_eq!(logged, vec![6.0, 16.0]);
+    assert_eq!(grad_vec(&x), vec![-18.0, -64.0]);
+    assert_eq!(grad_vec(&w), vec![-6.0, -32.0]);
+    Ok(())
+}
+
+#[test]
+fn hook_handle_removes_hook() -> Result<()>{
+    let x: Tensor = Tensor::vector(vec![1.0, -5.0]);
+    let clip = x.register_hook(|grad| Some(grad.map(|g| g.clamp(-1.0, 1.0))));
+
+    x.multiply(&Tensor::scalar(3.0))?.sum()?.backward()?;
+    assert_eq!(grad_vec(&x), vec![1.0, 1.0]);
+
+    clip.remove();
+    x.data.borrow_mut().grad = Some(TensorValue::Vector1D(vec![0.0, 0.0]));
+    x.multiply(&Tensor::scalar(3.0))?.sum()?.backward()?;
+    assert_eq!(grad_vec(&x), vec![3.0, 3.0]);
+    Ok(())
+}
+
+#[test]
+fn hook_rejects_shape_change() -> Result<()>{
+    let x = Tensor::vector(vec![1.0, 2.0]);
+    x.register_hook(|_| Some(TensorValue::Scalar(0.0)));
+    assert!(x.sum()?.backward().is_err());
+    Ok(())
+}
+
+#[cfg(test)]
+fn grad_vec(tensor: &Tensor) -> Vec<f64> {
+    match &tensor.grad().unwrap().value() {
+        TensorValue::Vector1D(v) => v.clone(),
+        _ => panic!("Error!")
+    }
+}
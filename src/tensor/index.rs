@@ -0,0 +1,122 @@
+// Integer and boolean tensors for indices, class labels and masks. They hold
+// their elements exactly and never take part in autodiff; ops such as
+// `Tensor::gather` and `Tensor::masked_fill` take them as plain arguments.
+
+use anyhow::{bail, Result};
+
+/// A tensor of `i64` (`IntTensor`) or `bool` (`BoolTensor`) elements.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IndexTensor<T> {
+    Scalar(T),
+    Vector1D(Vec<T>),
+    Matrix2D(Vec<Vec<T>>),
+}
+
+pub type IntTensor = IndexTensor<i64>;
+pub type BoolTensor = IndexTensor<bool>;
+
+impl<T: Copy> IndexTensor<T> {
+    pub fn scalar(value: T) -> Self {
+        IndexTensor::Scalar(value)
+    }
+
+    pub fn vector(data: Vec<T>) -> Self {
+        IndexTensor::Vector1D(data)
+    }
+
+    pub fn matrix(data: Vec<Vec<T>>) -> Self {
+        IndexTensor::Matrix2D(data)
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        match self {
+            IndexTensor::Scalar(_) => vec![],
+            IndexTensor::Vector1D(v) => vec![v.len()],
+            IndexTensor::Matrix2D(m) => vec![m.len(), m[0].len()],
+        }
+    }
+
+    pub fn numel(&self) -> usize {
+        self.shape().iter().product()
+    }
+
+    pub fn flatten(&self) -> Vec<T> {
+        match self {
+            IndexTensor::Scalar(s) => vec![*s],
+            IndexTensor::Vector1D(v) => v.clone(),
+            IndexTensor::Matrix2D(m) => m.iter().flatten().copied().collect(),
+        }
+    }
+}
+
+impl BoolTensor {
+    pub fn logical_not(&self) -> BoolTensor {
+        match self {
+            IndexTensor::Scalar(s) => IndexTensor::Scalar(!s),
+            IndexTensor::Vector1D(v) => IndexTensor::Vector1D(v.iter().map(|x| !x).collect()),
+            IndexTensor::Matrix2D(m) => IndexTensor::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| !x).collect()).collect()
+            ),
+        }
+    }
+}
+
+impl IntTensor {
+    /// Elementwise `self == value`, e.g. a mask selecting one class.
+    pub fn equal_to(&self, value: i64) -> BoolTensor {
+        match self {
+            IndexTensor::Scalar(s) => IndexTensor::Scalar(*s == value),
+            IndexTensor::Vector1D(v) => IndexTensor::Vector1D(v.iter().map(|x| *x == value).collect()),
+            IndexTensor::Matrix2D(m) => IndexTensor::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *x == value).collect()).collect()
+            ),
+        }
+    }
+}
+
+/// Checks that `index` can gather along `dim` from a tensor of shape `shape`:
+/// same rank, no larger than `shape` outside `dim`, entries in range along `dim`.
+pub(crate) fn check_gather_index(shape: &[usize], dim: usize, index: &IntTensor) -> Result<()> {
+    let index_shape = index.shape();
+    if index_shape.len() != shape.len() || dim >= shape.len() {
+        bail!("Gather index of shape {:?} along dim {} does not fit input of shape {:?}", index_shape, dim, shape);
+    }
+    for (d, (&i, &s)) in index_shape.iter().zip(shape).enumerate() {
+        if d != dim && i > s {
+            bail!("Gather index of shape {:?} is larger than input of shape {:?} in dim {}", index_shape, shape, d);
+        }
+    }
+    if let Some(bad) = index.flatten().into_iter().find(|&i| i < 0 || i as usize >= shape[dim]) {
+        bail!("Index {} is out of range for dim {} of size {}", bad, dim, shape[dim]);
+    }
+    Ok(())
+}
+
+/// For each element of `index` (in row-major order), the row-major offset of
+/// the element it picks from a tensor of shape `shape`: its own position, with
+/// the coordinate along `dim` replaced by the index value.
+pub(crate) fn gather_offsets(shape: &[usize], dim: usize, index: &IntTensor) -> Vec<usize> {
+    let index_shape = index.shape();
+    index.flatten().into_iter().enumerate().map(|(k, i)| {
+        let mut rest = k;
+        let mut coords = vec![0; index_shape.len()];
+        for d in (0..index_shape.len()).rev() {
+            coords[d] = rest % index_shape[d];
+            rest /= index_shape[d];
+        }
+        coords[dim] = i as usize;
+        coords.iter().zip(shape).fold(0, |offset, (&c, &s)| offset * s + c)
+    }).collect()
+}
+
+#[test]
+fn gather_offsets_follow_dim() {
+    let index = IntTensor::matrix(vec![vec![2, 0], vec![1, 1]]);
+    // Picking along the columns of a 2x3 matrix: row i keeps i.
+    assert_eq!(gather_offsets(&[2, 3], 1, &index), vec![2, 0, 4, 4]);
+    // Picking along the rows of a 3x2 matrix: column j keeps j.
+    assert_eq!(gather_offsets(&[3, 2], 0, &index), vec![4, 1, 2, 3]);
+    assert!(check_gather_index(&[2, 3], 0, &index).is_err());
+    assert!(check_gather_index(&[3], 0, &index).is_err());
+    assert!(check_gather_index(&[1, 3], 1, &index).is_err());
+}
@@ -1,60 +1,83 @@
+pub mod dtype;
 pub mod value;
+pub mod arena;
+pub mod index;
 pub mod autodiff;
+pub mod autograd;
+pub mod gradcheck;
 pub mod node;
+pub mod tape;
 pub mod operation;
+pub mod function;
+pub mod hook;
+pub mod anomaly;
+pub mod dot;
+pub mod stats;
+pub mod trace;
+pub mod checkpoint;
+pub mod forward_ad;
 pub mod utils;
 pub mod ops;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::tensor::dtype::Float;
+use crate::tensor::arena::NodeRef;
 use crate::tensor::value::TensorValue;
 use crate::tensor::node::NodeData;
 use crate::tensor::operation::Operation;
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 #[derive(Clone, Debug)]
-pub struct Tensor {
-    pub data: Rc<RefCell<NodeData>>,
+pub struct Tensor<T: Float = f64> {
+    data: NodeRef<T>,
 }
 
-impl Tensor {
+impl<T: Float> Tensor<T> {
     fn metadata(&self) -> String {
         todo!()
     }
 
-    fn from_value(value: TensorValue) -> Self {
-        let grad = match &value {
-            TensorValue::Scalar(_) => TensorValue::Scalar(0.0),
-            TensorValue::Vector1D(v) => TensorValue::Vector1D(vec![0.0; v.len()]),
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(vec![vec![0.0; m[0].len()]; m.len()])
-            }
-            TensorValue::Tensor3D(_) => todo!()
-        };
-
+    fn from_value(value: TensorValue<T>) -> Self {
         Tensor {
-            data: Rc::new(RefCell::new(NodeData {
+            data: NodeRef::new(NodeData {
                 value,
-                grad,
+                grad: None,
+                retain_grad: false,
+                grad_graph: None,
                 operation: Operation::None,
                 dependencies: vec![],
-            })),
+                hooks: vec![],
+                created_at: None,
+                tangent: None,
+            }),
         }
     }
 
-    pub fn scalar(value: f64) -> Self {
+    pub fn scalar(value: T) -> Self {
         Self::from_value(TensorValue::Scalar(value))
     }
 
-    pub fn vector(data: Vec<f64>) -> Self {
+    pub fn vector(data: Vec<T>) -> Self {
         Self::from_value(TensorValue::Vector1D(data))
     }
 
-    pub fn matrix(data: Vec<Vec<f64>>) -> Self {
+    pub fn matrix(data: Vec<Vec<T>>) -> Self {
         Self::from_value(TensorValue::Matrix2D(data))
     }
 
-    pub fn to_scalar(&self) -> Result<f64> {
+    pub fn tensor3d(data: Vec<Vec<Vec<T>>>) -> Self {
+        Self::from_value(TensorValue::Tensor3D(data))
+    }
+
+    pub fn tensor4d(data: Vec<Vec<Vec<Vec<T>>>>) -> Self {
+        Self::from_value(TensorValue::Tensor4D(data))
+    }
+
+    /// A tensor of `shape` filled from row-major `data`.
+    pub fn from_flat(shape: &[usize], data: &[T]) -> Self {
+        Self::from_value(TensorValue::from_flat(shape, data))
+    }
+
+    pub fn to_scalar(&self) -> Result<T> {
         let data = self.data.borrow();
         if let TensorValue::Scalar(s) = &data.value {
             Ok(s.clone())
@@ -63,7 +86,7 @@ impl Tensor {
         }
     }
 
-    pub fn to_vec(&self) -> Result<Vec<f64>> {
+    pub fn to_vec(&self) -> Result<Vec<T>> {
         let data = self.data.borrow();
         if let TensorValue::Vector1D(v) = &data.value {
             Ok(v.clone())
@@ -72,7 +95,7 @@ impl Tensor {
         }
     }
 
-    pub fn to_matrix(&self) -> Result<Vec<Vec<f64>>> {
+    pub fn to_matrix(&self) -> Result<Vec<Vec<T>>> {
         let data = self.data.borrow();
         if let TensorValue::Matrix2D(v) = &data.value {
             Ok(v.clone())
@@ -81,5 +104,73 @@ impl Tensor {
         }
     }
 
+    /// A copy of the current value.
+    pub fn value(&self) -> TensorValue<T> {
+        self.data.borrow().value.clone()
+    }
+
+    /// Replaces the value in place, e.g. for an optimizer update. The shape
+    /// must not change, since ops recorded on this tensor rely on it.
+    pub fn set_value(&self, value: TensorValue<T>) {
+        let mut data = self.data.borrow_mut();
+        assert_eq!(data.value.shape(), value.shape(), "set_value cannot change the shape");
+        data.value = value;
+    }
+
+    /// A copy of the accumulated gradient as a new leaf tensor, or `None` if no
+    /// gradient is stored (see `retain_grad`).
+    pub fn grad(&self) -> Option<Tensor<T>> {
+        self.data.borrow().grad.clone().map(Tensor::from_value)
+    }
+
+    /// Resets a stored gradient to zeros, keeping its buffer allocated.
+    pub fn zero_grad(&self) {
+        if let Some(grad) = &mut self.data.borrow_mut().grad {
+            grad.fill(T::zero());
+        }
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        self.data.borrow().value.shape()
+    }
+
+    /// Number of elements; 1 for scalars.
+    pub fn numel(&self) -> usize {
+        self.shape().iter().product()
+    }
+
+    /// The only element of a tensor with one element, whatever its shape.
+    pub fn item(&self) -> Result<T> {
+        let values = self.data.borrow().value.flatten();
+        if values.len() != 1 {
+            bail!("item() needs a tensor with one element, got shape {:?}", self.shape());
+        }
+        Ok(values[0])
+    }
+
+
+}
 
-}
\ No newline at end of file
+#[test]
+fn accessors_copy_value_and_grad() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+    assert_eq!(x.shape(), vec![2, 3]);
+    assert_eq!(x.numel(), 6);
+    assert!(x.grad().is_none());
+    assert!(x.item().is_err());
+
+    let loss = x.sum()?;
+    assert_eq!(loss.numel(), 1);
+    assert_eq!(loss.item()?, 21.0);
+    assert_eq!(Tensor::vector(vec![4.0]).item()?, 4.0);
+
+    loss.backward()?;
+    let grad = x.grad().unwrap();
+    assert_eq!(grad.to_matrix()?, vec![vec![1.0; 3]; 2]);
+    // The gradient is detached from `x`.
+    assert!(grad.is_leaf());
+    x.zero_grad();
+    assert_eq!(x.grad().unwrap().value().flatten(), vec![0.0; 6]);
+    assert_eq!(grad.value().flatten(), vec![1.0; 6]);
+    Ok(())
+}
@@ -1,60 +1,41 @@
+use crate::tensor::dtype::Float;
+use std::panic::Location;
+use crate::tensor::hook::GradHook;
 use crate::tensor::operation::Operation;
 use crate::tensor::Tensor;
 use crate::tensor::value::TensorValue;
 use anyhow::Result;
 
 #[derive(Clone, Debug)]
-pub struct NodeData {
-    pub value: TensorValue,
-    pub grad: TensorValue,
-    pub operation: Operation,
-    pub dependencies: Vec<Tensor>,
+pub struct NodeData<T: Float = f64> {
+    pub value: TensorValue<T>,
+    /// Accumulated gradient, allocated when the first gradient flows in. Non-leaf
+    /// nodes drop it again once backward has propagated it, see `retain_grad`.
+    pub grad: Option<TensorValue<T>>,
+    pub retain_grad: bool,
+    /// Differentiable gradient, only filled in by `Tensor::backward_create_graph`.
+    pub grad_graph: Option<Tensor<T>>,
+    pub operation: Operation<T>,
+    pub dependencies: Vec<Tensor<T>>,
+    pub hooks: Vec<GradHook<T>>,
+    /// Where the op was called, only recorded in anomaly detection mode.
+    pub created_at: Option<&'static Location<'static>>,
+    /// Tangent carried along by `forward_ad::jvp`, tagged with the call it
+    /// belongs to.
+    pub tangent: Option<(u64, TensorValue<T>)>,
 }
 
-impl NodeData {
-    pub fn add_grad_scalar(&mut self, delta: f64) -> Result<()>{
-        match &mut self.grad {
-            TensorValue::Scalar(v) => *v += delta,
-            TensorValue::Vector1D(vec) => vec.iter_mut().for_each(|x| *x += delta),
-            TensorValue::Matrix2D(mat) => mat.iter_mut()
-                .for_each(|row| row.iter_mut().for_each(|x| *x += delta)),
-            TensorValue::Tensor3D(_) => todo!()
-        }
-        Ok(())
+impl<T: Float> NodeData<T> {
+    fn grad_mut(&mut self) -> &mut TensorValue<T> {
+        let value = &self.value;
+        self.grad.get_or_insert_with(|| TensorValue::full(&value.shape(), T::zero()))
     }
 
-    pub fn add_grad(&mut self, delta: TensorValue) -> Result<()>{
-        match (&mut self.grad, &delta) {
-            (TensorValue::Scalar(a), TensorValue::Scalar(b)) => *a += b,
-            (TensorValue::Vector1D(a), TensorValue::Vector1D(b)) => {
-                a.iter_mut().zip(b).for_each(|(a, b)| *a += b)
-            }
-            (TensorValue::Matrix2D(a), TensorValue::Matrix2D(b)) => {
-                a.iter_mut().zip(b).for_each(|(a_row, b_row)| {
-                    a_row.iter_mut().zip(b_row).for_each(|(a, b)| *a += b)
-                })
-            }
-
-            // 梯度广播
-            (TensorValue::Vector1D(a), TensorValue::Scalar(b)) => {
-                a.iter_mut().for_each(|a| *a += b)
-            }
-            (TensorValue::Matrix2D(a), TensorValue::Vector1D(b)) => {
-                a.iter_mut().for_each(|a_row| {
-                    a_row.iter_mut().zip(b.clone()).for_each(|(a, b_val)| *a += b_val)
-                })
-            }
-            (TensorValue::Matrix2D(a), TensorValue::Scalar(b)) => {
-                a.iter_mut().for_each(|a_row| {
-                    a_row.iter_mut().for_each(|a| *a += b)
-                })
-            }
-
-            _ => {
-                println!("{:?}", self.grad);
-                println!("{:?}", delta);
-                panic!("Gradient shape mismatch")
-            },
+    pub fn add_grad(&mut self, delta: &TensorValue<T>) -> Result<()>{
+        if self.grad.is_none() && delta.same_shape(&self.value) {
+            self.grad = Some(delta.clone());
+        } else {
+            self.grad_mut().accumulate(delta)?;
         }
         Ok(())
     }
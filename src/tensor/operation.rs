@@ -1,5 +1,14 @@
-#[derive(Clone, Copy, Debug)]
-pub enum Operation{
+use std::rc::Rc;
+use crate::tensor::dtype::Float;
+use crate::tensor::function::Function;
+use crate::tensor::index::{BoolTensor, IntTensor};
+use crate::tensor::ops::conv1d::ConvOptions;
+use crate::tensor::ops::pool::Pooling;
+use crate::tensor::ops::batch_norm::RunningStats;
+use crate::tensor::ops::to_dtype::CastSource;
+
+#[derive(Clone, Debug)]
+pub enum Operation<T: Float = f64> {
     None,
 
     Add,
@@ -10,17 +19,60 @@ pub enum Operation{
     Sigmoid,
     Tanh,
     Mean,
-    Pow(f64),
+    Pow(T),
     ReLU,
-    Log(f64),
+    Log(T),
     Softmax,
+    LogSoftmax,
     Sum,
     T,
+    Gather(usize, Rc<IntTensor>),
+    ScatterAdd(usize, Rc<IntTensor>),
+    MaskedFill(Rc<BoolTensor>, T),
+    Reshape(Vec<usize>),
+    MaxPool(Pooling),
+    AvgPool(Pooling),
+    /// `eps`, and the running statistics if normalizing with those.
+    BatchNorm(T, Option<RunningStats<T>>),
+    LayerNorm(T),
+    GroupNorm(usize, T),
+    /// A conversion from a tensor of another element type.
+    Cast(Rc<dyn CastSource<T>>),
     // TODO
 
     Broadcast,
     Unsqueeze(usize),
     Squeeze(usize),
-    Convolution1D,
-    Convolution2D,
-}
\ No newline at end of file
+    Convolution1D(ConvOptions),
+    Convolution2D(ConvOptions),
+
+    /// A user-defined op, see `tensor::function`.
+    Custom(Rc<dyn Function<T>>),
+}
+
+impl<T: Float> Operation<T> {
+    /// The variant name without its parameters (`Pow` for `Pow(2.0)`); custom
+    /// ops are named by their `Debug` output.
+    pub fn name(&self) -> String {
+        match self {
+            Operation::Pow(_) => "Pow".to_string(),
+            Operation::Log(_) => "Log".to_string(),
+            Operation::Unsqueeze(_) => "Unsqueeze".to_string(),
+            Operation::Squeeze(_) => "Squeeze".to_string(),
+            Operation::Gather(..) => "Gather".to_string(),
+            Operation::ScatterAdd(..) => "ScatterAdd".to_string(),
+            Operation::MaskedFill(..) => "MaskedFill".to_string(),
+            Operation::Reshape(_) => "Reshape".to_string(),
+            Operation::MaxPool(_) => "MaxPool".to_string(),
+            Operation::AvgPool(_) => "AvgPool".to_string(),
+            Operation::BatchNorm(..) => "BatchNorm".to_string(),
+            Operation::LayerNorm(_) => "LayerNorm".to_string(),
+            Operation::GroupNorm(..) => "GroupNorm".to_string(),
+            Operation::Convolution1D(_) => "Convolution1D".to_string(),
+            Operation::Convolution2D(_) => "Convolution2D".to_string(),
+            Operation::Cast(_) => "Cast".to_string(),
+            Operation::Custom(function) => format!("{:?}", function),
+            operation => format!("{:?}", operation),
+        }
+    }
+}
@@ -1,106 +1,147 @@
+use crate::tensor::dtype::Float;
 use std::error::Error;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
+use super::{reduce_grad, shape_of};
 
-impl Tensor {
-    pub fn add(&self, other: &Tensor) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn add(&self, other: &Tensor<T>) -> Result<Tensor<T>> {
         let a = self.data.borrow();
         let b = other.data.borrow();
 
-        let result_value = match (&a.value, &b.value) {
-
-            // Scalar + Scalar
-            (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
-                TensorValue::Scalar(a_val + b_val)
-            }
-
-            // dim1 + dim1
-            (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
-                assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
-                TensorValue::Vector1D(
-                    a_vec.iter().zip(b_vec).map(|(a, b)| a + b).collect()
-                )
-            }
-
-            // dim2 + dim2
-            (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
-                assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
-                assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
-                TensorValue::Matrix2D(
-                    a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
-                        a_row.iter().zip(b_row).map(|(a, b)| a + b).collect()
-                    }).collect()
-                )
-            }
-
-            // Dim3 + Dim3
-            (TensorValue::Tensor3D(a_3d), TensorValue::Tensor3D(b_3d)) => {
-                assert_eq!(a_3d.len(), b_3d.len(), "Matrix rows mismatch");
-                assert_eq!(a_3d[0].len(), b_3d[0].len(), "Matrix cols mismatch");
-                assert_eq!(a_3d[0].len(), b_3d[0].len(), "Matrix cols mismatch");
-
-                TensorValue::Tensor3D(
-                    a_3d.iter().zip(b_3d).map(|(a_1, b_1)| {
-                        a_1.iter().zip(b_1).map(|(a_2, b_2)|{
-                            a_2.iter().zip(b_2).map(|(a, b)| a + b).collect()
-                        }).collect()
-                    }).collect()
-                )
-            }
-
-            // Broadcast
-            // Dim1 + Scalar
-            (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
-                TensorValue::Vector1D(v.iter().map(|x| x + s).collect())
-            }
-
-            // Dim2 + Scalar
-            (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| x + s).collect()).collect()
-                )
-            }
-
-            _ => panic!("Invalid add operation between types"),
-        };
+        let result_value = forward(&a.value, &b.value);
         let result = Self::from_value(result_value);
         {
             let mut res_data = result.data.borrow_mut();
             res_data.operation = Operation::Add;
             res_data.dependencies = vec![self.clone(), other.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(a: &TensorValue<T>, b: &TensorValue<T>) -> TensorValue<T> {
+    match (a, b) {
+
+        // Scalar + Scalar
+        (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
+            TensorValue::Scalar(*a_val + *b_val)
+        }
+
+        // dim1 + dim1
+        (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
+            assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
+            TensorValue::Vector1D(
+                a_vec.iter().zip(b_vec).map(|(a, b)| *a + *b).collect()
+            )
+        }
+
+        // dim2 + dim2
+        (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
+            assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
+            assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
+            TensorValue::Matrix2D(
+                a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
+                    a_row.iter().zip(b_row).map(|(a, b)| *a + *b).collect()
+                }).collect()
+            )
+        }
+
+        // Dim3 + Dim3
+        (TensorValue::Tensor3D(a_3d), TensorValue::Tensor3D(b_3d)) => {
+            assert_eq!(a_3d.len(), b_3d.len(), "Matrix rows mismatch");
+            assert_eq!(a_3d[0].len(), b_3d[0].len(), "Matrix cols mismatch");
+            assert_eq!(a_3d[0].len(), b_3d[0].len(), "Matrix cols mismatch");
+
+            TensorValue::Tensor3D(
+                a_3d.iter().zip(b_3d).map(|(a_1, b_1)| {
+                    a_1.iter().zip(b_1).map(|(a_2, b_2)|{
+                        a_2.iter().zip(b_2).map(|(a, b)| *a + *b).collect()
+                    }).collect()
+                }).collect()
+            )
+        }
+
+        (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            assert!(a.same_shape(b), "Tensor4D shape mismatch");
+            a.zip_map(b, |a, b| a + b)
+        }
+
+        // Broadcast
+        // Dim1 + Scalar
+        (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *x + *s).collect())
+        }
+
+        // Dim2 + Scalar
+        (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *x + *s).collect()).collect()
+            )
+        }
+
+        // Dim2 + Dim1: the vector is added to every row
+        (TensorValue::Matrix2D(m), TensorValue::Vector1D(v)) => {
+            assert_eq!(m[0].len(), v.len(), "Matrix cols mismatch with vector length");
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().zip(v).map(|(a, b)| *a + *b).collect()).collect()
+            )
+        }
+
+        _ => panic!("Invalid add operation between types"),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, a: &TensorValue<T>, b: &TensorValue<T>) {
+    if !out.zip_map_into(a, b, |a, b| a + b) {
+        *out = forward(a, b);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
 
     if dependencies.len() != 2 {
         panic!("Add operation requires exactly 2 dependencies");
     }
-    let a = &dependencies[0];
-    let b = &dependencies[1];
 
-    match &data.grad {
+    let (a_grad, b_grad) = match grad {
         TensorValue::Scalar(grad) => {
-            a.data.borrow_mut().add_grad_scalar(*grad)?;
-            b.data.borrow_mut().add_grad_scalar(*grad)?;
+            (TensorValue::Scalar(*grad), TensorValue::Scalar(*grad))
         }
         TensorValue::Vector1D(grad_vec) => {
-            a.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_vec.clone()))?;
-            b.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_vec.clone()))?;
+            (TensorValue::Vector1D(grad_vec.clone()), TensorValue::Vector1D(grad_vec.clone()))
         }
         TensorValue::Matrix2D(grad_mat) => {
-            a.data.borrow_mut().add_grad(TensorValue::Matrix2D(grad_mat.clone()))?;
-            b.data.borrow_mut().add_grad(TensorValue::Matrix2D(grad_mat.clone()))?;
+            (TensorValue::Matrix2D(grad_mat.clone()), TensorValue::Matrix2D(grad_mat.clone()))
         }
-        TensorValue::Tensor3D(_) => todo!()
-    }
+        TensorValue::Tensor3D(grad_3d) => {
+            (TensorValue::Tensor3D(grad_3d.clone()), TensorValue::Tensor3D(grad_3d.clone()))
+        }
+        TensorValue::Tensor4D(_) => (grad.clone(), grad.clone()),
+    };
 
-    Ok(())
+    Ok(vec![a_grad, b_grad])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let a = &data.dependencies[0];
+    let b = &data.dependencies[1];
+
+    Ok(vec![
+        reduce_grad(grad.clone(), &shape_of(a))?,
+        reduce_grad(grad.clone(), &shape_of(b))?,
+    ])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    forward(&tangents[0], &tangents[1])
 }
 
 #[test]
@@ -109,11 +150,11 @@ fn add_works() -> Result<()>{
     let b = Tensor::scalar(3.0);
     let c = a.add(&b)?;
     c.backward()?;
-    let a_grad = match &a.data.borrow().grad {
+    let a_grad = match &a.grad().unwrap().value() {
         TensorValue::Scalar(s) => *s,
         _ => panic!("Error!")
     };
-    let b_grad = match &b.data.borrow().grad {
+    let b_grad = match &b.grad().unwrap().value() {
         TensorValue::Scalar(s) => *s,
         _ => panic!("Error!")
     };
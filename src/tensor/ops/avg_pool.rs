@@ -0,0 +1,129 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::pool::{Pooling, PoolWindows};
+use super::super::{Tensor, TensorValue};
+use super::route_grad;
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// Mean over windows along the length of a `[batch, channels, length]` input.
+    #[track_caller]
+    pub fn avg_pool1d(&self, pooling: Pooling) -> Result<Tensor<T>> {
+        if self.shape().len() != 3 {
+            bail!("avg_pool1d expects a [batch, channels, length] input, got shape {:?}", self.shape());
+        }
+        self.avg_pool(pooling)
+    }
+
+    /// Mean over windows of a `[batch, channels, height, width]` input.
+    #[track_caller]
+    pub fn avg_pool2d(&self, pooling: Pooling) -> Result<Tensor<T>> {
+        if self.shape().len() != 4 {
+            bail!("avg_pool2d expects a [batch, channels, height, width] input, got shape {:?}", self.shape());
+        }
+        self.avg_pool(pooling)
+    }
+
+    #[track_caller]
+    fn avg_pool(&self, pooling: Pooling) -> Result<Tensor<T>> {
+        let result_value = forward(&self.data.borrow().value, &pooling)?;
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::AvgPool(pooling);
+            res_data.dependencies = vec![self.clone()];
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, pooling: &Pooling) -> Result<TensorValue<T>> {
+    let windows = PoolWindows::new(&input.shape(), pooling)?;
+    let x = input.flatten();
+    let mut out = vec![T::zero(); windows.output_len()];
+    windows.for_each(|o, base, window| {
+        let sum: T = window.iter().map(|i| x[base + i]).sum();
+        out[o] = sum / T::from_f64(window.len() as f64);
+    });
+    Ok(TensorValue::from_flat(&windows.output_shape, &out))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, pooling: &Pooling) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    if data.dependencies.len() != 1 {
+        panic!("AvgPool operation requires exactly 1 dependency");
+    }
+
+    let input_shape = data.dependencies[0].shape();
+    let windows = PoolWindows::new(&input_shape, pooling)?;
+    let g = grad.flatten();
+    let mut dx = vec![T::zero(); input_shape.iter().product()];
+    windows.for_each(|o, base, window| {
+        let share = g[o] / T::from_f64(window.len() as f64);
+        window.iter().for_each(|i| dx[base + i] += share);
+    });
+    Ok(vec![TensorValue::from_flat(&input_shape, &dx)])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, pooling: &Pooling) -> Result<Vec<Tensor<T>>>{
+    let input_shape = tensor.data.borrow().dependencies[0].shape();
+    let windows = PoolWindows::new(&input_shape, pooling)?;
+    let (mut from, mut to, mut shares) = (vec![], vec![], vec![]);
+    windows.for_each(|o, base, window| {
+        for i in window {
+            from.push(o as i64);
+            to.push((base + i) as i64);
+            shares.push(T::one() / T::from_f64(window.len() as f64));
+        }
+    });
+    Ok(vec![route_grad(grad, from, to, Some(Tensor::vector(shares)), &input_shape)?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], pooling: &Pooling) -> Result<TensorValue<T>> {
+    forward(&tangents[0], pooling)
+}
+
+#[test]
+fn adaptive_avg_pool_averages_uneven_windows() -> Result<()> {
+    let x = Tensor::tensor3d(vec![vec![vec![1.0, 2.0, 3.0, 4.0, 5.0]]]);
+    let y = x.avg_pool1d(Pooling::Adaptive(vec![2]))?;
+    assert_eq!(y.value().flatten(), vec![2.0, 4.0]);
+
+    y.sum()?.backward()?;
+    assert_eq!(x.grad().unwrap().value().flatten(), vec![1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0]);
+    Ok(())
+}
+
+#[test]
+fn avg_pool2d_gradients_match_finite_differences() -> Result<()> {
+    let data: Vec<f64> = (0..2 * 3 * 4 * 5).map(|i| (i as f64 * 0.41).sin()).collect();
+    let x = Tensor::from_value(TensorValue::from_flat(&[2, 3, 4, 5], &data));
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].avg_pool2d(Pooling::Window { kernel_size: 2, stride: 1, padding: 1 })?.tanh(),
+        std::slice::from_ref(&x),
+        1e-6,
+        1e-5,
+    )?;
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].avg_pool2d(Pooling::Adaptive(vec![3, 2]))?.tanh(),
+        &[x],
+        1e-6,
+        1e-5,
+    )
+}
+
+#[test]
+fn avg_pool_second_order_gradients_flow_through_the_pooling() -> Result<()> {
+    // y = sum(avg_pool(x)^2) with windows of two: dy/dx_i = (x_i + x_j) / 2 for
+    // the other element j of its window, so sum(dy/dx) has gradient 1 everywhere.
+    let x = Tensor::tensor3d(vec![vec![vec![1.0, 2.0, 3.0, 4.0]]]);
+    let y = x.avg_pool1d(Pooling::window(2))?.pow(2.0)?.sum()?;
+    let dx = crate::tensor::autograd::grad(&[y], std::slice::from_ref(&x))?;
+    assert_eq!(dx[0].value().flatten(), vec![1.5, 1.5, 3.5, 3.5]);
+    let d2x = crate::tensor::autograd::grad(&[dx[0].sum()?], std::slice::from_ref(&x))?;
+    assert_eq!(d2x[0].value().flatten(), vec![1.0, 1.0, 1.0, 1.0]);
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::rc::Rc;
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::norm::{inv_std, moments, normalize, normalize_backward, normalize_backward_graph, normalize_jvp};
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+/// Fixed `(mean, var)` per channel for `batch_norm` to normalize with instead
+/// of the batch statistics.
+pub type RunningStats<T> = Rc<(Vec<T>, Vec<T>)>;
+
+impl<T: Float> Tensor<T> {
+    /// Normalizes every channel (dimension 1) of a `[batch, channels, ...]`
+    /// input to zero mean and unit variance over the batch and any spatial
+    /// dimensions, then scales by `weight` and shifts by `bias`, both
+    /// `[channels]`. With `running` set, its `(mean, var)` are used instead of
+    /// the batch statistics and are treated as constants.
+    #[track_caller]
+    pub fn batch_norm(&self, weight: &Tensor<T>, bias: &Tensor<T>, running: Option<(&Tensor<T>, &Tensor<T>)>, eps: T) -> Result<Tensor<T>> {
+        let running = running.map(|(mean, var)| Rc::new((mean.value().flatten(), var.value().flatten())));
+        let result_value = forward(&self.value(), &weight.value(), &bias.value(), eps, running.as_ref())?;
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::BatchNorm(eps, running);
+            res_data.dependencies = vec![self.clone(), weight.clone(), bias.clone()];
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+/// Channel count and the channel of every element of a `[batch, channels, ...]` shape.
+pub(crate) fn channel_layout(shape: &[usize]) -> Result<(usize, impl Fn(usize) -> usize)> {
+    if shape.len() < 2 {
+        bail!("Batch normalization expects a [batch, channels, ...] input, got shape {:?}", shape);
+    }
+    let channels = shape[1];
+    let spatial: usize = shape[2..].iter().product();
+    Ok((channels, move |i: usize| (i / spatial) % channels))
+}
+
+fn statistics<T: Float>(x: &[T], shape: &[usize], eps: T, running: Option<&RunningStats<T>>) -> Result<Vec<(T, T)>> {
+    let (channels, channel_of) = channel_layout(shape)?;
+    let moments = match running {
+        Some(running) => {
+            if running.0.len() != channels || running.1.len() != channels {
+                bail!("Batch normalization running statistics must have one entry per channel ({})", channels);
+            }
+            running.0.iter().copied().zip(running.1.iter().copied()).collect()
+        }
+        None => moments(x, channels, channel_of),
+    };
+    Ok(inv_std(&moments, eps))
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, weight: &TensorValue<T>, bias: &TensorValue<T>, eps: T, running: Option<&RunningStats<T>>) -> Result<TensorValue<T>> {
+    let shape = input.shape();
+    let x = input.flatten();
+    let (weight, bias) = (weight.flatten(), bias.flatten());
+    let (channels, channel_of) = channel_layout(&shape)?;
+    if weight.len() != channels || bias.len() != channels {
+        bail!("Batch normalization weight and bias must have one entry per channel ({}), got {} and {}", channels, weight.len(), bias.len());
+    }
+
+    let stats = statistics(&x, &shape, eps, running)?;
+    Ok(TensorValue::from_flat(&shape, &normalize(&x, &stats, &channel_of, &weight, &bias, &channel_of)))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, eps: T, running: Option<&RunningStats<T>>) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    if data.dependencies.len() != 3 {
+        panic!("BatchNorm operation requires exactly 3 dependencies");
+    }
+
+    let input = data.dependencies[0].value();
+    let shape = input.shape();
+    let x = input.flatten();
+    let weight = data.dependencies[1].value().flatten();
+    let (_, channel_of) = channel_layout(&shape)?;
+
+    let stats = statistics(&x, &shape, eps, running)?;
+    let (dx, d_weight, d_bias) = normalize_backward(&x, &grad.flatten(), &stats, &channel_of, &weight, &channel_of, running.is_none());
+    Ok(vec![
+        TensorValue::from_flat(&shape, &dx),
+        TensorValue::Vector1D(d_weight),
+        TensorValue::Vector1D(d_bias),
+    ])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, eps: T, running: Option<&RunningStats<T>>) -> Result<Vec<Tensor<T>>>{
+    let shape = tensor.data.borrow().dependencies[0].shape();
+    let (channels, channel_of) = channel_layout(&shape)?;
+    let running: Option<Vec<(T, T)>> = running.map(|running| running.0.iter().copied().zip(running.1.iter().copied()).collect());
+    normalize_backward_graph(tensor, grad, eps, channels, &channel_of, &channel_of, running.as_deref())
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], eps: T, running: Option<&RunningStats<T>>) -> Result<TensorValue<T>> {
+    let data = tensor.data.borrow();
+    let input = data.dependencies[0].value();
+    let shape = input.shape();
+    let x = input.flatten();
+    let weight = data.dependencies[1].value().flatten();
+    let (_, channel_of) = channel_layout(&shape)?;
+
+    let stats = statistics(&x, &shape, eps, running)?;
+    let tangents: Vec<Vec<T>> = tangents.iter().map(|t| t.flatten()).collect();
+    let out = normalize_jvp(&x, &stats, &channel_of, &weight, &channel_of, [&tangents[0], &tangents[1], &tangents[2]], running.is_none());
+    Ok(TensorValue::from_flat(&shape, &out))
+}
+
+#[test]
+fn batch_norm_normalizes_each_channel() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 10.0], vec![3.0, 20.0], vec![5.0, 60.0]]);
+    let y = x.batch_norm(&Tensor::vector(vec![1.0, 2.0]), &Tensor::vector(vec![0.0, 1.0]), None, 0.0)?;
+    for channel in 0..2 {
+        let column: Vec<f64> = y.to_matrix()?.iter().map(|row| row[channel]).collect();
+        let mean = column.iter().sum::<f64>() / 3.0;
+        let var = column.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 3.0;
+        approx::assert_abs_diff_eq!(mean, channel as f64, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(var, (1.0 + channel as f64).powi(2), epsilon = 1e-12);
+    }
+
+    assert!(x.batch_norm(&Tensor::vector(vec![1.0; 3]), &Tensor::vector(vec![0.0; 3]), None, 0.0).is_err());
+    assert!(Tensor::vector(vec![1.0, 2.0]).batch_norm(&Tensor::vector(vec![1.0]), &Tensor::vector(vec![0.0]), None, 0.0).is_err());
+    let short = Tensor::vector(vec![0.0]);
+    assert!(x.batch_norm(&Tensor::vector(vec![1.0; 2]), &Tensor::vector(vec![0.0; 2]), Some((&short, &short)), 0.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn batch_norm_gradients_match_finite_differences() -> Result<()> {
+    let data: Vec<f64> = (0..3 * 2 * 2 * 3).map(|i| (i as f64 * 0.53).sin() * 2.0).collect();
+    let inputs = [
+        Tensor::from_flat(&[3, 2, 2, 3], &data),
+        Tensor::vector(vec![0.8, -1.3]),
+        Tensor::vector(vec![0.1, 0.4]),
+    ];
+    // Weights make the loss depend on more than the normalized sum, which is constant.
+    let weights: Vec<f64> = (0..data.len()).map(|i| (i as f64 * 1.7).cos()).collect();
+    let weights = Tensor::from_flat(&[3, 2, 2, 3], &weights);
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].batch_norm(&inputs[1], &inputs[2], None, 1e-5)?.multiply(&weights),
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+
+    let mean = Tensor::vector(vec![0.2, -0.1]);
+    let var = Tensor::vector(vec![1.5, 0.7]);
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].batch_norm(&inputs[1], &inputs[2], Some((&mean, &var)), 1e-5)?.tanh(),
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn batch_norm_second_order_gradients_match_finite_differences() -> Result<()> {
+    let data: Vec<f64> = (0..3 * 2 * 2).map(|i| (i as f64 * 0.53).sin() * 2.0).collect();
+    let inputs = [
+        Tensor::from_flat(&[3, 2, 2], &data),
+        Tensor::vector(vec![0.8, -1.3]),
+        Tensor::vector(vec![0.1, 0.4]),
+    ];
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].batch_norm(&inputs[1], &inputs[2], None, 1e-5)?.tanh()?.sum()?;
+            let grads = crate::tensor::autograd::grad(&[y], inputs)?;
+            grads[0].pow(2.0)?.sum()?.add(&grads[1].tanh()?.sum()?)?.add(&grads[2].pow(2.0)?.sum()?)
+        },
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+
+    let mean = Tensor::vector(vec![0.2, -0.1]);
+    let var = Tensor::vector(vec![1.5, 0.7]);
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].batch_norm(&inputs[1], &inputs[2], Some((&mean, &var)), 1e-5)?.tanh()?.sum()?;
+            let grads = crate::tensor::autograd::grad(&[y], inputs)?;
+            grads[0].pow(2.0)?.sum()?.add(&grads[1].tanh()?.sum()?)?.add(&grads[2].pow(2.0)?.sum()?)
+        },
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+
+    // The graph gradients are the ones `backward` computes.
+    let y = inputs[0].batch_norm(&inputs[1], &inputs[2], None, 1e-5)?.tanh()?.sum()?;
+    let grads = crate::tensor::autograd::grad(std::slice::from_ref(&y), &inputs)?;
+    y.backward()?;
+    for (input, grad) in inputs.iter().zip(&grads) {
+        for (a, b) in input.grad().unwrap().value().flatten().iter().zip(grad.value().flatten()) {
+            approx::assert_abs_diff_eq!(*a, b, epsilon = 1e-12);
+        }
+    }
+    Ok(())
+}
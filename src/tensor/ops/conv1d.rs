@@ -0,0 +1,302 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use crate::tensor::index::IntTensor;
+use super::super::{Tensor, TensorValue};
+use super::{flat, route_grad};
+use anyhow::{bail, Result};
+
+/// Hyperparameters shared by the convolution ops, applied to every spatial
+/// dimension alike.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConvOptions {
+    pub stride: usize,
+    pub padding: usize,
+    pub dilation: usize,
+    /// Input and output channels are split into `groups` blocks, and each
+    /// output block only sees the matching input block.
+    pub groups: usize,
+}
+
+impl Default for ConvOptions {
+    fn default() -> Self {
+        ConvOptions { stride: 1, padding: 0, dilation: 1, groups: 1 }
+    }
+}
+
+impl ConvOptions {
+    /// Output size along a dimension of `size` for a kernel of `kernel` taps.
+    pub fn output_size(&self, size: usize, kernel: usize) -> Result<usize> {
+        if kernel == 0 || self.stride == 0 || self.dilation == 0 {
+            bail!("Invalid convolution: kernel {}, stride {}, dilation {}", kernel, self.stride, self.dilation);
+        }
+        let span = self.dilation * (kernel - 1) + 1;
+        if size + 2 * self.padding < span {
+            bail!("Kernel spanning {} does not fit input of size {} with padding {}", span, size, self.padding);
+        }
+        Ok((size + 2 * self.padding - span) / self.stride + 1)
+    }
+}
+
+impl<T: Float> Tensor<T> {
+    /// 1D convolution (cross-correlation) of a `[batch, in_channels, length]`
+    /// input with `[out_channels, in_channels / groups, kernel]` weights and
+    /// an optional `[out_channels]` bias, giving `[batch, out_channels, length_out]`.
+    #[track_caller]
+    pub fn conv1d(&self, weight: &Tensor<T>, bias: Option<&Tensor<T>>, options: ConvOptions) -> Result<Tensor<T>> {
+        let result_value = {
+            let x = self.data.borrow();
+            let w = weight.data.borrow();
+            let b = bias.map(|bias| bias.data.borrow());
+            forward(&x.value, &w.value, b.as_ref().map(|b| &b.value), options)?
+        };
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::Convolution1D(options);
+            res_data.dependencies = vec![self.clone(), weight.clone()];
+            res_data.dependencies.extend(bias.cloned());
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+struct Conv1dShape {
+    batch: usize,
+    in_channels: usize,
+    length: usize,
+    out_channels: usize,
+    kernel: usize,
+    length_out: usize,
+}
+
+impl Conv1dShape {
+    fn new(input: &[usize], weight: &[usize], options: ConvOptions) -> Result<Self> {
+        if input.len() != 3 || weight.len() != 3 {
+            bail!("conv1d expects a [batch, channels, length] input and [out, in / groups, kernel] weights, got {:?} and {:?}", input, weight);
+        }
+        let groups = options.groups;
+        if groups == 0 || !input[1].is_multiple_of(groups) || !weight[0].is_multiple_of(groups) {
+            bail!("conv1d channels ({} in, {} out) must be divisible by groups ({})", input[1], weight[0], groups);
+        }
+        if weight[1] != input[1] / groups {
+            bail!("conv1d weights of shape {:?} do not match {} input channels in {} groups", weight, input[1], groups);
+        }
+        Ok(Conv1dShape {
+            batch: input[0],
+            in_channels: input[1],
+            length: input[2],
+            out_channels: weight[0],
+            kernel: weight[2],
+            length_out: options.output_size(input[2], weight[2])?,
+        })
+    }
+
+    /// Calls `f(input_offset, weight_offset, output_offset)` for every product
+    /// the convolution sums, skipping taps that land in the padding.
+    fn for_each_tap(&self, options: ConvOptions, mut f: impl FnMut(usize, usize, usize)) {
+        let group_in = self.in_channels / options.groups;
+        let group_out = self.out_channels / options.groups;
+        for b in 0..self.batch {
+            for o in 0..self.out_channels {
+                let first_in = (o / group_out) * group_in;
+                for c in 0..group_in {
+                    let input_row = (b * self.in_channels + first_in + c) * self.length;
+                    let weight_row = (o * group_in + c) * self.kernel;
+                    let output_row = (b * self.out_channels + o) * self.length_out;
+                    for t in 0..self.length_out {
+                        for k in 0..self.kernel {
+                            let pos = (t * options.stride + k * options.dilation) as isize - options.padding as isize;
+                            if pos < 0 || pos as usize >= self.length {
+                                continue;
+                            }
+                            f(input_row + pos as usize, weight_row + k, output_row + t);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn output_shape(&self) -> [usize; 3] {
+        [self.batch, self.out_channels, self.length_out]
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, weight: &TensorValue<T>, bias: Option<&TensorValue<T>>, options: ConvOptions) -> Result<TensorValue<T>> {
+    let shape = Conv1dShape::new(&input.shape(), &weight.shape(), options)?;
+    let x = input.flatten();
+    let w = weight.flatten();
+
+    let mut out = vec![T::zero(); shape.output_shape().iter().product()];
+    shape.for_each_tap(options, |i, k, o| out[o] += x[i] * w[k]);
+    if let Some(bias) = bias {
+        let bias = bias.flatten();
+        if bias.len() != shape.out_channels {
+            bail!("conv1d bias has {} entries, expected one per output channel ({})", bias.len(), shape.out_channels);
+        }
+        for (row, chunk) in out.chunks_mut(shape.length_out).enumerate() {
+            let b = bias[row % shape.out_channels];
+            chunk.iter_mut().for_each(|y| *y += b);
+        }
+    }
+    Ok(TensorValue::from_flat(&shape.output_shape(), &out))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, options: ConvOptions) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let dependencies = &data.dependencies;
+    if dependencies.len() != 2 && dependencies.len() != 3 {
+        panic!("Convolution1D operation requires 2 or 3 dependencies");
+    }
+
+    let input = dependencies[0].value();
+    let weight = dependencies[1].value();
+    let shape = Conv1dShape::new(&input.shape(), &weight.shape(), options)?;
+    let x = input.flatten();
+    let w = weight.flatten();
+    let g = grad.flatten();
+
+    let mut dx = vec![T::zero(); x.len()];
+    let mut dw = vec![T::zero(); w.len()];
+    shape.for_each_tap(options, |i, k, o| {
+        dx[i] += g[o] * w[k];
+        dw[k] += g[o] * x[i];
+    });
+
+    let mut grads = vec![
+        TensorValue::from_flat(&input.shape(), &dx),
+        TensorValue::from_flat(&weight.shape(), &dw),
+    ];
+    if dependencies.len() == 3 {
+        let mut db = vec![T::zero(); shape.out_channels];
+        for (row, chunk) in g.chunks(shape.length_out).enumerate() {
+            db[row % shape.out_channels] += chunk.iter().copied().sum::<T>();
+        }
+        grads.push(TensorValue::Vector1D(db));
+    }
+    Ok(grads)
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, options: ConvOptions) -> Result<Vec<Tensor<T>>>{
+    let (input_shape, weight_shape) = {
+        let data = tensor.data.borrow();
+        (data.dependencies[0].shape(), data.dependencies[1].shape())
+    };
+    let shape = Conv1dShape::new(&input_shape, &weight_shape, options)?;
+    let mut taps = [vec![], vec![], vec![]];
+    shape.for_each_tap(options, |i, k, o| {
+        taps[0].push(i as i64);
+        taps[1].push(k as i64);
+        taps[2].push(o as i64);
+    });
+    taps_backward_graph(tensor, grad, taps, shape.length_out)
+}
+
+/// The convolution gradients built out of tensor ops, from the `(input,
+/// weight, output)` offsets of every product the forward pass sums.
+pub(super) fn taps_backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, taps: [Vec<i64>; 3], output_len: usize) -> Result<Vec<Tensor<T>>>{
+    let dependencies = tensor.data.borrow().dependencies.clone();
+    let [inputs, weights, outputs] = taps;
+    let input = &dependencies[0];
+    let weight = &dependencies[1];
+
+    let weight_taps = flat(weight)?.gather(0, &IntTensor::vector(weights.clone()))?;
+    let input_taps = flat(input)?.gather(0, &IntTensor::vector(inputs.clone()))?;
+    let mut grads = vec![
+        route_grad(grad, outputs.clone(), inputs, Some(weight_taps), &input.shape())?,
+        route_grad(grad, outputs, weights, Some(input_taps), &weight.shape())?,
+    ];
+    if let Some(bias) = dependencies.get(2) {
+        let channels = bias.shape()[0];
+        let numel: usize = grad.shape().iter().product();
+        let channel_of = (0..numel).map(|o| ((o / output_len) % channels) as i64).collect();
+        grads.push(route_grad(grad, (0..numel as i64).collect(), channel_of, None, &[channels])?);
+    }
+    Ok(grads)
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], options: ConvOptions) -> Result<TensorValue<T>> {
+    bilinear_jvp(tensor, tangents, |x, w, b| forward(x, w, b, options))
+}
+
+/// Tangent of a convolution computed by `forward`: it is bilinear in input
+/// and weight, so d(x * w) = dx * w + x * dw, plus the bias tangent.
+pub(super) fn bilinear_jvp<T: Float>(
+    tensor: &Tensor<T>,
+    tangents: &[TensorValue<T>],
+    forward: impl Fn(&TensorValue<T>, &TensorValue<T>, Option<&TensorValue<T>>) -> Result<TensorValue<T>>,
+) -> Result<TensorValue<T>> {
+    let data = tensor.data.borrow();
+    let input = data.dependencies[0].value();
+    let weight = data.dependencies[1].value();
+    let dx_w = forward(&tangents[0], &weight, tangents.get(2))?;
+    let x_dw = forward(&input, &tangents[1], None)?;
+    Ok(dx_w.zip_map(&x_dw, |a, b| a + b))
+}
+
+#[test]
+fn conv1d_matches_hand_computed_values() -> Result<()> {
+    // One sample, one channel, kernel [1, 0, -1]: a central difference.
+    let x = Tensor::tensor3d(vec![vec![vec![1.0, 2.0, 4.0, 8.0]]]);
+    let w = Tensor::tensor3d(vec![vec![vec![1.0, 0.0, -1.0]]]);
+    let b = Tensor::vector(vec![0.5]);
+    let y = x.conv1d(&w, Some(&b), ConvOptions { padding: 1, ..Default::default() })?;
+    assert_eq!(y.value().flatten(), vec![-1.5, -2.5, -5.5, 4.5]);
+
+    let strided = x.conv1d(&w, None, ConvOptions { stride: 2, dilation: 1, ..Default::default() })?;
+    assert_eq!(strided.value().flatten(), vec![-3.0]);
+    let dilated = x.conv1d(&Tensor::tensor3d(vec![vec![vec![1.0, -1.0]]]), None, ConvOptions { dilation: 3, ..Default::default() })?;
+    assert_eq!(dilated.value().flatten(), vec![-7.0]);
+
+    let long_kernel = Tensor::tensor3d(vec![vec![vec![1.0; 5]]]);
+    assert!(x.conv1d(&long_kernel, None, ConvOptions::default()).is_err());
+    assert!(x.conv1d(&w, None, ConvOptions { stride: 0, ..Default::default() }).is_err());
+    assert!(x.conv1d(&w, None, ConvOptions { groups: 2, ..Default::default() }).is_err());
+    assert!(x.conv1d(&w, Some(&Tensor::vector(vec![0.5, 0.5])), ConvOptions::default()).is_err());
+    assert!(Tensor::matrix(vec![vec![1.0, 2.0, 4.0]]).conv1d(&w, None, ConvOptions::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn conv1d_gradients_match_finite_differences() -> Result<()> {
+    let x = Tensor::tensor3d(vec![
+        vec![vec![0.1, -0.4, 0.8, 0.3, -0.2], vec![0.5, 0.2, -0.7, 0.9, 0.0], vec![-0.3, 0.6, 0.1, -0.8, 0.4], vec![0.2, 0.2, -0.5, 0.7, -0.1]],
+        vec![vec![0.7, 0.1, 0.0, -0.6, 0.3], vec![-0.2, 0.4, 0.9, 0.1, -0.5], vec![0.3, -0.9, 0.2, 0.5, 0.6], vec![-0.4, 0.0, 0.8, -0.3, 0.1]],
+    ]);
+    // Two groups of two input channels, each feeding one output channel.
+    let w = Tensor::tensor3d(vec![
+        vec![vec![0.3, -0.2], vec![0.5, 0.1]],
+        vec![vec![-0.4, 0.6], vec![0.2, -0.3]],
+    ]);
+    let b = Tensor::vector(vec![0.1, -0.2]);
+    let options = ConvOptions { stride: 2, padding: 1, dilation: 2, groups: 2 };
+
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].conv1d(&inputs[1], Some(&inputs[2]), options)?.tanh(),
+        &[x, w, b],
+        1e-6,
+        1e-5,
+    )
+}
+
+#[test]
+fn conv1d_second_order_gradients_match_finite_differences() -> Result<()> {
+    // The gradient of sum(conv1d(x, w)^2) differentiated again, through both x and w.
+    let x = Tensor::tensor3d(vec![vec![vec![0.3, -0.5, 0.8, 0.1], vec![-0.2, 0.6, 0.4, -0.7]]]);
+    let w = Tensor::tensor3d(vec![vec![vec![0.5, -0.3], vec![0.2, 0.7]], vec![vec![-0.6, 0.1], vec![0.4, 0.3]]]);
+    let b = Tensor::vector(vec![0.2, -0.1]);
+    let options = ConvOptions { padding: 1, ..Default::default() };
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].conv1d(&inputs[1], Some(&inputs[2]), options)?.pow(2.0)?.sum()?;
+            let grads = crate::tensor::autograd::grad(&[y], inputs)?;
+            grads[0].tanh()?.sum()?.add(&grads[1].pow(2.0)?.sum()?)?.add(&grads[2].sum()?)
+        },
+        &[x, w, b],
+        1e-6,
+        1e-5,
+    )
+}
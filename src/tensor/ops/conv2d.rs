@@ -0,0 +1,320 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use crate::tensor::utils::{matrix_multiply, transpose};
+use super::conv1d::{bilinear_jvp, taps_backward_graph, ConvOptions};
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// 2D convolution (cross-correlation) of a `[batch, in_channels, height, width]`
+    /// input with `[out_channels, in_channels / groups, kernel_h, kernel_w]`
+    /// weights and an optional `[out_channels]` bias, giving
+    /// `[batch, out_channels, height_out, width_out]`.
+    #[track_caller]
+    pub fn conv2d(&self, weight: &Tensor<T>, bias: Option<&Tensor<T>>, options: ConvOptions) -> Result<Tensor<T>> {
+        let result_value = {
+            let x = self.data.borrow();
+            let w = weight.data.borrow();
+            let b = bias.map(|bias| bias.data.borrow());
+            forward(&x.value, &w.value, b.as_ref().map(|b| &b.value), options)?
+        };
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::Convolution2D(options);
+            res_data.dependencies = vec![self.clone(), weight.clone()];
+            res_data.dependencies.extend(bias.cloned());
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+struct Conv2dShape {
+    batch: usize,
+    in_channels: usize,
+    height: usize,
+    width: usize,
+    out_channels: usize,
+    kernel_h: usize,
+    kernel_w: usize,
+    height_out: usize,
+    width_out: usize,
+    groups: usize,
+}
+
+impl Conv2dShape {
+    fn new(input: &[usize], weight: &[usize], options: ConvOptions) -> Result<Self> {
+        if input.len() != 4 || weight.len() != 4 {
+            bail!("conv2d expects a [batch, channels, height, width] input and [out, in / groups, kh, kw] weights, got {:?} and {:?}", input, weight);
+        }
+        let groups = options.groups;
+        if groups == 0 || !input[1].is_multiple_of(groups) || !weight[0].is_multiple_of(groups) {
+            bail!("conv2d channels ({} in, {} out) must be divisible by groups ({})", input[1], weight[0], groups);
+        }
+        if weight[1] != input[1] / groups {
+            bail!("conv2d weights of shape {:?} do not match {} input channels in {} groups", weight, input[1], groups);
+        }
+        Ok(Conv2dShape {
+            batch: input[0],
+            in_channels: input[1],
+            height: input[2],
+            width: input[3],
+            out_channels: weight[0],
+            kernel_h: weight[2],
+            kernel_w: weight[3],
+            height_out: options.output_size(input[2], weight[2])?,
+            width_out: options.output_size(input[3], weight[3])?,
+            groups,
+        })
+    }
+
+    fn group_in(&self) -> usize {
+        self.in_channels / self.groups
+    }
+
+    fn group_out(&self) -> usize {
+        self.out_channels / self.groups
+    }
+
+    fn output_len(&self) -> usize {
+        self.height_out * self.width_out
+    }
+
+    fn output_shape(&self) -> [usize; 4] {
+        [self.batch, self.out_channels, self.height_out, self.width_out]
+    }
+
+    /// Calls `f(row, col, input_offset)` for every entry of the im2col matrix
+    /// of sample `b` and group `g` that does not land in the padding. Rows run
+    /// over (channel, kernel_y, kernel_x) and columns over output positions.
+    fn for_each_patch(&self, options: ConvOptions, b: usize, g: usize, mut f: impl FnMut(usize, usize, usize)) {
+        let at = |out: usize, k: usize| (out * options.stride + k * options.dilation) as isize - options.padding as isize;
+        for c in 0..self.group_in() {
+            let channel = (b * self.in_channels + g * self.group_in() + c) * self.height * self.width;
+            for ky in 0..self.kernel_h {
+                for kx in 0..self.kernel_w {
+                    let row = (c * self.kernel_h + ky) * self.kernel_w + kx;
+                    for oy in 0..self.height_out {
+                        let y = at(oy, ky);
+                        if y < 0 || y as usize >= self.height {
+                            continue;
+                        }
+                        for ox in 0..self.width_out {
+                            let x = at(ox, kx);
+                            if x < 0 || x as usize >= self.width {
+                                continue;
+                            }
+                            f(row, oy * self.width_out + ox, channel + y as usize * self.width + x as usize);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls `f(input_offset, weight_offset, output_offset)` for every product
+    /// the convolution sums, skipping taps that land in the padding.
+    fn for_each_tap(&self, options: ConvOptions, mut f: impl FnMut(usize, usize, usize)) {
+        let row_len = self.group_in() * self.kernel_h * self.kernel_w;
+        for b in 0..self.batch {
+            for g in 0..self.groups {
+                self.for_each_patch(options, b, g, |row, col, i| {
+                    for o in g * self.group_out()..(g + 1) * self.group_out() {
+                        f(i, o * row_len + row, (b * self.out_channels + o) * self.output_len() + col);
+                    }
+                });
+            }
+        }
+    }
+
+    fn im2col<T: Float>(&self, x: &[T], options: ConvOptions, b: usize, g: usize) -> Vec<Vec<T>> {
+        let mut cols = vec![vec![T::zero(); self.output_len()]; self.group_in() * self.kernel_h * self.kernel_w];
+        self.for_each_patch(options, b, g, |row, col, i| cols[row][col] = x[i]);
+        cols
+    }
+
+    /// The weights of group `g` as a `[group_out, group_in * kh * kw]` matrix.
+    fn group_weights<T: Float>(&self, w: &[T], g: usize) -> Vec<Vec<T>> {
+        let row_len = self.group_in() * self.kernel_h * self.kernel_w;
+        w.chunks(row_len).skip(g * self.group_out()).take(self.group_out()).map(|row| row.to_vec()).collect()
+    }
+
+    /// Rows of `values` (laid out like the output) for sample `b` and group `g`.
+    fn group_output<T: Float>(&self, values: &[T], b: usize, g: usize) -> Vec<Vec<T>> {
+        let first = b * self.out_channels + g * self.group_out();
+        values.chunks(self.output_len()).skip(first).take(self.group_out()).map(|row| row.to_vec()).collect()
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, weight: &TensorValue<T>, bias: Option<&TensorValue<T>>, options: ConvOptions) -> Result<TensorValue<T>> {
+    let shape = Conv2dShape::new(&input.shape(), &weight.shape(), options)?;
+    let x = input.flatten();
+    let w = weight.flatten();
+    let bias = bias.map(|bias| bias.flatten());
+    if let Some(bias) = &bias {
+        if bias.len() != shape.out_channels {
+            bail!("conv2d bias has {} entries, expected one per output channel ({})", bias.len(), shape.out_channels);
+        }
+    }
+
+    let mut out = Vec::with_capacity(shape.output_shape().iter().product());
+    for b in 0..shape.batch {
+        for g in 0..shape.groups {
+            let rows = matrix_multiply(&shape.group_weights(&w, g), &shape.im2col(&x, options, b, g));
+            for (o, row) in rows.into_iter().enumerate() {
+                let offset = bias.as_ref().map_or(T::zero(), |bias| bias[g * shape.group_out() + o]);
+                out.extend(row.into_iter().map(|y| y + offset));
+            }
+        }
+    }
+    Ok(TensorValue::from_flat(&shape.output_shape(), &out))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, options: ConvOptions) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let dependencies = &data.dependencies;
+    if dependencies.len() != 2 && dependencies.len() != 3 {
+        panic!("Convolution2D operation requires 2 or 3 dependencies");
+    }
+
+    let input = dependencies[0].value();
+    let weight = dependencies[1].value();
+    let shape = Conv2dShape::new(&input.shape(), &weight.shape(), options)?;
+    let x = input.flatten();
+    let w = weight.flatten();
+    let g_out = grad.flatten();
+
+    let mut dx = vec![T::zero(); x.len()];
+    let mut dw = vec![T::zero(); w.len()];
+    let group_weights_t: Vec<Vec<Vec<T>>> = (0..shape.groups).map(|g| transpose(&shape.group_weights(&w, g))).collect();
+    let row_len = shape.group_in() * shape.kernel_h * shape.kernel_w;
+    for b in 0..shape.batch {
+        for (g, weights_t) in group_weights_t.iter().enumerate() {
+            let grad_rows = shape.group_output(&g_out, b, g);
+            let cols = shape.im2col(&x, options, b, g);
+
+            // dW = dY · colsᵀ
+            let dw_group = matrix_multiply(&grad_rows, &transpose(&cols));
+            let dw_offset = g * shape.group_out() * row_len;
+            for (acc, d) in dw[dw_offset..].iter_mut().zip(dw_group.iter().flatten()) {
+                *acc += *d;
+            }
+
+            // dX = col2im(Wᵀ · dY)
+            let dcols = matrix_multiply(weights_t, &grad_rows);
+            shape.for_each_patch(options, b, g, |row, col, i| dx[i] += dcols[row][col]);
+        }
+    }
+
+    let mut grads = vec![
+        TensorValue::from_flat(&input.shape(), &dx),
+        TensorValue::from_flat(&weight.shape(), &dw),
+    ];
+    if dependencies.len() == 3 {
+        let mut db = vec![T::zero(); shape.out_channels];
+        for (row, chunk) in g_out.chunks(shape.output_len()).enumerate() {
+            db[row % shape.out_channels] += chunk.iter().copied().sum::<T>();
+        }
+        grads.push(TensorValue::Vector1D(db));
+    }
+    Ok(grads)
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, options: ConvOptions) -> Result<Vec<Tensor<T>>>{
+    let (input_shape, weight_shape) = {
+        let data = tensor.data.borrow();
+        (data.dependencies[0].shape(), data.dependencies[1].shape())
+    };
+    let shape = Conv2dShape::new(&input_shape, &weight_shape, options)?;
+    let mut taps = [vec![], vec![], vec![]];
+    shape.for_each_tap(options, |i, k, o| {
+        taps[0].push(i as i64);
+        taps[1].push(k as i64);
+        taps[2].push(o as i64);
+    });
+    taps_backward_graph(tensor, grad, taps, shape.output_len())
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], options: ConvOptions) -> Result<TensorValue<T>> {
+    bilinear_jvp(tensor, tangents, |x, w, b| forward(x, w, b, options))
+}
+
+#[test]
+fn conv2d_matches_direct_convolution() -> Result<()> {
+    // A 3x3 image convolved with a 2x2 kernel, checked against the textbook sum.
+    let image = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+    let kernel = [[1.0, -1.0], [0.5, 2.0]];
+    let x = Tensor::tensor4d(vec![vec![image.iter().map(|row| row.to_vec()).collect()]]);
+    let w = Tensor::tensor4d(vec![vec![kernel.iter().map(|row| row.to_vec()).collect()]]);
+    let b = Tensor::vector(vec![0.25]);
+
+    let y = x.conv2d(&w, Some(&b), ConvOptions::default())?;
+    assert_eq!(y.shape(), vec![1, 1, 2, 2]);
+    let mut expected = vec![];
+    for i in 0..2 {
+        for j in 0..2 {
+            let mut acc = 0.25;
+            for (ki, kernel_row) in kernel.iter().enumerate() {
+                for (kj, k) in kernel_row.iter().enumerate() {
+                    acc += image[i + ki][j + kj] * k;
+                }
+            }
+            expected.push(acc);
+        }
+    }
+    assert_eq!(y.value().flatten(), expected);
+
+    let padded = x.conv2d(&w, None, ConvOptions { stride: 2, padding: 1, ..Default::default() })?;
+    assert_eq!(padded.shape(), vec![1, 1, 2, 2]);
+    assert_eq!(padded.value().flatten(), vec![2.0, 7.0, 10.0, 21.0]);
+
+    let wide = Tensor::tensor4d(vec![vec![vec![vec![1.0; 4]; 2]]]);
+    assert!(x.conv2d(&wide, None, ConvOptions::default()).is_err());
+    assert!(x.conv2d(&w, Some(&Tensor::vector(vec![0.25; 2])), ConvOptions::default()).is_err());
+    assert!(x.conv2d(&Tensor::tensor3d(vec![kernel.iter().map(|row| row.to_vec()).collect()]), None, ConvOptions::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn conv2d_gradients_match_finite_differences() -> Result<()> {
+    let value = |shape: &[usize], offset: f64| {
+        let len = shape.iter().product::<usize>();
+        let data: Vec<f64> = (0..len).map(|i| (i as f64 * 0.37 + offset).sin()).collect();
+        Tensor::from_value(TensorValue::from_flat(shape, &data))
+    };
+    let x = value(&[2, 4, 4, 5], 0.1);
+    // Two groups of two input channels, each feeding two output channels.
+    let w = value(&[4, 2, 2, 3], 0.7);
+    let b = value(&[4], 1.3);
+    let options = ConvOptions { stride: 2, padding: 1, dilation: 2, groups: 2 };
+
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].conv2d(&inputs[1], Some(&inputs[2]), options)?.tanh(),
+        &[x, w, b],
+        1e-6,
+        1e-5,
+    )
+}
+
+#[test]
+fn conv2d_second_order_gradients_match_finite_differences() -> Result<()> {
+    let value = |shape: &[usize], offset: f64| {
+        let len = shape.iter().product::<usize>();
+        let data: Vec<f64> = (0..len).map(|i| (i as f64 * 0.53 + offset).sin()).collect();
+        Tensor::from_value(TensorValue::from_flat(shape, &data))
+    };
+    let options = ConvOptions { stride: 2, padding: 1, dilation: 1, groups: 2 };
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].conv2d(&inputs[1], Some(&inputs[2]), options)?.pow(2.0)?.sum()?;
+            let grads = crate::tensor::autograd::grad(&[y], inputs)?;
+            grads[0].tanh()?.sum()?.add(&grads[1].pow(2.0)?.sum()?)?.add(&grads[2].sum()?)
+        },
+        &[value(&[1, 4, 3, 4], 0.2), value(&[2, 2, 2, 2], 0.9), value(&[2], 1.7)],
+        1e-6,
+        1e-5,
+    )
+}
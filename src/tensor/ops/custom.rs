@@ -0,0 +1,118 @@
+use crate::tensor::dtype::Float;
+use std::rc::Rc;
+use crate::tensor::function::Function;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn custom(function: Rc<dyn Function<T>>, inputs: &[Tensor<T>]) -> Result<Tensor<T>> {
+        let input_values: Vec<TensorValue<T>> = inputs.iter()
+            .map(|input| input.value())
+            .collect();
+        let result_value = function.forward(&input_values)?;
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::Custom(function);
+            res_data.dependencies = inputs.to_vec();
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let function = match &data.operation {
+        Operation::Custom(function) => function,
+        _ => panic!("Custom backward called on {:?}", data.operation),
+    };
+
+    let input_values: Vec<TensorValue<T>> = data.dependencies.iter()
+        .map(|dep| dep.value())
+        .collect();
+    let grads = function.backward(&input_values, &data.value, grad)?;
+    if grads.len() != data.dependencies.len() {
+        bail!(
+            "{:?} returned {} gradients for {} inputs",
+            function, grads.len(), data.dependencies.len()
+        );
+    }
+    Ok(grads)
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, function: &Rc<dyn Function<T>>) -> Result<Vec<Tensor<T>>>{
+    let dependencies = tensor.data.borrow().dependencies.clone();
+    if let Some(grads) = function.backward_graph(&dependencies, tensor, grad)? {
+        return Ok(grads);
+    }
+
+    let input_values: Vec<TensorValue<T>> = dependencies.iter()
+        .map(|dep| dep.value())
+        .collect();
+    let output_value = tensor.value();
+    let grad_value = grad.value();
+    let grads = function.backward(&input_values, &output_value, &grad_value)?;
+    Ok(grads.into_iter().map(Tensor::from_value).collect())
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], function: &Rc<dyn Function<T>>) -> Result<TensorValue<T>>{
+    let data = tensor.data.borrow();
+    let input_values: Vec<TensorValue<T>> = data.dependencies.iter()
+        .map(|dep| dep.value())
+        .collect();
+    function.jvp(&input_values, &data.value, tangents)
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+struct Hypot;
+
+#[cfg(test)]
+impl Function for Hypot {
+    fn forward(&self, inputs: &[TensorValue]) -> Result<TensorValue> {
+        Ok(inputs[0].zip_map(&inputs[1], |a, b| a.hypot(b)))
+    }
+
+    fn backward(&self, inputs: &[TensorValue], output: &TensorValue, grad: &TensorValue) -> Result<Vec<TensorValue>> {
+        let scale = grad.zip_map(output, |g, h| g / h);
+        Ok(vec![
+            scale.zip_map(&inputs[0], |s, a| s * a),
+            scale.zip_map(&inputs[1], |s, b| s * b),
+        ])
+    }
+}
+
+#[test]
+fn custom_function_backward_works() -> Result<()>{
+    let a = Tensor::vector(vec![3.0, 5.0]);
+    let b = Tensor::vector(vec![4.0, 12.0]);
+    let c = Tensor::custom(Rc::new(Hypot), &[a.clone(), b.clone()])?;
+    assert_eq!(c.to_vec()?, vec![5.0, 13.0]);
+
+    c.sum()?.backward()?;
+    let a_grad = match &a.grad().unwrap().value() {
+        TensorValue::Vector1D(v) => v.clone(),
+        _ => panic!("Error!")
+    };
+    let b_grad = match &b.grad().unwrap().value() {
+        TensorValue::Vector1D(v) => v.clone(),
+        _ => panic!("Error!")
+    };
+    let expected = [(3.0 / 5.0, 4.0 / 5.0), (5.0 / 13.0, 12.0 / 13.0)];
+    for ((a_actual, b_actual), (a_expected, b_expected)) in a_grad.iter().zip(&b_grad).zip(expected) {
+        approx::assert_abs_diff_eq!(*a_actual, a_expected, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(*b_actual, b_expected, epsilon = 1e-12);
+    }
+
+    crate::tensor::gradcheck::gradcheck(
+        |x| Tensor::custom(Rc::new(Hypot), x)?.tanh(),
+        &[Tensor::matrix(vec![vec![0.3, -1.2]]), Tensor::matrix(vec![vec![0.7, 0.4]])],
+        1e-6,
+        1e-5,
+    )
+}
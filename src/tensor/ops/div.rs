@@ -1,54 +1,18 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
+use super::{reduce_grad, shape_of};
 use itertools::izip;
 
-impl Tensor {
-    pub fn div(&self, other: &Tensor) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn div(&self, other: &Tensor<T>) -> Result<Tensor<T>> {
         let a = self.data.borrow();
         let b = other.data.borrow();
 
-        let result_value = match (&a.value, &b.value) {
-
-            (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
-                TensorValue::Scalar(a_val / b_val)
-            }
-
-            (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
-                assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
-                TensorValue::Vector1D(
-                    a_vec.iter().zip(b_vec).map(|(a, b)| a / b).collect()
-                )
-            }
-
-            (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
-                assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
-                assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
-                TensorValue::Matrix2D(
-                    a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
-                        a_row.iter().zip(b_row).map(|(a, b)| a / b).collect()
-                    }).collect()
-                )
-            }
-
-            (TensorValue::Scalar(s), TensorValue::Vector1D(v)) => {
-                TensorValue::Vector1D(v.iter().map(|x| s / x).collect())
-            }
-            (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
-                TensorValue::Vector1D(v.iter().map(|x| x / s).collect())
-            }
-            (TensorValue::Scalar(s), TensorValue::Matrix2D(m)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| s / x).collect()).collect()
-                )
-            }
-            (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| x / s).collect()).collect()
-                )
-            }
-            _ => panic!("Invalid div operation between types"),
-        };
+        let result_value = forward(&a.value, &b.value);
 
         let result = Self::from_value(result_value);
         {
@@ -56,11 +20,69 @@ impl Tensor {
             res_data.operation = Operation::Div;
             res_data.dependencies = vec![self.clone(), other.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(a: &TensorValue<T>, b: &TensorValue<T>) -> TensorValue<T> {
+    match (a, b) {
+
+        (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
+            TensorValue::Scalar(*a_val / *b_val)
+        }
+
+        (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
+            assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
+            TensorValue::Vector1D(
+                a_vec.iter().zip(b_vec).map(|(a, b)| *a / *b).collect()
+            )
+        }
+
+        (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
+            assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
+            assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
+            TensorValue::Matrix2D(
+                a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
+                    a_row.iter().zip(b_row).map(|(a, b)| *a / *b).collect()
+                }).collect()
+            )
+        }
+
+        (TensorValue::Scalar(s), TensorValue::Vector1D(v)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *s / *x).collect())
+        }
+        (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *x / *s).collect())
+        }
+        (TensorValue::Scalar(s), TensorValue::Matrix2D(m)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *s / *x).collect()).collect()
+            )
+        }
+        (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *x / *s).collect()).collect()
+            )
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            assert!(a.same_shape(b), "Tensor shape mismatch");
+            a.zip_map(b, |a, b| a / b)
+        }
+        (TensorValue::Scalar(_), TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_)) | (TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_), TensorValue::Scalar(_)) => {
+            a.zip_map(b, |a, b| a / b)
+        }
+        _ => panic!("Invalid div operation between types"),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, a: &TensorValue<T>, b: &TensorValue<T>) {
+    if !out.zip_map_into(a, b, |a, b| a / b) {
+        *out = forward(a, b);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 2 {
@@ -76,40 +98,38 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
         (a_data.value.clone(), b_data.value.clone())
     };
 
-    match (&data.grad, &a_val, &b_val) {
+    let (a_grad, b_grad) = match (grad, &a_val, &b_val) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
             let b_sq = b_val.powi(2);
-            a.data.borrow_mut().add_grad_scalar(grad / b_val)?;
-            b.data.borrow_mut().add_grad_scalar(-grad * a_val / b_sq)?;
+            (TensorValue::Scalar(*grad / *b_val), TensorValue::Scalar(-*grad * *a_val / b_sq))
         },
         (TensorValue::Vector1D(v_grad), TensorValue::Vector1D(v_a_val), TensorValue::Vector1D(v_b_val)) => {
-            let a_grad = v_grad.iter().zip(v_b_val).map(|(g, b_val_)| g / b_val_).collect();
+            let a_grad = v_grad.iter().zip(v_b_val).map(|(g, b_val_)| *g / *b_val_).collect();
             let a_grad = TensorValue::Vector1D(a_grad);
 
 
             // Many Thanks to bluss. Cite: https://stackoverflow.com/questions/29669287/how-can-i-zip-more-than-two-iterators
             // Where I found how to zip more than two iterators in rust.
-            let mut b_grad: Vec<f64> = vec![];
+            let mut b_grad: Vec<T> = vec![];
             for (a_val_, b_val_, g) in izip!(v_a_val, v_b_val, v_grad) {
-                b_grad.push(-g * a_val_ / b_val_.powi(2));
+                b_grad.push(-*g * *a_val_ / b_val_.powi(2));
             }
             let b_grad = TensorValue::Vector1D(b_grad);
 
-            a.data.borrow_mut().add_grad(a_grad)?;
-            b.data.borrow_mut().add_grad(b_grad)?;
+            (a_grad, b_grad)
         }
 
         (TensorValue::Matrix2D(m_grad), TensorValue::Matrix2D(m_a_val), TensorValue::Matrix2D(m_b_val)) => {
             let a_grad = m_grad.iter().zip(m_b_val).map(|(v_grad, v_b_val)|
-                v_grad.iter().zip(v_b_val).map(|(g, b_val_)| g / b_val_).collect()
+                v_grad.iter().zip(v_b_val).map(|(g, b_val_)| *g / *b_val_).collect()
             ).collect();
 
-            let mut b_grad: Vec<Vec<f64>> = vec![];
+            let mut b_grad: Vec<Vec<T>> = vec![];
 
             for(v_a_val, v_b_val, v_grad) in izip!(m_a_val, m_b_val, m_grad) {
-                let mut b_grad_row: Vec<f64> = vec![];
+                let mut b_grad_row: Vec<T> = vec![];
                 for(a_val_, b_val_, g) in izip!(v_a_val, v_b_val, v_grad) {
-                    b_grad_row.push(-g * a_val_ / b_val_.powi(2));
+                    b_grad_row.push(-*g * *a_val_ / b_val_.powi(2));
                 }
                 b_grad.push(b_grad_row);
             }
@@ -117,13 +137,42 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
             let a_grad = TensorValue::Matrix2D(a_grad);
             let b_grad = TensorValue::Matrix2D(b_grad);
 
-            a.data.borrow_mut().add_grad(a_grad)?;
-            b.data.borrow_mut().add_grad(b_grad)?;
+            (a_grad, b_grad)
+        }
+        // Broadcast operands: the gradient is summed back to the operand's shape
+        // when it is accumulated.
+        _ => {
+            let a_grad = grad.zip_map(&b_val, |g, b_| g / b_);
+            let b_grad = grad.zip_map(&a_val, |g, a_| -g * a_)
+                .zip_map(&b_val, |x, b_| x / b_.powi(2));
+            (a_grad, b_grad)
         }
-        // TODO: 其他类型处理类似，需要实现对应的梯度计算
-        _ => unimplemented!("Div backward for non-scalar not implemented"),
     };
-    Ok(())
+    Ok(vec![a_grad, b_grad])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let a = &data.dependencies[0];
+    let b = &data.dependencies[1];
+
+    // d(a / b) / db = -a / b^2
+    let b_grad = grad.multiply(a)?
+        .div(&b.pow(T::from_f64(2.0))?)?
+        .multiply(&Tensor::scalar(-T::one()))?;
+    Ok(vec![
+        reduce_grad(grad.div(b)?, &shape_of(a))?,
+        reduce_grad(b_grad, &shape_of(b))?,
+    ])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    // d(a / b) = (da - (a / b) * db) / b
+    let data = tensor.data.borrow();
+    let b_data = data.dependencies[1].data.borrow();
+    let b = &b_data.value;
+    let y_db = data.value.zip_map(&tangents[1], |y, t| y * t);
+    tangents[0].zip_map(&y_db, |t, u| t - u).zip_map(b, |t, b| t / b)
 }
 
 #[test]
@@ -134,11 +183,11 @@ fn test_div_backward() -> Result<()>{
     let output = a.div(&b)?.sum()?;
     output.backward()?;
 
-    let a_grad_vec = match &a.data.borrow().grad {
+    let a_grad_vec = match &a.grad().unwrap().value() {
         TensorValue::Vector1D(v) => v.clone(),
         _ => panic!()
     };
-    let b_grad_vec = match &b.data.borrow().grad {
+    let b_grad_vec = match &b.grad().unwrap().value() {
         TensorValue::Vector1D(v) => v.clone(),
         _ => panic!()
     };
@@ -0,0 +1,103 @@
+use crate::tensor::dtype::Float;
+use std::rc::Rc;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::index::{check_gather_index, gather_offsets, IntTensor};
+use crate::tensor::operation::Operation;
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+use super::shape_of;
+
+impl<T: Float> Tensor<T> {
+    /// Picks elements along `dim`: the output has the shape of `index`, and
+    /// for a matrix and `dim == 1`, `out[i][j] = self[i][index[i][j]]`.
+    #[track_caller]
+    pub fn gather(&self, dim: usize, index: &IntTensor) -> Result<Tensor<T>> {
+        let data = self.data.borrow();
+        check_gather_index(&data.value.shape(), dim, index)?;
+        let result_value = forward(&data.value, dim, index);
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::Gather(dim, Rc::new(index.clone()));
+            res_data.dependencies = vec![self.clone()];
+        }
+
+        check_forward(&result)?;
+        Ok(result)
+    }
+
+    /// Selects whole entries along `dim`: the elements of a vector, or the
+    /// rows (`dim == 0`) or columns (`dim == 1`) of a matrix.
+    #[track_caller]
+    pub fn index_select(&self, dim: usize, indices: &[i64]) -> Result<Tensor<T>> {
+        let shape = self.shape();
+        let index = match (shape.len(), dim) {
+            (1, 0) => IntTensor::vector(indices.to_vec()),
+            (2, 0) => IntTensor::matrix(indices.iter().map(|&i| vec![i; shape[1]]).collect()),
+            (2, 1) => IntTensor::matrix(vec![indices.to_vec(); shape[0]]),
+            _ => bail!("index_select along dim {} is not supported for shape {:?}", dim, shape),
+        };
+        self.gather(dim, &index)
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, dim: usize, index: &IntTensor) -> TensorValue<T> {
+    let values = input.flatten();
+    let gathered: Vec<T> = gather_offsets(&input.shape(), dim, index).into_iter()
+        .map(|offset| values[offset])
+        .collect();
+    TensorValue::from_flat(&index.shape(), &gathered)
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, dim: usize, index: &IntTensor) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let dependencies = &data.dependencies;
+    if dependencies.len() != 1 {
+        panic!("Gather operation requires exactly 1 dependency");
+    }
+
+    // Every picked element sends its gradient back to where it came from.
+    let input = TensorValue::full(&shape_of(&dependencies[0]), T::zero());
+    Ok(vec![super::scatter_add::forward(&input, grad, dim, index)])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, dim: usize, index: &IntTensor) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let zeros = Tensor::from_value(TensorValue::full(&shape_of(&data.dependencies[0]), T::zero()));
+    Ok(vec![zeros.scatter_add(dim, index, grad)?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], dim: usize, index: &IntTensor) -> TensorValue<T> {
+    forward(&tangents[0], dim, index)
+}
+
+#[test]
+fn gather_backward_works() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+    let picked = x.gather(1, &IntTensor::matrix(vec![vec![2, 2], vec![0, 1]]))?;
+    assert_eq!(picked.to_matrix()?, vec![vec![3.0, 3.0], vec![4.0, 5.0]]);
+
+    picked.sum()?.backward()?;
+    assert_eq!(x.grad().unwrap().to_matrix()?, vec![vec![0.0, 0.0, 2.0], vec![1.0, 1.0, 0.0]]);
+
+    crate::tensor::gradcheck::gradcheck(
+        |x| x[0].gather(0, &IntTensor::matrix(vec![vec![1, 0, 1]]))?.pow(3.0),
+        &[x],
+        1e-6,
+        1e-5,
+    )
+}
+
+#[test]
+fn index_select_picks_rows_and_columns() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+    assert_eq!(x.index_select(0, &[1, 1])?.to_matrix()?, vec![vec![4.0, 5.0, 6.0]; 2]);
+    assert_eq!(x.index_select(1, &[2, 0])?.to_matrix()?, vec![vec![3.0, 1.0], vec![6.0, 4.0]]);
+    assert_eq!(Tensor::vector(vec![7.0, 8.0]).index_select(0, &[1])?.to_vec()?, vec![8.0]);
+
+    let error = x.index_select(1, &[3]).unwrap_err().to_string();
+    assert_eq!(error, "Index 3 is out of range for dim 1 of size 3");
+    assert!(x.index_select(2, &[0]).is_err());
+    assert!(Tensor::scalar(1.0).index_select(0, &[0]).is_err());
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::norm::{inv_std, moments, normalize, normalize_backward, normalize_backward_graph, normalize_jvp};
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// Splits the channels (dimension 1) of a `[batch, channels, ...]` input
+    /// into `groups` blocks and normalizes each block of each sample, over its
+    /// channels and any spatial dimensions, to zero mean and unit variance;
+    /// then scales and shifts every channel by `weight` and `bias`, both `[channels]`.
+    #[track_caller]
+    pub fn group_norm(&self, groups: usize, weight: &Tensor<T>, bias: &Tensor<T>, eps: T) -> Result<Tensor<T>> {
+        let result_value = forward(&self.value(), &weight.value(), &bias.value(), groups, eps)?;
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::GroupNorm(groups, eps);
+            res_data.dependencies = vec![self.clone(), weight.clone(), bias.clone()];
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+/// Checks a `group_norm` input shape against `groups` and the lengths of the
+/// affine parameters.
+fn check_shapes(shape: &[usize], groups: usize, weight: usize, bias: usize) -> Result<()> {
+    if shape.len() < 2 {
+        bail!("group_norm expects a [batch, channels, ...] input, got shape {:?}", shape);
+    }
+    let channels = shape[1];
+    if groups == 0 || !channels.is_multiple_of(groups) {
+        bail!("group_norm channels ({}) must be divisible by groups ({})", channels, groups);
+    }
+    if weight != channels || bias != channels {
+        bail!("group_norm weight and bias must have one entry per channel ({}), got {} and {}", channels, weight, bias);
+    }
+    Ok(())
+}
+
+/// The number of statistics groups, and maps from an element to its group and
+/// to its channel, for a shape that passed `check_shapes`.
+fn group_layout(shape: &[usize], groups: usize) -> (usize, impl Fn(usize) -> usize, impl Fn(usize) -> usize) {
+    let channels = shape[1];
+    let spatial: usize = shape[2..].iter().product();
+    let per_group = channels / groups;
+    let channel_of = move |i: usize| (i / spatial) % channels;
+    let group_of = move |i: usize| (i / (channels * spatial)) * groups + channel_of(i) / per_group;
+    (shape[0] * groups, group_of, channel_of)
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, weight: &TensorValue<T>, bias: &TensorValue<T>, groups: usize, eps: T) -> Result<TensorValue<T>> {
+    let shape = input.shape();
+    let (weight, bias) = (weight.flatten(), bias.flatten());
+    check_shapes(&shape, groups, weight.len(), bias.len())?;
+    let (count, group_of, channel_of) = group_layout(&shape, groups);
+    let x = input.flatten();
+
+    let stats = inv_std(&moments(&x, count, &group_of), eps);
+    Ok(TensorValue::from_flat(&shape, &normalize(&x, &stats, group_of, &weight, &bias, channel_of)))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, groups: usize, eps: T) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    if data.dependencies.len() != 3 {
+        panic!("GroupNorm operation requires exactly 3 dependencies");
+    }
+
+    let input = data.dependencies[0].value();
+    let shape = input.shape();
+    let (count, group_of, channel_of) = group_layout(&shape, groups);
+    let x = input.flatten();
+
+    let stats = inv_std(&moments(&x, count, &group_of), eps);
+    let weight = data.dependencies[1].value().flatten();
+    let (dx, d_weight, d_bias) = normalize_backward(&x, &grad.flatten(), &stats, group_of, &weight, channel_of, true);
+    Ok(vec![
+        TensorValue::from_flat(&shape, &dx),
+        TensorValue::Vector1D(d_weight),
+        TensorValue::Vector1D(d_bias),
+    ])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, groups: usize, eps: T) -> Result<Vec<Tensor<T>>>{
+    let shape = tensor.data.borrow().dependencies[0].shape();
+    let (count, group_of, channel_of) = group_layout(&shape, groups);
+    normalize_backward_graph(tensor, grad, eps, count, group_of, channel_of, None)
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], groups: usize, eps: T) -> Result<TensorValue<T>> {
+    let data = tensor.data.borrow();
+    let input = data.dependencies[0].value();
+    let shape = input.shape();
+    let (count, group_of, channel_of) = group_layout(&shape, groups);
+    let x = input.flatten();
+
+    let stats = inv_std(&moments(&x, count, &group_of), eps);
+    let weight = data.dependencies[1].value().flatten();
+    let tangents: Vec<Vec<T>> = tangents.iter().map(|t| t.flatten()).collect();
+    let out = normalize_jvp(&x, &stats, group_of, &weight, channel_of, [&tangents[0], &tangents[1], &tangents[2]], true);
+    Ok(TensorValue::from_flat(&shape, &out))
+}
+
+#[test]
+fn group_norm_gradients_match_finite_differences() -> Result<()> {
+    let data: Vec<f64> = (0..2 * 4 * 3).map(|i| (i as f64 * 0.47).sin() * 2.0 + 0.1 * i as f64).collect();
+    let inputs = [
+        Tensor::from_flat(&[2, 4, 3], &data),
+        Tensor::vector(vec![0.8, -1.3, 1.1, 0.5]),
+        Tensor::vector(vec![0.1, 0.4, -0.2, 0.0]),
+    ];
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].group_norm(2, &inputs[1], &inputs[2], 1e-5)?.tanh(),
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+
+    assert!(inputs[0].group_norm(3, &inputs[1], &inputs[2], 1e-5).is_err());
+    assert!(inputs[0].group_norm(0, &inputs[1], &inputs[2], 1e-5).is_err());
+    assert!(inputs[0].group_norm(2, &inputs[1].index_select(0, &[0, 1])?, &inputs[2], 1e-5).is_err());
+
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].group_norm(2, &inputs[1], &inputs[2], 1e-5)?.tanh()?.sum()?;
+            let grads = crate::tensor::autograd::grad(&[y], inputs)?;
+            grads[0].pow(2.0)?.sum()?.add(&grads[1].tanh()?.sum()?)?.add(&grads[2].pow(2.0)?.sum()?)
+        },
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::norm::{inv_std, moments, normalize, normalize_backward, normalize_backward_graph, normalize_jvp};
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// Normalizes every sample over its trailing dimensions, those of the
+    /// shape of `weight` and `bias`, to zero mean and unit variance, then
+    /// scales and shifts it elementwise by `weight` and `bias`.
+    #[track_caller]
+    pub fn layer_norm(&self, weight: &Tensor<T>, bias: &Tensor<T>, eps: T) -> Result<Tensor<T>> {
+        let result_value = forward(&self.value(), &weight.value(), &bias.value(), eps)?;
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::LayerNorm(eps);
+            res_data.dependencies = vec![self.clone(), weight.clone(), bias.clone()];
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+/// Number of samples and the number of elements normalized together in each.
+fn sample_layout(shape: &[usize], normalized_shape: &[usize]) -> Result<(usize, usize)> {
+    if normalized_shape.is_empty() || !shape.ends_with(normalized_shape) {
+        bail!("layer_norm over {:?} does not match the trailing dimensions of an input of shape {:?}", normalized_shape, shape);
+    }
+    let normalized: usize = normalized_shape.iter().product();
+    Ok((shape.iter().product::<usize>() / normalized, normalized))
+}
+
+fn statistics<T: Float>(x: &[T], samples: usize, normalized: usize, eps: T) -> Vec<(T, T)> {
+    inv_std(&moments(x, samples, |i| i / normalized), eps)
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, weight: &TensorValue<T>, bias: &TensorValue<T>, eps: T) -> Result<TensorValue<T>> {
+    let shape = input.shape();
+    if !weight.same_shape(bias) {
+        bail!("layer_norm weight {:?} and bias {:?} must have the same shape", weight.shape(), bias.shape());
+    }
+    let (samples, normalized) = sample_layout(&shape, &weight.shape())?;
+    let x = input.flatten();
+
+    let stats = statistics(&x, samples, normalized, eps);
+    let out = normalize(&x, &stats, |i| i / normalized, &weight.flatten(), &bias.flatten(), |i| i % normalized);
+    Ok(TensorValue::from_flat(&shape, &out))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, eps: T) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    if data.dependencies.len() != 3 {
+        panic!("LayerNorm operation requires exactly 3 dependencies");
+    }
+
+    let input = data.dependencies[0].value();
+    let weight = data.dependencies[1].value();
+    let shape = input.shape();
+    let (samples, normalized) = sample_layout(&shape, &weight.shape())?;
+    let x = input.flatten();
+
+    let stats = statistics(&x, samples, normalized, eps);
+    let (dx, d_weight, d_bias) = normalize_backward(&x, &grad.flatten(), &stats, |i| i / normalized, &weight.flatten(), |i| i % normalized, true);
+    Ok(vec![
+        TensorValue::from_flat(&shape, &dx),
+        TensorValue::from_flat(&weight.shape(), &d_weight),
+        TensorValue::from_flat(&weight.shape(), &d_bias),
+    ])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, eps: T) -> Result<Vec<Tensor<T>>>{
+    let (shape, weight_shape) = {
+        let data = tensor.data.borrow();
+        (data.dependencies[0].shape(), data.dependencies[1].shape())
+    };
+    let (samples, normalized) = sample_layout(&shape, &weight_shape)?;
+    normalize_backward_graph(tensor, grad, eps, samples, |i| i / normalized, |i| i % normalized, None)
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], eps: T) -> Result<TensorValue<T>> {
+    let data = tensor.data.borrow();
+    let input = data.dependencies[0].value();
+    let weight = data.dependencies[1].value();
+    let shape = input.shape();
+    let (samples, normalized) = sample_layout(&shape, &weight.shape())?;
+    let x = input.flatten();
+
+    let stats = statistics(&x, samples, normalized, eps);
+    let tangents: Vec<Vec<T>> = tangents.iter().map(|t| t.flatten()).collect();
+    let out = normalize_jvp(&x, &stats, |i| i / normalized, &weight.flatten(), |i| i % normalized, [&tangents[0], &tangents[1], &tangents[2]], true);
+    Ok(TensorValue::from_flat(&shape, &out))
+}
+
+#[test]
+fn layer_norm_is_stable_for_large_offsets() -> Result<()> {
+    // A naive E[x²] - E[x]² loses every digit of the variance here.
+    let x = Tensor::matrix(vec![vec![1e9 + 1.0, 1e9 + 2.0, 1e9 + 3.0], vec![-1.0, 0.0, 1.0]]);
+    let y = x.layer_norm(&Tensor::vector(vec![1.0; 3]), &Tensor::vector(vec![0.0; 3]), 0.0)?;
+    let expected = [-(1.5_f64.sqrt()), 0.0, 1.5_f64.sqrt()];
+    for row in y.to_matrix()? {
+        for (actual, expected) in row.iter().zip(expected) {
+            approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-6);
+        }
+    }
+
+    assert!(x.layer_norm(&Tensor::vector(vec![1.0; 2]), &Tensor::vector(vec![0.0; 2]), 0.0).is_err());
+    assert!(x.layer_norm(&Tensor::vector(vec![1.0; 3]), &Tensor::vector(vec![0.0; 2]), 0.0).is_err());
+    Ok(())
+}
+
+#[test]
+fn layer_norm_gradients_match_finite_differences() -> Result<()> {
+    let data: Vec<f64> = (0..2 * 3 * 4).map(|i| (i as f64 * 0.61).sin() * 2.0).collect();
+    let inputs = [
+        Tensor::from_flat(&[2, 3, 4], &data),
+        Tensor::from_flat(&[3, 4], &(0..12).map(|i| 1.0 + (i as f64 * 0.3).cos()).collect::<Vec<_>>()),
+        Tensor::from_flat(&[3, 4], &(0..12).map(|i| (i as f64 * 0.9).sin()).collect::<Vec<_>>()),
+    ];
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].layer_norm(&inputs[1], &inputs[2], 1e-5)?.tanh(),
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].layer_norm(&inputs[1], &inputs[2], 1e-5)?.tanh()?.sum()?;
+            let grads = crate::tensor::autograd::grad(&[y], inputs)?;
+            grads[0].pow(2.0)?.sum()?.add(&grads[1].tanh()?.sum()?)?.add(&grads[2].pow(2.0)?.sum()?)
+        },
+        &inputs,
+        1e-6,
+        1e-5,
+    )?;
+    Ok(())
+}
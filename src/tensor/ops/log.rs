@@ -1,30 +1,15 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn log(&self, value: f64) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn log(&self, value: T) -> Result<Tensor<T>> {
         let a = self.data.borrow();
 
-        let result_value = match &a.value {
-            TensorValue::Scalar(v) => {
-                let s = v.log(value);
-                TensorValue::Scalar(s)
-            }
-            TensorValue::Vector1D(v) => {
-                TensorValue::Vector1D(
-                    v.iter().map(|x| x.log(value)).collect()
-                )
-            }
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| {
-                        row.iter().map(|x| x.log(value)).collect()
-                    }).collect()
-                )
-            }
-            TensorValue::Tensor3D(_) => todo!()
-        };
+        let result_value = forward(&a.value, value);
         let result = Self::from_value(result_value);
         {
             let mut res_data = result.data.borrow_mut();
@@ -32,11 +17,40 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor, base: f64) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>, base: T) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(v) => {
+            let s = v.log(base);
+            TensorValue::Scalar(s)
+        }
+        TensorValue::Vector1D(v) => {
+            TensorValue::Vector1D(
+                v.iter().map(|x| x.log(base)).collect()
+            )
+        }
+        TensorValue::Matrix2D(m) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| {
+                    row.iter().map(|x| x.log(base)).collect()
+                }).collect()
+            )
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => input.map(|x| x.log(base))
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, input: &TensorValue<T>, base: T) {
+    if !out.map_into(input, |x| x.log(base)) {
+        *out = forward(input, base);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, base: T) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
@@ -49,51 +63,74 @@ pub fn backward(tensor: &Tensor, base: f64) -> Result<()>{
         x_data.value.clone()
     };
 
-    match (&data.grad, &x_val) {
+    let grad_x = match (grad, &x_val) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(x_val)) => {
-            let dx = if x_val.abs() < 1e-12 {
-                0.0  // 处理log(0)的梯度爆炸
+            let dx = if x_val.abs() < T::from_f64(1e-12) {
+                T::zero()  // 处理log(0)的梯度爆炸
             } else {
-                grad / (x_val * base.ln())
+                *grad / (*x_val * base.ln())
             };
-            x.data.borrow_mut().add_grad_scalar(dx)?;
+            TensorValue::Scalar(dx)
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(x_vec)) => {
             assert_eq!(x_vec.len(), grad.len(), "Vector length mismatch in Log backward");
 
-            let dx_vec: Vec<f64> = x_vec.iter().zip(grad)
+            let dx_vec: Vec<T> = x_vec.iter().zip(grad)
                 .map(|(x_, g)| {
-                    if x_.abs() < 1e-12 {
-                        0.0
+                    if x_.abs() < T::from_f64(1e-12) {
+                        T::zero()
                     } else {
-                        g / x_ * base.ln()
+                        *g / (*x_ * base.ln())
                     }
                 })
                 .collect();
 
-            x.data.borrow_mut().add_grad(TensorValue::Vector1D(dx_vec))?;
+            TensorValue::Vector1D(dx_vec)
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(x_mat)) => {
-            let dx_mat: Vec<Vec<f64>> = x_mat.iter()
+            let dx_mat: Vec<Vec<T>> = x_mat.iter()
                 .zip(grad)
                 .map(|(x_row, g_row)| {
                     x_row.iter()
                         .zip(g_row)
                         .map(|(x_, g)| {
-                            if x_.abs() < 1e-12 {
-                                0.0
+                            if x_.abs() < T::from_f64(1e-12) {
+                                T::zero()
                             } else {
-                                g / x_ * base.ln()
+                                *g / (*x_ * base.ln())
                             }
                         })
                         .collect()
                 })
                 .collect();
 
-            x.data.borrow_mut().add_grad(TensorValue::Matrix2D(dx_mat))?;
+            TensorValue::Matrix2D(dx_mat)
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            grad.zip_map(&x_val, |g, x_| {
+                if x_.abs() < T::from_f64(1e-12) {
+                    T::zero()
+                } else {
+                    g / (x_ * base.ln())
+                }
+            })
         }
         _ => panic!("Mismatched tensor types in Log backward"),
-    }
+    };
+
+    Ok(vec![grad_x])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, base: T) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let x = &data.dependencies[0];
+
+    Ok(vec![grad.div(&x.multiply(&Tensor::scalar(base.ln()))?)?])
+}
 
-    Ok(())
-}
\ No newline at end of file
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], base: T) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    let x_data = data.dependencies[0].data.borrow();
+    let x = &x_data.value;
+    tangents[0].zip_map(x, |t, x| t / (x * base.ln()))
+}
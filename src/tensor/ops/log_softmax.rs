@@ -0,0 +1,101 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use crate::tensor::utils::transpose;
+use super::super::{Tensor, TensorValue};
+use anyhow::Result;
+use super::{reduce_grad, shape_of};
+
+impl<T: Float> Tensor<T> {
+    /// `ln(softmax(x))` over the whole vector or each column of a matrix, like
+    /// `softmax`. Computed as `x - max - ln(sum(exp(x - max)))`, it stays
+    /// finite where the softmax itself underflows to zero.
+    #[track_caller]
+    pub fn log_softmax(&self) -> Result<Tensor<T>> {
+        let data = self.data.borrow();
+        let result_value = forward(&data.value);
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::LogSoftmax;
+            res_data.dependencies = vec![self.clone()];
+        }
+
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+fn log_softmax<T: Float>(x: &[T]) -> Vec<T> {
+    let max = x.iter().fold(T::neg_infinity(), |a, &b| a.max(b));
+    let log_sum_exp = max + x.iter().map(|&v| (v - max).exp()).sum::<T>().ln();
+    x.iter().map(|&v| v - log_sum_exp).collect()
+}
+
+/// Sums over the whole vector, or each column of a matrix.
+fn lane_sums<T: Float>(value: &TensorValue<T>) -> TensorValue<T> {
+    match value {
+        TensorValue::Matrix2D(m) => TensorValue::Vector1D(
+            (0..m[0].len()).map(|j| m.iter().map(|row| row[j]).sum()).collect()
+        ),
+        _ => super::sum::forward(value),
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Vector1D(v) => TensorValue::Vector1D(log_softmax(v)),
+        TensorValue::Matrix2D(m) => {
+            assert!(!m.is_empty(), "Matrix is empty");
+            let columns: Vec<Vec<T>> = transpose(m).iter().map(|col| log_softmax(col)).collect();
+            TensorValue::Matrix2D(transpose(&columns))
+        }
+        _ => panic!("log_softmax only supported for 1D/2D"),
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>> {
+    let data = tensor.data.borrow();
+    if data.dependencies.len() != 1 {
+        panic!("LogSoftmax backward requires exactly 1 dependency");
+    }
+
+    // dz = dy - softmax * sum(dy), with softmax = exp(output).
+    let sum_dy = lane_sums(grad);
+    let dz = data.value.zip_map(&sum_dy, |y, sum| y.exp() * sum).zip_map(grad, |p, g| g - p);
+    Ok(vec![dz])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let x = &data.dependencies[0];
+
+    let sum_dy = reduce_grad(grad.clone(), &shape_of(tensor)[1..])?;
+    Ok(vec![grad.sub(&x.softmax()?.multiply(&sum_dy)?)?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    // dy = dx - sum(softmax * dx)
+    let data = tensor.data.borrow();
+    let s_dx = data.value.zip_map(&tangents[0], |y, t| y.exp() * t);
+    tangents[0].zip_map(&lane_sums(&s_dx), |t, sum| t - sum)
+}
+
+#[test]
+fn log_softmax_stays_finite_where_softmax_underflows() -> Result<()> {
+    let x = Tensor::vector(vec![0.0, 800.0]);
+    let y = x.log_softmax()?;
+    assert_eq!(y.to_vec()?, vec![-800.0, 0.0]);
+
+    y.multiply(&Tensor::vector(vec![-1.0, 0.0]))?.sum()?.backward()?;
+    assert_eq!(x.grad().unwrap().to_vec()?, vec![-1.0, 1.0]);
+
+    let m = Tensor::matrix(vec![vec![1.0, -2.0], vec![3.0, 0.5], vec![0.0, 0.25]]);
+    let expected = m.softmax()?.log(std::f64::consts::E)?.to_matrix()?;
+    for (row, expected_row) in m.log_softmax()?.to_matrix()?.iter().zip(expected) {
+        for (actual, expected) in row.iter().zip(expected_row) {
+            approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-12);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use crate::tensor::dtype::Float;
+use std::rc::Rc;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::index::BoolTensor;
+use crate::tensor::operation::Operation;
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// Replaces the elements where `mask` is true with `value`. No gradient
+    /// flows through the replaced elements.
+    #[track_caller]
+    pub fn masked_fill(&self, mask: &BoolTensor, value: T) -> Result<Tensor<T>> {
+        let data = self.data.borrow();
+        if data.value.shape() != mask.shape() {
+            bail!("Mask of shape {:?} must match the tensor shape {:?}", mask.shape(), data.value.shape());
+        }
+        let result_value = forward(&data.value, mask, value);
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::MaskedFill(Rc::new(mask.clone()), value);
+            res_data.dependencies = vec![self.clone()];
+        }
+
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, mask: &BoolTensor, value: T) -> TensorValue<T> {
+    let filled: Vec<T> = input.flatten().into_iter().zip(mask.flatten())
+        .map(|(x, masked)| if masked { value } else { x })
+        .collect();
+    TensorValue::from_flat(&input.shape(), &filled)
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, mask: &BoolTensor) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let dependencies = &data.dependencies;
+    if dependencies.len() != 1 {
+        panic!("MaskedFill operation requires exactly 1 dependency");
+    }
+
+    Ok(vec![forward(grad, mask, T::zero())])
+}
+
+pub fn backward_graph<T: Float>(grad: &Tensor<T>, mask: &BoolTensor) -> Result<Vec<Tensor<T>>>{
+    Ok(vec![grad.masked_fill(mask, T::zero())?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], mask: &BoolTensor) -> TensorValue<T> {
+    forward(&tangents[0], mask, T::zero())
+}
+
+#[test]
+fn masked_fill_blocks_gradient() -> Result<()> {
+    let x = Tensor::matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+    let mask = BoolTensor::matrix(vec![vec![true, false], vec![false, true]]);
+    let y = x.masked_fill(&mask, -1.0)?;
+    assert_eq!(y.to_matrix()?, vec![vec![-1.0, 2.0], vec![3.0, -1.0]]);
+
+    y.pow(2.0)?.sum()?.backward()?;
+    assert_eq!(x.grad().unwrap().to_matrix()?, vec![vec![0.0, 4.0], vec![6.0, 0.0]]);
+
+    assert!(x.masked_fill(&BoolTensor::vector(vec![true, false]), 0.0).is_err());
+    Ok(())
+}
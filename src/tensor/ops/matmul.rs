@@ -1,37 +1,17 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
-use crate::tensor::utils::{matrix_multiply, transpose};
+use crate::tensor::utils::{matrix_multiply, matrix_multiply_into, transpose};
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn matmul(&self, other: &Tensor) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn matmul(&self, other: &Tensor<T>) -> Result<Tensor<T>> {
         let a = self.data.borrow();
         let b = other.data.borrow();
 
-        let result_value = match (&a.value, &b.value) {
-            (TensorValue::Matrix2D(a_mat), TensorValue::Vector1D(b_vec)) => {
-                assert_eq!(a_mat[0].len(), b_vec.len(),
-                           "Matrix multiplication dimension mismatch: {} vs {}",
-                           a_mat[0].len(), b_vec.len()
-                );
-                let mut result = vec![0.0; a_mat.len()];
-                for i in 0..a_mat.len() {
-                    for j in 0..a_mat[0].len() {
-                        result[i] += a_mat[i][j] * b_vec[j];
-                    }
-                }
-                TensorValue::Vector1D(result)
-            },
-            (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
-                assert_eq!(a_mat[0].len(), b_mat.len(),
-                           "Matrix multiplication dimension mismatch: {} vs {}",
-                           a_mat[0].len(), b_mat.len());
-
-                let result = matrix_multiply(a_mat, b_mat);
-                TensorValue::Matrix2D(result)
-            }
-            _ => panic!("Matmul only supported for 2D matrices"),
-        };
+        let result_value = forward(&a.value, &b.value);
 
         let result = Self::from_value(result_value);
         {
@@ -39,11 +19,59 @@ impl Tensor {
             res_data.operation = Operation::Matmul;
             res_data.dependencies = vec![self.clone(), other.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(a: &TensorValue<T>, b: &TensorValue<T>) -> TensorValue<T> {
+    match (a, b) {
+        (TensorValue::Matrix2D(a_mat), TensorValue::Vector1D(b_vec)) => {
+            assert_eq!(a_mat[0].len(), b_vec.len(),
+                       "Matrix multiplication dimension mismatch: {} vs {}",
+                       a_mat[0].len(), b_vec.len()
+            );
+            let mut result = vec![T::zero(); a_mat.len()];
+            for i in 0..a_mat.len() {
+                for j in 0..a_mat[0].len() {
+                    result[i] += a_mat[i][j] * b_vec[j];
+                }
+            }
+            TensorValue::Vector1D(result)
+        },
+        (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
+            assert_eq!(a_mat[0].len(), b_mat.len(),
+                       "Matrix multiplication dimension mismatch: {} vs {}",
+                       a_mat[0].len(), b_mat.len());
+
+            let result = matrix_multiply(a_mat, b_mat);
+            TensorValue::Matrix2D(result)
+        }
+        _ => panic!("Matmul only supported for 2D matrices"),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, a: &TensorValue<T>, b: &TensorValue<T>) {
+    match (&mut *out, a, b) {
+        (TensorValue::Vector1D(result), TensorValue::Matrix2D(a_mat), TensorValue::Vector1D(b_vec))
+            if result.len() == a_mat.len() && a_mat[0].len() == b_vec.len() => {
+            result.fill(T::zero());
+            for i in 0..a_mat.len() {
+                for j in 0..a_mat[0].len() {
+                    result[i] += a_mat[i][j] * b_vec[j];
+                }
+            }
+        }
+        (TensorValue::Matrix2D(result), TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat))
+            if result.len() == a_mat.len() && result[0].len() == b_mat[0].len() && a_mat[0].len() == b_mat.len() => {
+            result.iter_mut().for_each(|row| row.fill(T::zero()));
+            matrix_multiply_into(result, a_mat, b_mat);
+        }
+        _ => *out = forward(a, b),
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
 
     // TODO
@@ -55,15 +83,13 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
     let a = &dependencies[0];
     let b = &dependencies[1];
 
-    let grad = &data.grad;
-
     let (a_val, b_val) = {
         let a_data = a.data.borrow();
         let b_data = b.data.borrow();
         (a_data.value.clone(), b_data.value.clone())
     };
 
-    match (&a_val, &b_val) {
+    let (a_grad, b_grad) = match (&a_val, &b_val) {
         (TensorValue::Matrix2D(a_mat), TensorValue::Vector1D(b_vec)) => {
             let (m, n) = (a_mat.len(), a_mat[0].len());
             let p = 1;
@@ -75,7 +101,7 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
                         "Gradient vector length mismatch: expected {}, got {}",
                         m, v.len()
                     );
-                    v.iter().map(|&x| vec![x]).collect::<Vec<Vec<f64>>>()
+                    v.iter().map(|&x| vec![x]).collect::<Vec<Vec<T>>>()
                 },
                 TensorValue::Matrix2D(mat) => {
                     assert_eq!(
@@ -101,21 +127,20 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
 
             let db = db_mat.into_iter()
                 .map(|row| row[0])
-                .collect::<Vec<f64>>();
+                .collect::<Vec<T>>();
 
             assert_eq!(da.len(), m, "dA row mismatch");
             assert_eq!(da[0].len(), n, "dA column mismatch");
             assert_eq!(db.len(), n, "dB length mismatch");
 
-            a.data.borrow_mut().add_grad(TensorValue::Matrix2D(da))?;
-            b.data.borrow_mut().add_grad(TensorValue::Vector1D(db))?;
+            (TensorValue::Matrix2D(da), TensorValue::Vector1D(db))
 
         }
         (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
             let (m, n) = (a_mat.len(), a_mat[0].len());
             let (n_, p) = (b_mat.len(), b_mat[0].len());
 
-            let grad_ = match &data.grad {
+            let grad_ = match grad {
                 TensorValue::Matrix2D(grad_) => grad_,
                 _ => panic!("")
             };
@@ -129,13 +154,39 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
             let a_t = transpose(a_mat);
             let db = matrix_multiply(&a_t, &grad_);
 
-            a.data.borrow_mut().add_grad(TensorValue::Matrix2D(da))?;
-            b.data.borrow_mut().add_grad(TensorValue::Matrix2D(db))?;
+            (TensorValue::Matrix2D(da), TensorValue::Matrix2D(db))
         }
 
         _ => panic!("Unsupported Matmul backward combination"),
-    }
-    Ok(())
+    };
+    Ok(vec![a_grad, b_grad])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let a = &data.dependencies[0];
+    let b = &data.dependencies[1];
+
+    let is_vector = matches!(b.data.borrow().value, TensorValue::Vector1D(_));
+    let da = if is_vector {
+        // Outer product of the output gradient and the input vector.
+        grad.unsqueeze(1)?.matmul(&b.unsqueeze(0)?)?
+    } else {
+        grad.matmul(&b.t()?)?
+    };
+    let db = a.t()?.matmul(grad)?;
+    Ok(vec![da, db])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    let a_data = data.dependencies[0].data.borrow();
+    let a = &a_data.value;
+    let b_data = data.dependencies[1].data.borrow();
+    let b = &b_data.value;
+    let da_b = forward(&tangents[0], b);
+    let a_db = forward(a, &tangents[1]);
+    da_b.zip_map(&a_db, |x, y| x + y)
 }
 
 #[test]
@@ -145,11 +196,11 @@ fn matmul_works() -> Result<()>{
     let c = a.matmul(&b)?;
     c.backward()?;
 
-    let a_grad = match &a.data.borrow().grad {
+    let a_grad = match &a.grad().unwrap().value() {
         TensorValue::Matrix2D(m) => m.clone(),
         _ => panic!("Error!")
     };
-    let b_grad = match &b.data.borrow().grad {
+    let b_grad = match &b.grad().unwrap().value() {
         TensorValue::Matrix2D(m) => m.clone(),
         _ => panic!("Error!")
     };
@@ -0,0 +1,133 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::pool::{Pooling, PoolWindows};
+use super::super::{Tensor, TensorValue};
+use super::route_grad;
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// Max over windows along the length of a `[batch, channels, length]` input.
+    #[track_caller]
+    pub fn max_pool1d(&self, pooling: Pooling) -> Result<Tensor<T>> {
+        if self.shape().len() != 3 {
+            bail!("max_pool1d expects a [batch, channels, length] input, got shape {:?}", self.shape());
+        }
+        self.max_pool(pooling)
+    }
+
+    /// Max over windows of a `[batch, channels, height, width]` input.
+    #[track_caller]
+    pub fn max_pool2d(&self, pooling: Pooling) -> Result<Tensor<T>> {
+        if self.shape().len() != 4 {
+            bail!("max_pool2d expects a [batch, channels, height, width] input, got shape {:?}", self.shape());
+        }
+        self.max_pool(pooling)
+    }
+
+    #[track_caller]
+    fn max_pool(&self, pooling: Pooling) -> Result<Tensor<T>> {
+        let result_value = forward(&self.data.borrow().value, &pooling)?;
+
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::MaxPool(pooling);
+            res_data.dependencies = vec![self.clone()];
+        }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+/// The input offset of the maximum of every window; ties go to the first.
+fn argmax<T: Float>(x: &[T], windows: &PoolWindows) -> Vec<usize> {
+    let mut argmax = vec![0; windows.output_len()];
+    windows.for_each(|o, base, window| {
+        argmax[o] = window.iter().map(|i| base + i)
+            .reduce(|best, i| if x[i] > x[best] { i } else { best })
+            .unwrap();
+    });
+    argmax
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, pooling: &Pooling) -> Result<TensorValue<T>> {
+    let windows = PoolWindows::new(&input.shape(), pooling)?;
+    let x = input.flatten();
+    let out: Vec<T> = argmax(&x, &windows).into_iter().map(|i| x[i]).collect();
+    Ok(TensorValue::from_flat(&windows.output_shape, &out))
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, pooling: &Pooling) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    if data.dependencies.len() != 1 {
+        panic!("MaxPool operation requires exactly 1 dependency");
+    }
+
+    // Each output gradient goes to the element that won its window.
+    let input = data.dependencies[0].value();
+    let windows = PoolWindows::new(&input.shape(), pooling)?;
+    let x = input.flatten();
+    let mut dx = vec![T::zero(); x.len()];
+    for (i, g) in argmax(&x, &windows).into_iter().zip(grad.flatten()) {
+        dx[i] += g;
+    }
+    Ok(vec![TensorValue::from_flat(&input.shape(), &dx)])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, pooling: &Pooling) -> Result<Vec<Tensor<T>>>{
+    let input = tensor.data.borrow().dependencies[0].value();
+    let windows = PoolWindows::new(&input.shape(), pooling)?;
+    let winners = argmax(&input.flatten(), &windows).into_iter().map(|i| i as i64).collect();
+    Ok(vec![route_grad(grad, (0..windows.output_len() as i64).collect(), winners, None, &input.shape())?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], pooling: &Pooling) -> Result<TensorValue<T>> {
+    let input = tensor.data.borrow().dependencies[0].value();
+    let windows = PoolWindows::new(&input.shape(), pooling)?;
+    let tangent = tangents[0].flatten();
+    let out: Vec<T> = argmax(&input.flatten(), &windows).into_iter().map(|i| tangent[i]).collect();
+    Ok(TensorValue::from_flat(&windows.output_shape, &out))
+}
+
+#[test]
+fn max_pool_routes_gradient_to_the_argmax() -> Result<()> {
+    let x = Tensor::tensor3d(vec![vec![vec![1.0, 3.0, 2.0, 5.0, 4.0, 0.0]]]);
+    let y = x.max_pool1d(Pooling::window(2))?;
+    assert_eq!(y.value().flatten(), vec![3.0, 5.0, 4.0]);
+
+    y.multiply(&Tensor::tensor3d(vec![vec![vec![1.0, 2.0, 3.0]]]))?.sum()?.backward()?;
+    assert_eq!(x.grad().unwrap().value().flatten(), vec![0.0, 1.0, 0.0, 2.0, 3.0, 0.0]);
+
+    assert!(x.max_pool2d(Pooling::window(2)).is_err());
+    assert!(x.max_pool1d(Pooling::window(7)).is_err());
+    Ok(())
+}
+
+#[test]
+fn max_pool2d_gradients_match_finite_differences() -> Result<()> {
+    // Distinct values keep every window's maximum away from a tie.
+    let data: Vec<f64> = (0..2 * 2 * 5 * 5).map(|i| ((i * 37) % 101) as f64 / 50.0 - 1.0).collect();
+    let x = Tensor::from_value(TensorValue::from_flat(&[2, 2, 5, 5], &data));
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].max_pool2d(Pooling::Window { kernel_size: 3, stride: 2, padding: 1 })?.tanh(),
+        &[x],
+        1e-6,
+        1e-5,
+    )
+}
+
+#[test]
+fn max_pool_second_order_gradients_match_finite_differences() -> Result<()> {
+    let data: Vec<f64> = (0..2 * 2 * 4 * 4).map(|i| ((i * 37) % 101) as f64 / 50.0 - 1.0).collect();
+    let x = Tensor::from_value(TensorValue::from_flat(&[2, 2, 4, 4], &data));
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| {
+            let y = inputs[0].max_pool2d(Pooling::window(2))?.tanh()?.sum()?;
+            crate::tensor::autograd::grad(&[y], inputs)?[0].pow(2.0)?.sum()
+        },
+        &[x],
+        1e-6,
+        1e-5,
+    )
+}
@@ -1,61 +1,75 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
+use super::{expand_grad, shape_of};
 
-impl Tensor {
-    pub fn mean(&self) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn mean(&self) -> Result<Tensor<T>> {
         let data = self.data.borrow();
-        let result_value = match &data.value {
-            TensorValue::Vector1D(v) => {
-                // Mean for vec
-                let sum: f64 = v.iter().sum();
-                let count = v.len();
-
-                TensorValue::Scalar(
-                    sum / count as f64
-                )
-            }
+        let result_value = forward(&data.value);
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::Mean;
+            res_data.dependencies = vec![self.clone()];
+        }
 
-            TensorValue::Matrix2D(m ) => {
-                if m.is_empty() || m[0].is_empty() {
-                    panic!("Matrix is empty");
-                }
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
 
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Vector1D(v) => {
+            // Mean for vec
+            let sum: T = v.iter().copied().sum();
+            let count = v.len();
+
+            TensorValue::Scalar(
+                sum / T::from_f64(count as f64)
+            )
+        }
 
-                let cols = m[0].len();
-                assert!(
-                    m.iter().all(|row| row.len() == cols),
-                    "Inconsistent matrix columns"
-                );
+        TensorValue::Matrix2D(m ) => {
+            if m.is_empty() || m[0].is_empty() {
+                panic!("Matrix is empty");
+            }
 
 
-                let mut sum = 0.0;
-                let mut count = 0;
-                for row in m {
-                    for &val in row {
-                        sum += val;
-                        count += 1;
-                    }
-                }
+            let cols = m[0].len();
+            assert!(
+                m.iter().all(|row| row.len() == cols),
+                "Inconsistent matrix columns"
+            );
 
-                // 返回标量均值
-                TensorValue::Scalar(sum / count as f64)
 
+            let mut sum = T::zero();
+            let mut count = 0;
+            for row in m {
+                for &val in row {
+                    sum += val;
+                    count += 1;
+                }
             }
-            _ => panic!("mean only supported for 1D Vectors"),
-        };
-        let result = Self::from_value(result_value);
-        {
-            let mut res_data = result.data.borrow_mut();
-            res_data.operation = Operation::Mean;
-            res_data.dependencies = vec![self.clone()];
-        }
 
-        Ok(result)
+            // 返回标量均值
+            TensorValue::Scalar(sum / T::from_f64(count as f64))
+
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => {
+            let values = input.flatten();
+            let count = T::from_f64(values.len() as f64);
+            TensorValue::Scalar(values.into_iter().sum::<T>() / count)
+        }
+        _ => panic!("mean only supported for 1D Vectors"),
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
@@ -69,12 +83,12 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
         input_data.value.shape()
     };
 
-    let grad = match &data.grad {
+    let grad = match grad {
         TensorValue::Scalar(g) => *g,
         _ => panic!("Mean gradient must be a scalar!"),
     };
 
-    let num_elements = input_shape.iter().product::<usize>() as f64;
+    let num_elements = T::from_f64(input_shape.iter().product::<usize>() as f64);
     let grad_per_elements = grad / num_elements;
 
     let grad_tensor = match input_shape.len() {
@@ -87,9 +101,22 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
         2 => {
             TensorValue::Matrix2D(vec![vec![grad_per_elements; input_shape[1]]; input_shape[0]])
         }
+        3 | 4 => TensorValue::full(&input_shape, grad_per_elements),
         _ => panic!("Unsupported dimension for mean backward!")
     };
 
-    input.data.borrow_mut().add_grad(grad_tensor)?;
-    Ok(())
-}
\ No newline at end of file
+    Ok(vec![grad_tensor])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let input_shape = shape_of(&data.dependencies[0]);
+
+    let num_elements = T::from_f64(input_shape.iter().product::<usize>() as f64);
+    let grad_per_elements = grad.div(&Tensor::scalar(num_elements))?;
+    Ok(vec![expand_grad(&grad_per_elements, &input_shape)?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    forward(&tangents[0])
+}
@@ -1,9 +1,17 @@
 // This file is used to manage backward operation of tensor.
 // @Author: Yuyuan12138x@gmail.com
 
+use crate::tensor::dtype::Float;
 use super::Tensor;
+use super::node::NodeData;
 use super::operation::Operation;
+use super::value::TensorValue;
+use super::hook;
+use super::tape::{Gradients, Tape};
+use super::anomaly;
+use super::index::IntTensor;
 use anyhow::Result;
+use std::cell::Ref;
 pub mod add;
 pub mod div;
 pub mod log;
@@ -16,36 +24,306 @@ pub mod sigmoid;
 pub mod sub;
 pub mod tanh;
 pub mod softmax;
+pub mod log_softmax;
 pub mod sum;
 pub mod t;
 pub mod unsqueeze;
 pub mod squeeze;
+pub mod gather;
+pub mod scatter_add;
+pub mod masked_fill;
+pub mod reshape;
+pub mod conv1d;
+pub mod conv2d;
+pub mod pool;
+pub mod max_pool;
+pub mod avg_pool;
+pub mod norm;
+pub mod batch_norm;
+pub mod layer_norm;
+pub mod group_norm;
+pub mod to_dtype;
+pub mod custom;
+
+/// Propagates the gradient of node `index` of `tape` to its dependencies.
+/// `grads` is the gradient arena of the backward pass, indexed like the tape.
+pub fn _backward<T: Float>(tape: &Tape<T>, index: usize, grads: &mut Gradients<T>) -> Result<()>{
+    let grad = match grads.take(index) {
+        Some(grad) => grad,
+        None => return Ok(()),
+    };
+    let tensor = &tape.nodes()[index];
+    // Every node depending on `tensor` has been processed, so its gradient is final.
+    let grad = hook::run_hooks(tensor, grad)?;
 
-pub fn _backward(tensor: &Tensor) -> Result<()>{
     let data = tensor.data.borrow();
-    match data.operation {
-        Operation::Add => add::backward(&tensor)?,
-        Operation::Sub => sub::backward(&tensor)?,
-        Operation::Multiply => multiply::backward(&tensor)?,
-        Operation::Div => div::backward(&tensor).expect("Div backward went wrong"),
-        Operation::Sigmoid => sigmoid::backward(&tensor)?,
-        Operation::ReLU => relu::backward(&tensor)?,
-        Operation::Matmul => matmul::backward(&tensor)?,
-        Operation::Mean => mean::backward(&tensor)?,
-        Operation::Log(base) => log::backward(&tensor, base)?,
-        Operation::Pow(exponent) => pow::backward(&tensor, exponent)?,
-        Operation::Tanh => tanh::backward(&tensor)?,
-        Operation::Softmax => softmax::backward(&tensor)?,
-        Operation::Sum => sum::backward(&tensor)?,
-        Operation::T => t::backward(&tensor)?,
-        Operation::Unsqueeze(dim) => unsqueeze::backward(&tensor, dim)?,
-        Operation::Squeeze(dim) => squeeze::backward(&tensor, dim)?,
-
-        Operation::None => {}
+    let input_grads = match &data.operation {
+        Operation::Add => add::backward(tensor, &grad)?,
+        Operation::Sub => sub::backward(tensor, &grad)?,
+        Operation::Multiply => multiply::backward(tensor, &grad)?,
+        Operation::Div => div::backward(tensor, &grad).expect("Div backward went wrong"),
+        Operation::Sigmoid => sigmoid::backward(tensor, &grad)?,
+        Operation::ReLU => relu::backward(tensor, &grad)?,
+        Operation::Matmul => matmul::backward(tensor, &grad)?,
+        Operation::Mean => mean::backward(tensor, &grad)?,
+        Operation::Log(base) => log::backward(tensor, &grad, *base)?,
+        Operation::Pow(exponent) => pow::backward(tensor, &grad, *exponent)?,
+        Operation::Tanh => tanh::backward(tensor, &grad)?,
+        Operation::Softmax => softmax::backward(tensor, &grad)?,
+        Operation::LogSoftmax => log_softmax::backward(tensor, &grad)?,
+        Operation::Sum => sum::backward(tensor, &grad)?,
+        Operation::T => t::backward(tensor, &grad)?,
+        Operation::Unsqueeze(dim) => unsqueeze::backward(tensor, &grad, *dim)?,
+        Operation::Squeeze(dim) => squeeze::backward(tensor, &grad, *dim)?,
+        Operation::Gather(dim, index) => gather::backward(tensor, &grad, *dim, index)?,
+        Operation::ScatterAdd(dim, index) => scatter_add::backward(tensor, &grad, *dim, index)?,
+        Operation::MaskedFill(mask, _) => masked_fill::backward(tensor, &grad, mask)?,
+        Operation::Reshape(_) => reshape::backward(tensor, &grad)?,
+        Operation::Convolution1D(options) => conv1d::backward(tensor, &grad, *options)?,
+        Operation::Convolution2D(options) => conv2d::backward(tensor, &grad, *options)?,
+        Operation::MaxPool(pooling) => max_pool::backward(tensor, &grad, pooling)?,
+        Operation::AvgPool(pooling) => avg_pool::backward(tensor, &grad, pooling)?,
+        Operation::BatchNorm(eps, running) => batch_norm::backward(tensor, &grad, *eps, running.as_ref())?,
+        Operation::LayerNorm(eps) => layer_norm::backward(tensor, &grad, *eps)?,
+        Operation::GroupNorm(groups, eps) => group_norm::backward(tensor, &grad, *groups, *eps)?,
+        Operation::Cast(source) => to_dtype::backward(&grad, source, grads),
+        Operation::Custom(_) => custom::backward(tensor, &grad)?,
+
+        // Leaves accumulate their gradient across backward passes.
+        Operation::None => {
+            drop(data);
+            tensor.data.borrow_mut().add_grad(&grad)?;
+            grads.restore(index, grad);
+            return Ok(());
+        }
         _ => {
             panic!("No definitive operation {:?}", data.operation);
         }
     };
+    anomaly::check_backward(tensor, &input_grads)?;
+    let retain_grad = data.retain_grad;
+    drop(data);
+
+    for (&dep, dep_grad) in tape.dependencies(index).iter().zip(input_grads) {
+        grads.add(dep, dep_grad, &tape.nodes()[dep].data.borrow().value)?;
+    }
+
+    // Intermediate gradients are only kept on the node if asked for.
+    if retain_grad {
+        tensor.data.borrow_mut().grad = Some(grad.clone());
+    }
+    grads.restore(index, grad);
+    Ok(())
+}
+/// Recomputes the value of `tensor` from the current values of its dependencies.
+pub fn _forward<T: Float>(tensor: &Tensor<T>) -> Result<TensorValue<T>>{
+    let data = tensor.data.borrow();
+    let deps: Vec<Ref<NodeData<T>>> = data.dependencies.iter().map(|dep| dep.data.borrow()).collect();
+    let inputs: Vec<&TensorValue<T>> = deps.iter().map(|dep| &dep.value).collect();
+
+    let value = match &data.operation {
+        Operation::Add => add::forward(inputs[0], inputs[1]),
+        Operation::Sub => sub::forward(inputs[0], inputs[1]),
+        Operation::Multiply => multiply::forward(inputs[0], inputs[1]),
+        Operation::Div => div::forward(inputs[0], inputs[1]),
+        Operation::Sigmoid => sigmoid::forward(inputs[0]),
+        Operation::ReLU => relu::forward(inputs[0]),
+        Operation::Matmul => matmul::forward(inputs[0], inputs[1]),
+        Operation::Mean => mean::forward(inputs[0]),
+        Operation::Log(base) => log::forward(inputs[0], *base),
+        Operation::Pow(exponent) => pow::forward(inputs[0], *exponent),
+        Operation::Tanh => tanh::forward(inputs[0]),
+        Operation::Softmax => softmax::forward(inputs[0]),
+        Operation::LogSoftmax => log_softmax::forward(inputs[0]),
+        Operation::Sum => sum::forward(inputs[0]),
+        Operation::T => t::forward(inputs[0]),
+        Operation::Unsqueeze(dim) => unsqueeze::forward(inputs[0], *dim),
+        Operation::Squeeze(dim) => squeeze::forward(inputs[0], *dim),
+        Operation::Gather(dim, index) => gather::forward(inputs[0], *dim, index),
+        Operation::ScatterAdd(dim, index) => scatter_add::forward(inputs[0], inputs[1], *dim, index),
+        Operation::MaskedFill(mask, value) => masked_fill::forward(inputs[0], mask, *value),
+        Operation::Reshape(shape) => reshape::forward(inputs[0], shape),
+        Operation::Convolution1D(options) => conv1d::forward(inputs[0], inputs[1], inputs.get(2).copied(), *options)?,
+        Operation::Convolution2D(options) => conv2d::forward(inputs[0], inputs[1], inputs.get(2).copied(), *options)?,
+        Operation::MaxPool(pooling) => max_pool::forward(inputs[0], pooling)?,
+        Operation::AvgPool(pooling) => avg_pool::forward(inputs[0], pooling)?,
+        Operation::BatchNorm(eps, running) => batch_norm::forward(inputs[0], inputs[1], inputs[2], *eps, running.as_ref())?,
+        Operation::LayerNorm(eps) => layer_norm::forward(inputs[0], inputs[1], inputs[2], *eps)?,
+        Operation::GroupNorm(groups, eps) => group_norm::forward(inputs[0], inputs[1], inputs[2], *groups, *eps)?,
+        Operation::Cast(source) => to_dtype::forward(source),
+        Operation::Custom(function) => {
+            let input_values: Vec<TensorValue<T>> = inputs.iter().map(|value| (*value).clone()).collect();
+            function.forward(&input_values)?
+        }
 
+        // Leaves keep whatever value they hold.
+        Operation::None => data.value.clone(),
+        _ => {
+            panic!("No definitive operation {:?}", data.operation);
+        }
+    };
+
+    Ok(value)
+}
+
+/// Like `_forward`, but writes into the node's existing value, so replaying a
+/// trace reuses the buffers of the element-wise ops and matmul instead of
+/// allocating new ones. Other ops still build a fresh value.
+pub fn _forward_into<T: Float>(tensor: &Tensor<T>) -> Result<()>{
+    let mut data = tensor.data.borrow_mut();
+    let NodeData { value, dependencies, operation, .. } = &mut *data;
+    let input = |i: usize| dependencies[i].data.borrow();
+
+    match operation {
+        Operation::Add => add::forward_into(value, &input(0).value, &input(1).value),
+        Operation::Sub => sub::forward_into(value, &input(0).value, &input(1).value),
+        Operation::Multiply => multiply::forward_into(value, &input(0).value, &input(1).value),
+        Operation::Div => div::forward_into(value, &input(0).value, &input(1).value),
+        Operation::Matmul => matmul::forward_into(value, &input(0).value, &input(1).value),
+        Operation::Sigmoid => sigmoid::forward_into(value, &input(0).value),
+        Operation::ReLU => relu::forward_into(value, &input(0).value),
+        Operation::Tanh => tanh::forward_into(value, &input(0).value),
+        Operation::Log(base) => log::forward_into(value, &input(0).value, *base),
+        Operation::Pow(exponent) => pow::forward_into(value, &input(0).value, *exponent),
+        _ => {
+            drop(data);
+            let value = _forward(tensor)?;
+            tensor.data.borrow_mut().value = value;
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Computes the gradients of `tensor`'s dependencies out of tensor ops, so that
+/// they are part of a graph themselves. Used by `Tensor::backward_create_graph`.
+pub fn _backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let operation = tensor.data.borrow().operation.clone();
+    let grads = match operation {
+        Operation::Add => add::backward_graph(tensor, grad)?,
+        Operation::Sub => sub::backward_graph(tensor, grad)?,
+        Operation::Multiply => multiply::backward_graph(tensor, grad)?,
+        Operation::Div => div::backward_graph(tensor, grad)?,
+        Operation::Sigmoid => sigmoid::backward_graph(tensor, grad)?,
+        Operation::ReLU => relu::backward_graph(tensor, grad)?,
+        Operation::Matmul => matmul::backward_graph(tensor, grad)?,
+        Operation::Mean => mean::backward_graph(tensor, grad)?,
+        Operation::Log(base) => log::backward_graph(tensor, grad, base)?,
+        Operation::Pow(exponent) => pow::backward_graph(tensor, grad, exponent)?,
+        Operation::Tanh => tanh::backward_graph(tensor, grad)?,
+        Operation::Softmax => softmax::backward_graph(tensor, grad)?,
+        Operation::LogSoftmax => log_softmax::backward_graph(tensor, grad)?,
+        Operation::Sum => sum::backward_graph(tensor, grad)?,
+        Operation::T => t::backward_graph(grad)?,
+        Operation::Unsqueeze(dim) => unsqueeze::backward_graph(grad, dim)?,
+        Operation::Squeeze(dim) => squeeze::backward_graph(grad, dim)?,
+        Operation::Gather(dim, index) => gather::backward_graph(tensor, grad, dim, &index)?,
+        Operation::ScatterAdd(dim, index) => scatter_add::backward_graph(grad, dim, &index)?,
+        Operation::MaskedFill(mask, _) => masked_fill::backward_graph(grad, &mask)?,
+        Operation::Reshape(_) => reshape::backward_graph(tensor, grad)?,
+        Operation::Convolution1D(options) => conv1d::backward_graph(tensor, grad, options)?,
+        Operation::Convolution2D(options) => conv2d::backward_graph(tensor, grad, options)?,
+        Operation::MaxPool(pooling) => max_pool::backward_graph(tensor, grad, &pooling)?,
+        Operation::AvgPool(pooling) => avg_pool::backward_graph(tensor, grad, &pooling)?,
+        Operation::BatchNorm(eps, running) => batch_norm::backward_graph(tensor, grad, eps, running.as_ref())?,
+        Operation::LayerNorm(eps) => layer_norm::backward_graph(tensor, grad, eps)?,
+        Operation::GroupNorm(groups, eps) => group_norm::backward_graph(tensor, grad, groups, eps)?,
+        Operation::Cast(source) => to_dtype::backward_graph(&source)?,
+        Operation::Custom(function) => custom::backward_graph(tensor, grad, &function)?,
+
+        Operation::None => vec![],
+        _ => {
+            panic!("No definitive operation {:?}", operation);
+        }
+    };
+
+    Ok(grads)
+}
+
+/// Tangent of `tensor` given the tangents of its dependencies, in order. Used by
+/// `tensor::forward_ad`.
+pub fn _jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> Result<TensorValue<T>>{
+    let operation = tensor.data.borrow().operation.clone();
+    let tangent = match operation {
+        Operation::Add => add::jvp(tangents),
+        Operation::Sub => sub::jvp(tangents),
+        Operation::Multiply => multiply::jvp(tensor, tangents),
+        Operation::Div => div::jvp(tensor, tangents),
+        Operation::Sigmoid => sigmoid::jvp(tensor, tangents),
+        Operation::ReLU => relu::jvp(tensor, tangents),
+        Operation::Matmul => matmul::jvp(tensor, tangents),
+        Operation::Mean => mean::jvp(tangents),
+        Operation::Log(base) => log::jvp(tensor, tangents, base),
+        Operation::Pow(exponent) => pow::jvp(tensor, tangents, exponent),
+        Operation::Tanh => tanh::jvp(tensor, tangents),
+        Operation::Softmax => softmax::jvp(tensor, tangents),
+        Operation::LogSoftmax => log_softmax::jvp(tensor, tangents),
+        Operation::Sum => sum::jvp(tangents),
+        Operation::T => t::jvp(tangents),
+        Operation::Unsqueeze(dim) => unsqueeze::jvp(tangents, dim),
+        Operation::Squeeze(dim) => squeeze::jvp(tangents, dim),
+        Operation::Gather(dim, index) => gather::jvp(tangents, dim, &index),
+        Operation::ScatterAdd(dim, index) => scatter_add::jvp(tangents, dim, &index),
+        Operation::MaskedFill(mask, _) => masked_fill::jvp(tangents, &mask),
+        Operation::Reshape(shape) => reshape::jvp(tangents, &shape),
+        Operation::Convolution1D(options) => conv1d::jvp(tensor, tangents, options)?,
+        Operation::Convolution2D(options) => conv2d::jvp(tensor, tangents, options)?,
+        Operation::MaxPool(pooling) => max_pool::jvp(tensor, tangents, &pooling)?,
+        Operation::AvgPool(pooling) => avg_pool::jvp(tangents, &pooling)?,
+        Operation::BatchNorm(eps, running) => batch_norm::jvp(tensor, tangents, eps, running.as_ref())?,
+        Operation::LayerNorm(eps) => layer_norm::jvp(tensor, tangents, eps)?,
+        Operation::GroupNorm(groups, eps) => group_norm::jvp(tensor, tangents, groups, eps)?,
+        Operation::Cast(_) => panic!("Casts take their tangent from their source"),
+        Operation::Custom(function) => custom::jvp(tensor, tangents, &function)?,
+
+        Operation::None => panic!("Leaf tensors have no jvp"),
+        _ => {
+            panic!("No definitive operation {:?}", operation);
+        }
+    };
+
+    Ok(tangent)
+}
+
+/// Sums a gradient that was broadcast in the forward pass back down to `shape`.
+fn reduce_grad<T: Float>(grad: Tensor<T>, shape: &[usize]) -> Result<Tensor<T>>{
+    let grad_shape = grad.shape();
+    if grad_shape == shape {
+        return Ok(grad);
+    }
+
+    match (grad_shape.len(), shape.len()) {
+        (_, 0) => grad.sum(),
+        // A vector broadcast over the rows of a matrix: sum over the rows.
+        (2, 1) => Tensor::vector(vec![T::one(); grad_shape[0]])
+            .unsqueeze(0)?
+            .matmul(&grad)?
+            .squeeze(0),
+        _ => panic!("Cannot reduce gradient of shape {:?} to {:?}", grad_shape, shape),
+    }
+}
+
+/// Broadcasts a scalar gradient to every element of `shape`.
+fn expand_grad<T: Float>(grad: &Tensor<T>, shape: &[usize]) -> Result<Tensor<T>>{
+    Tensor::from_value(TensorValue::full(shape, T::one())).multiply(grad)
+}
+
+/// `out[to[k]] += grad[from[k]] * scale[k]` over the flattened `grad`, laid
+/// out as `shape`. The gradients of the windowed ops are such sparse linear
+/// maps; built from `gather` and `scatter_add`, they stay differentiable.
+fn route_grad<T: Float>(grad: &Tensor<T>, from: Vec<i64>, to: Vec<i64>, scale: Option<Tensor<T>>, shape: &[usize]) -> Result<Tensor<T>>{
+    let mut routed = flat(grad)?.gather(0, &IntTensor::vector(from))?;
+    if let Some(scale) = scale {
+        routed = routed.multiply(&scale)?;
+    }
+    let zeros = Tensor::from_value(TensorValue::Vector1D(vec![T::zero(); shape.iter().product()]));
+    zeros.scatter_add(0, &IntTensor::vector(to), &routed)?.reshape(shape)
+}
+
+/// The elements of `tensor` as a vector.
+fn flat<T: Float>(tensor: &Tensor<T>) -> Result<Tensor<T>>{
+    tensor.reshape(&[tensor.shape().iter().product()])
+}
+
+fn shape_of<T: Float>(tensor: &Tensor<T>) -> Vec<usize> {
+    tensor.shape()
+}
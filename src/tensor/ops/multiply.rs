@@ -1,54 +1,18 @@
+use crate::tensor::dtype::Float;
 use anyhow::anyhow;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
+use super::{reduce_grad, shape_of};
 
-impl Tensor {
-    pub fn multiply(&self, other: &Tensor) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn multiply(&self, other: &Tensor<T>) -> Result<Tensor<T>> {
         let a = self.data.borrow();
         let b = other.data.borrow();
 
-        let result_value = match (&a.value, &b.value) {
-
-            (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
-                TensorValue::Scalar(a_val * b_val)
-            }
-
-            (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
-                assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
-                TensorValue::Vector1D(
-                    a_vec.iter().zip(b_vec).map(|(a, b)| a * b).collect()
-                )
-            }
-
-            (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
-                assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
-                assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
-                TensorValue::Matrix2D(
-                    a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
-                        a_row.iter().zip(b_row).map(|(a, b)| a * b).collect()
-                    }).collect()
-                )
-            }
-
-            (TensorValue::Scalar(s), TensorValue::Vector1D(v)) => {
-                TensorValue::Vector1D(v.iter().map(|x| s * x).collect())
-            }
-            (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
-                TensorValue::Vector1D(v.iter().map(|x| x * s).collect())
-            }
-            (TensorValue::Scalar(s), TensorValue::Matrix2D(m)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| s * x).collect()).collect()
-                )
-            }
-            (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| x * s).collect()).collect()
-                )
-            }
-            _ => panic!("Invalid multiply operation between types"),
-        };
+        let result_value = forward(&a.value, &b.value);
 
         let result = Self::from_value(result_value);
         {
@@ -56,11 +20,72 @@ impl Tensor {
             res_data.operation = Operation::Multiply;
             res_data.dependencies = vec![self.clone(), other.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(a: &TensorValue<T>, b: &TensorValue<T>) -> TensorValue<T> {
+    match (a, b) {
+
+        (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
+            TensorValue::Scalar(*a_val * *b_val)
+        }
+
+        (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
+            assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
+            TensorValue::Vector1D(
+                a_vec.iter().zip(b_vec).map(|(a, b)| *a * *b).collect()
+            )
+        }
+
+        (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
+            assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
+            assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
+            TensorValue::Matrix2D(
+                a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
+                    a_row.iter().zip(b_row).map(|(a, b)| *a * *b).collect()
+                }).collect()
+            )
+        }
+
+        (TensorValue::Scalar(s), TensorValue::Vector1D(v)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *s * *x).collect())
+        }
+        (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *x * *s).collect())
+        }
+        (TensorValue::Scalar(s), TensorValue::Matrix2D(m)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *s * *x).collect()).collect()
+            )
+        }
+        (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *x * *s).collect()).collect()
+            )
+        }
+        (TensorValue::Matrix2D(_), TensorValue::Vector1D(_)) => {
+            a.zip_map(b, |a, b| a * b)
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            assert!(a.same_shape(b), "Tensor shape mismatch");
+            a.zip_map(b, |a, b| a * b)
+        }
+        (TensorValue::Scalar(_), TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_)) | (TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_), TensorValue::Scalar(_)) => {
+            a.zip_map(b, |a, b| a * b)
+        }
+        _ => panic!("Invalid multiply operation between types"),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, a: &TensorValue<T>, b: &TensorValue<T>) {
+    if !out.zip_map_into(a, b, |a, b| a * b) {
+        *out = forward(a, b);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 2 {
@@ -76,20 +101,18 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
         (a_data.value.clone(), b_data.value.clone())
     };
 
-    match (&data.grad, &a_val, &b_val) {
+    let (a_grad, b_grad) = match (grad, &a_val, &b_val) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
-            a.data.borrow_mut().add_grad_scalar(grad * b_val)?;
-            b.data.borrow_mut().add_grad_scalar(grad * a_val)?;
+            (TensorValue::Scalar(*grad * *b_val), TensorValue::Scalar(*grad * *a_val))
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(a_val), TensorValue::Vector1D(b_val)) => {
-            let a_grad: Vec<_> = grad.iter().zip(b_val).map(|(g, b)| g * b).collect();
-            let b_grad: Vec<_> = grad.iter().zip(a_val).map(|(g, a)| g * a).collect();
-            a.data.borrow_mut().add_grad(TensorValue::Vector1D(a_grad))?;
-            b.data.borrow_mut().add_grad(TensorValue::Vector1D(b_grad))?;
+            let a_grad: Vec<_> = grad.iter().zip(b_val).map(|(g, b)| *g * *b).collect();
+            let b_grad: Vec<_> = grad.iter().zip(a_val).map(|(g, a)| *g * *a).collect();
+            (TensorValue::Vector1D(a_grad), TensorValue::Vector1D(b_grad))
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(a_val), TensorValue::Matrix2D(b_val)) => {
-            let mut a_grad = vec![vec![0.0; a_val[0].len()]; a_val.len()];
-            let mut b_grad = vec![vec![0.0; b_val[0].len()]; b_val.len()];
+            let mut a_grad = vec![vec![T::zero(); a_val[0].len()]; a_val.len()];
+            let mut b_grad = vec![vec![T::zero(); b_val[0].len()]; b_val.len()];
 
             for i in 0..grad.len() {
                 for j in 0..grad[0].len() {
@@ -98,10 +121,37 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
                 }
             }
 
-            a.data.borrow_mut().add_grad(TensorValue::Matrix2D(a_grad))?;
-            b.data.borrow_mut().add_grad(TensorValue::Matrix2D(b_grad))?;
+            (TensorValue::Matrix2D(a_grad), TensorValue::Matrix2D(b_grad))
         }
-        _ => panic!("Unsupported multiply backward combination"),
-    }
-    Ok(())
-}
\ No newline at end of file
+        // Broadcast operands: the gradient is summed back to the operand's shape
+        // when it is accumulated.
+        _ => {
+            let a_grad = grad.zip_map(&b_val, |g, b| g * b);
+            let b_grad = grad.zip_map(&a_val, |g, a| g * a);
+            (a_grad, b_grad)
+        }
+    };
+    Ok(vec![a_grad, b_grad])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let a = &data.dependencies[0];
+    let b = &data.dependencies[1];
+
+    Ok(vec![
+        reduce_grad(grad.multiply(b)?, &shape_of(a))?,
+        reduce_grad(grad.multiply(a)?, &shape_of(b))?,
+    ])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    let a_data = data.dependencies[0].data.borrow();
+    let a = &a_data.value;
+    let b_data = data.dependencies[1].data.borrow();
+    let b = &b_data.value;
+    let da_b = tangents[0].zip_map(b, |t, b| t * b);
+    let a_db = a.zip_map(&tangents[1], |a, t| a * t);
+    da_b.zip_map(&a_db, |x, y| x + y)
+}
@@ -0,0 +1,206 @@
+// Kernels shared by the normalization ops. Every element `i` is normalized with
+// the statistics of group `group_of(i)` and then scaled and shifted by the
+// affine parameters at `param_of(i)`; the ops differ only in those two maps.
+
+use crate::tensor::dtype::Float;
+use crate::tensor::index::IntTensor;
+use super::super::{Tensor, TensorValue};
+use super::flat;
+use anyhow::Result;
+
+/// Mean and biased variance of every group, the variance taken around the
+/// mean in a second pass rather than as `E[x²] - E[x]²`, which cancels badly.
+pub(crate) fn moments<T: Float>(x: &[T], groups: usize, group_of: impl Fn(usize) -> usize) -> Vec<(T, T)> {
+    let mut counts = vec![0usize; groups];
+    let mut sums = vec![T::zero(); groups];
+    for (i, v) in x.iter().enumerate() {
+        counts[group_of(i)] += 1;
+        sums[group_of(i)] += *v;
+    }
+    let means: Vec<T> = sums.iter().zip(&counts).map(|(s, n)| *s / T::from_f64(*n as f64)).collect();
+
+    let mut squares = vec![T::zero(); groups];
+    for (i, v) in x.iter().enumerate() {
+        let d = *v - means[group_of(i)];
+        squares[group_of(i)] += d * d;
+    }
+    means.into_iter().zip(squares).zip(counts)
+        .map(|((mean, sq), n)| (mean, sq / T::from_f64(n as f64)))
+        .collect()
+}
+
+/// `(mean, 1 / sqrt(var + eps))` for every group.
+pub(crate) fn inv_std<T: Float>(moments: &[(T, T)], eps: T) -> Vec<(T, T)> {
+    moments.iter().map(|(mean, var)| (*mean, T::one() / (*var + eps).sqrt())).collect()
+}
+
+pub(crate) fn normalize<T: Float>(
+    x: &[T],
+    stats: &[(T, T)],
+    group_of: impl Fn(usize) -> usize,
+    weight: &[T],
+    bias: &[T],
+    param_of: impl Fn(usize) -> usize,
+) -> Vec<T> {
+    x.iter().enumerate().map(|(i, v)| {
+        let (mean, r) = stats[group_of(i)];
+        let p = param_of(i);
+        (*v - mean) * r * weight[p] + bias[p]
+    }).collect()
+}
+
+/// Gradients for the input, weight and bias given the upstream `grad`.
+///
+/// With the statistics computed from `x` itself (`through_stats`), the input
+/// gradient is `r * (ĝ - mean(ĝ) - x̂ * mean(ĝ * x̂))` per group, where
+/// `ĝ = grad * weight` and `x̂` is the normalized input; otherwise the
+/// statistics are constants and it is just `r * ĝ`.
+pub(crate) fn normalize_backward<T: Float>(
+    x: &[T],
+    grad: &[T],
+    stats: &[(T, T)],
+    group_of: impl Fn(usize) -> usize,
+    weight: &[T],
+    param_of: impl Fn(usize) -> usize,
+    through_stats: bool,
+) -> (Vec<T>, Vec<T>, Vec<T>) {
+    let x_hat: Vec<T> = x.iter().enumerate()
+        .map(|(i, v)| { let (mean, r) = stats[group_of(i)]; (*v - mean) * r })
+        .collect();
+    let g_hat: Vec<T> = grad.iter().enumerate().map(|(i, g)| *g * weight[param_of(i)]).collect();
+
+    let mut d_weight = vec![T::zero(); weight.len()];
+    let mut d_bias = vec![T::zero(); weight.len()];
+    for (i, g) in grad.iter().enumerate() {
+        d_weight[param_of(i)] += *g * x_hat[i];
+        d_bias[param_of(i)] += *g;
+    }
+
+    let dx = if through_stats {
+        let (mean_g, mean_gx) = group_means(&g_hat, &x_hat, stats.len(), &group_of);
+        (0..x.len()).map(|i| {
+            let s = group_of(i);
+            stats[s].1 * (g_hat[i] - mean_g[s] - x_hat[i] * mean_gx[s])
+        }).collect()
+    } else {
+        g_hat.iter().enumerate().map(|(i, g)| stats[group_of(i)].1 * *g).collect()
+    };
+    (dx, d_weight, d_bias)
+}
+
+/// `normalize_backward` built out of tensor ops for `backward_create_graph`,
+/// given the output `tensor` of a normalization of `[input, weight, bias]`.
+/// Per-group sums are a `scatter_add` into one slot per group, spread back
+/// over the elements with `gather`. With fixed `running` `(mean, var)` the
+/// statistics are constants, as in `normalize_backward`.
+pub(crate) fn normalize_backward_graph<T: Float>(
+    tensor: &Tensor<T>,
+    grad: &Tensor<T>,
+    eps: T,
+    groups: usize,
+    group_of: impl Fn(usize) -> usize,
+    param_of: impl Fn(usize) -> usize,
+    running: Option<&[(T, T)]>,
+) -> Result<Vec<Tensor<T>>> {
+    let dependencies = tensor.data.borrow().dependencies.clone();
+    let (input, weight) = (&dependencies[0], &dependencies[1]);
+    let numel: usize = input.shape().iter().product();
+    let params: usize = weight.shape().iter().product();
+    let group_index = IntTensor::vector((0..numel).map(|i| group_of(i) as i64).collect());
+    let param_index = IntTensor::vector((0..numel).map(|i| param_of(i) as i64).collect());
+
+    let mut counts = vec![0usize; groups];
+    (0..numel).for_each(|i| counts[group_of(i)] += 1);
+    let inv_counts = Tensor::vector(counts.iter().map(|n| T::one() / T::from_f64(*n as f64)).collect());
+    let group_mean = |v: &Tensor<T>| -> Result<Tensor<T>> {
+        Tensor::from_value(TensorValue::full(&[groups], T::zero()))
+            .scatter_add(0, &group_index, v)?
+            .multiply(&inv_counts)?
+            .gather(0, &group_index)
+    };
+    let param_sum = |v: &Tensor<T>| -> Result<Tensor<T>> {
+        Tensor::from_value(TensorValue::full(&[params], T::zero()))
+            .scatter_add(0, &param_index, v)?
+            .reshape(&weight.shape())
+    };
+
+    let x = flat(input)?;
+    let (centered, r) = match running {
+        None => {
+            let centered = x.sub(&group_mean(&x)?)?;
+            let var = group_mean(&centered.multiply(&centered)?)?;
+            let r = var.add(&Tensor::scalar(eps))?.pow(T::from_f64(-0.5))?;
+            (centered, r)
+        }
+        Some(running) => {
+            let stats = inv_std(running, eps);
+            let mean = Tensor::vector((0..numel).map(|i| stats[group_of(i)].0).collect());
+            let r = Tensor::vector((0..numel).map(|i| stats[group_of(i)].1).collect());
+            (x.sub(&mean)?, r)
+        }
+    };
+    let x_hat = centered.multiply(&r)?;
+    let g = flat(grad)?;
+    let g_hat = g.multiply(&flat(weight)?.gather(0, &param_index)?)?;
+
+    let dx = match running {
+        None => {
+            let mean_g = group_mean(&g_hat)?;
+            let mean_gx = group_mean(&g_hat.multiply(&x_hat)?)?;
+            g_hat.sub(&mean_g)?.sub(&x_hat.multiply(&mean_gx)?)?.multiply(&r)?
+        }
+        Some(_) => g_hat.multiply(&r)?,
+    };
+    Ok(vec![
+        dx.reshape(&input.shape())?,
+        param_sum(&g.multiply(&x_hat)?)?,
+        param_sum(&g)?,
+    ])
+}
+
+/// Forward-mode counterpart of `normalize_backward`: the output tangent for
+/// input, weight and bias tangents.
+pub(crate) fn normalize_jvp<T: Float>(
+    x: &[T],
+    stats: &[(T, T)],
+    group_of: impl Fn(usize) -> usize,
+    weight: &[T],
+    param_of: impl Fn(usize) -> usize,
+    tangents: [&[T]; 3],
+    through_stats: bool,
+) -> Vec<T> {
+    let [dx, d_weight, d_bias] = tangents;
+    let x_hat: Vec<T> = x.iter().enumerate()
+        .map(|(i, v)| { let (mean, r) = stats[group_of(i)]; (*v - mean) * r })
+        .collect();
+    let (mean_dx, mean_xdx) = if through_stats {
+        group_means(dx, &x_hat, stats.len(), &group_of)
+    } else {
+        (vec![T::zero(); stats.len()], vec![T::zero(); stats.len()])
+    };
+
+    (0..x.len()).map(|i| {
+        let s = group_of(i);
+        let p = param_of(i);
+        let dx_hat = stats[s].1 * (dx[i] - mean_dx[s] - x_hat[i] * mean_xdx[s]);
+        weight[p] * dx_hat + d_weight[p] * x_hat[i] + d_bias[p]
+    }).collect()
+}
+
+/// Per-group `mean(a)` and `mean(a * b)`.
+fn group_means<T: Float>(a: &[T], b: &[T], groups: usize, group_of: impl Fn(usize) -> usize) -> (Vec<T>, Vec<T>) {
+    let mut counts = vec![0usize; groups];
+    let mut sum_a = vec![T::zero(); groups];
+    let mut sum_ab = vec![T::zero(); groups];
+    for i in 0..a.len() {
+        let s = group_of(i);
+        counts[s] += 1;
+        sum_a[s] += a[i];
+        sum_ab[s] += a[i] * b[i];
+    }
+    let n = |s: usize| T::from_f64(counts[s] as f64);
+    (
+        (0..groups).map(|s| sum_a[s] / n(s)).collect(),
+        (0..groups).map(|s| sum_ab[s] / n(s)).collect(),
+    )
+}
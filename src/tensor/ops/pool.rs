@@ -0,0 +1,135 @@
+// Window bookkeeping shared by the pooling ops. Inputs are `[batch, channels,
+// length]` for 1D pooling and `[batch, channels, height, width]` for 2D; each
+// channel of each sample is a plane that is pooled on its own.
+
+use std::ops::Range;
+use anyhow::{bail, Result};
+
+/// How a pooling op places its windows, the same along every spatial dimension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pooling {
+    /// Windows of `kernel_size` elements every `stride` elements. Padded
+    /// positions never take part: they are not a max candidate and are not
+    /// counted in an average.
+    Window { kernel_size: usize, stride: usize, padding: usize },
+    /// A fixed number of windows per spatial dimension, spread evenly over
+    /// the input whatever its size.
+    Adaptive(Vec<usize>),
+}
+
+impl Pooling {
+    /// Non-overlapping windows of `kernel_size`.
+    pub fn window(kernel_size: usize) -> Self {
+        Pooling::Window { kernel_size, stride: kernel_size, padding: 0 }
+    }
+
+    fn ranges(&self, dim: usize, size: usize) -> Result<Vec<Range<usize>>> {
+        match self {
+            Pooling::Window { kernel_size, stride, padding } => {
+                if *kernel_size == 0 || *stride == 0 || 2 * padding > *kernel_size {
+                    bail!("Invalid pooling window: kernel {}, stride {}, padding {}", kernel_size, stride, padding);
+                }
+                if size + 2 * padding < *kernel_size {
+                    bail!("Pooling window of {} does not fit input of size {} with padding {}", kernel_size, size, padding);
+                }
+                let count = (size + 2 * padding - kernel_size) / stride + 1;
+                Ok((0..count).map(|o| {
+                    let start = (o * stride).saturating_sub(*padding);
+                    let end = (o * stride + kernel_size - padding).min(size);
+                    start..end
+                }).collect())
+            }
+            Pooling::Adaptive(output_size) => {
+                let count = output_size[dim];
+                if count == 0 || count > size {
+                    bail!("Cannot pool a dimension of size {} into {} windows", size, count);
+                }
+                Ok((0..count).map(|o| (o * size / count)..((o + 1) * size).div_ceil(count)).collect())
+            }
+        }
+    }
+}
+
+/// The windows of one pooling op: for every output position of a plane, the
+/// offsets of the input elements it covers within that plane.
+pub(crate) struct PoolWindows {
+    pub planes: usize,
+    pub plane_len: usize,
+    pub output_shape: Vec<usize>,
+    pub windows: Vec<Vec<usize>>,
+}
+
+impl PoolWindows {
+    pub fn new(input_shape: &[usize], pooling: &Pooling) -> Result<Self> {
+        if input_shape.len() < 3 || input_shape.len() > 4 {
+            bail!("Pooling expects a [batch, channels, length] or [batch, channels, height, width] input, got {:?}", input_shape);
+        }
+        let spatial = &input_shape[2..];
+        if let Pooling::Adaptive(output_size) = pooling {
+            if output_size.len() != spatial.len() {
+                bail!("Adaptive pooling needs one output size per spatial dimension, got {:?} for input {:?}", output_size, input_shape);
+            }
+        }
+
+        let (height, width) = match spatial {
+            [length] => (1, *length),
+            [height, width] => (*height, *width),
+            _ => unreachable!(),
+        };
+        let rows = if spatial.len() == 1 { std::iter::once(0..1).collect() } else { pooling.ranges(0, height)? };
+        let cols = pooling.ranges(spatial.len() - 1, width)?;
+
+        let windows = rows.iter().flat_map(|rows| {
+            cols.iter().map(move |cols| {
+                rows.clone().flat_map(|y| cols.clone().map(move |x| y * width + x)).collect()
+            })
+        }).collect();
+
+        let mut output_shape = input_shape[..2].to_vec();
+        if spatial.len() == 2 {
+            output_shape.push(rows.len());
+        }
+        output_shape.push(cols.len());
+
+        Ok(PoolWindows {
+            planes: input_shape[0] * input_shape[1],
+            plane_len: height * width,
+            output_shape,
+            windows,
+        })
+    }
+
+    /// Calls `f(output_offset, plane_offset, window)` for every output
+    /// element; the input elements it covers are `plane_offset + window[i]`.
+    pub fn for_each(&self, mut f: impl FnMut(usize, usize, &[usize])) {
+        for plane in 0..self.planes {
+            for (o, window) in self.windows.iter().enumerate() {
+                f(plane * self.windows.len() + o, plane * self.plane_len, window);
+            }
+        }
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.planes * self.windows.len()
+    }
+}
+
+#[test]
+fn adaptive_windows_cover_the_input() -> Result<()> {
+    let windows = PoolWindows::new(&[1, 1, 5], &Pooling::Adaptive(vec![3]))?;
+    assert_eq!(windows.windows, vec![vec![0, 1], vec![1, 2, 3], vec![3, 4]]);
+
+    let padded = PoolWindows::new(&[1, 1, 4], &Pooling::Window { kernel_size: 3, stride: 2, padding: 1 })?;
+    assert_eq!(padded.windows, vec![vec![0, 1], vec![1, 2, 3]]);
+
+    let grid = PoolWindows::new(&[2, 3, 4, 4], &Pooling::window(2))?;
+    assert_eq!(grid.output_shape, vec![2, 3, 2, 2]);
+    assert_eq!(grid.windows[3], vec![10, 11, 14, 15]);
+
+    assert!(PoolWindows::new(&[1, 1, 2], &Pooling::window(3)).is_err());
+    assert!(PoolWindows::new(&[1, 1, 4], &Pooling::window(0)).is_err());
+    assert!(PoolWindows::new(&[1, 1, 2], &Pooling::Adaptive(vec![3])).is_err());
+    assert!(PoolWindows::new(&[1, 1, 4, 4], &Pooling::Adaptive(vec![2])).is_err());
+    assert!(PoolWindows::new(&[4, 4], &Pooling::window(2)).is_err());
+    Ok(())
+}
@@ -1,29 +1,14 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
-impl Tensor {
-    pub fn pow(&self, value: f64) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn pow(&self, value: T) -> Result<Tensor<T>> {
         let a = self.data.borrow();
 
-        let result_value = match &a.value {
-            TensorValue::Scalar(v) => {
-                let s = v.powf(value);
-                TensorValue::Scalar(s)
-            }
-            TensorValue::Vector1D(v) => {
-                TensorValue::Vector1D(
-                    v.iter().map(|x| x.powf(value)).collect()
-                )
-            }
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| {
-                        row.iter().map(|x| x.powf(value)).collect()
-                    }).collect()
-                )
-            }
-            TensorValue::Tensor3D(_) => todo!()
-        };
+        let result_value = forward(&a.value, value);
 
         let result = Self::from_value(result_value);
         {
@@ -32,11 +17,40 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor, exponent: f64) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>, exponent: T) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(v) => {
+            let s = v.powf(exponent);
+            TensorValue::Scalar(s)
+        }
+        TensorValue::Vector1D(v) => {
+            TensorValue::Vector1D(
+                v.iter().map(|x| x.powf(exponent)).collect()
+            )
+        }
+        TensorValue::Matrix2D(m) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| {
+                    row.iter().map(|x| x.powf(exponent)).collect()
+                }).collect()
+            )
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => input.map(|x| x.powf(exponent))
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, input: &TensorValue<T>, exponent: T) {
+    if !out.map_into(input, |x| x.powf(exponent)) {
+        *out = forward(input, exponent);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, exponent: T) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
@@ -50,27 +64,27 @@ pub fn backward(tensor: &Tensor, exponent: f64) -> Result<()>{
     };
 
     // value * x
-    match (&data.grad, &x_val) {
+    let grad_x = match (grad, &x_val) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(s)) => {
-            let dx = if s.abs() < 1e-12 && exponent < 1.0 {
-                0.0
+            let dx = if s.abs() < T::from_f64(1e-12) && exponent < T::one() {
+                T::zero()
             } else {
-                grad * exponent * s.powf(exponent - 1.0)
+                *grad * exponent * s.powf(exponent - T::one())
             };
-            x.data.borrow_mut().add_grad_scalar(dx)?;
+            TensorValue::Scalar(dx)
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(s)) => {
             assert_eq!(s.len(), grad.len(), "Vector length mismatch in Pow backward");
 
             let dx_vec: Vec<_> = s.iter().zip(grad).map(|(x_, g_)| {
-                if x_.abs() < 1e-12 && exponent < 1.0 {
-                    0.0
+                if x_.abs() < T::from_f64(1e-12) && exponent < T::one() {
+                    T::zero()
                 }else {
-                    g_ * exponent * x_.powf(exponent - 1.0)
+                    *g_ * exponent * x_.powf(exponent - T::one())
                 }
             }).collect();
 
-            x.data.borrow_mut().add_grad(TensorValue::Vector1D(dx_vec))?
+            TensorValue::Vector1D(dx_vec)
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(s)) => {
             let dx_mat = s.iter()
@@ -79,16 +93,61 @@ pub fn backward(tensor: &Tensor, exponent: f64) -> Result<()>{
                     x_row.iter()
                         .zip(g_row)
                         .map(|(x_, g_)| {
-                            if x_.abs() < 1e-12 && exponent < 1.0 {
-                                0.0
+                            if x_.abs() < T::from_f64(1e-12) && exponent < T::one() {
+                                T::zero()
                             }else {
-                                g_ * exponent * x_.powf(exponent - 1.0)
+                                *g_ * exponent * x_.powf(exponent - T::one())
                             }
                         }).collect()
                 }).collect();
-            x.data.borrow_mut().add_grad(TensorValue::Matrix2D(dx_mat))?;
+            TensorValue::Matrix2D(dx_mat)
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            grad.zip_map(&x_val, |g_, x_| {
+                if x_.abs() < T::from_f64(1e-12) && exponent < T::one() {
+                    T::zero()
+                } else {
+                    g_ * exponent * x_.powf(exponent - T::one())
+                }
+            })
         }
         _ => panic!("Invalid sigmoid gradient combination"),
+    };
+    Ok(vec![grad_x])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>, exponent: T) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let x = &data.dependencies[0];
+
+    let local = x.pow(exponent - T::one())?.multiply(&Tensor::scalar(exponent))?;
+    Ok(vec![grad.multiply(&local)?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>], exponent: T) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    let x_data = data.dependencies[0].data.borrow();
+    let x = &x_data.value;
+    tangents[0].zip_map(x, |t, x| t * exponent * x.powf(exponent - T::one()))
+}
+
+#[test]
+fn pow_and_log_work_on_feature_maps() -> Result<()> {
+    let data: Vec<f64> = (0..2 * 3 * 2 * 2).map(|i| 0.5 + (i as f64 * 0.7).sin().abs()).collect();
+    let x = Tensor::from_value(TensorValue::from_flat(&[2, 3, 2, 2], &data));
+    crate::tensor::gradcheck::gradcheck(
+        |inputs| inputs[0].pow(3.0)?.log(2.0)?.sum(),
+        std::slice::from_ref(&x),
+        1e-6,
+        1e-5,
+    )?;
+
+    // d/dx sum(d/dx sum(log2(x^3))) = -3 / (x^2 ln 2)
+    let y = x.pow(3.0)?.log(2.0)?.sum()?;
+    let dx = crate::tensor::autograd::grad(&[y], std::slice::from_ref(&x))?;
+    let d2x = crate::tensor::autograd::grad(&[dx[0].sum()?], std::slice::from_ref(&x))?;
+    for (actual, x) in d2x[0].value().flatten().iter().zip(data) {
+        approx::assert_abs_diff_eq!(*actual, -3.0 / (x * x * 2f64.ln()), epsilon = 1e-10);
     }
     Ok(())
-}
\ No newline at end of file
+}
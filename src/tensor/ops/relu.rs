@@ -1,37 +1,15 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn relu(&self) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn relu(&self) -> Result<Tensor<T>> {
         let a = self.data.borrow();
 
-        let result_value = match &a.value {
-            TensorValue::Scalar(v) => {
-                TensorValue::Scalar(v.max(0.0))
-            }
-
-            TensorValue::Vector1D(v) => {
-                TensorValue::Vector1D(
-                    v.iter()
-                        .map(|x| x.max(0.0))
-                        .collect()
-                )
-            }
-
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(
-                    m.iter()
-                        .map(|row| {
-                            row.iter()
-                                .map(|x| x.max(0.0))
-                                .collect()
-                        })
-                        .collect()
-                )
-            }
-            TensorValue::Tensor3D(_) => todo!()
-        };
+        let result_value = forward(&a.value);
 
         let result = Self::from_value(result_value);
 
@@ -41,36 +19,71 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(v) => {
+            TensorValue::Scalar(v.max(T::zero()))
+        }
+
+        TensorValue::Vector1D(v) => {
+            TensorValue::Vector1D(
+                v.iter()
+                    .map(|x| x.max(T::zero()))
+                    .collect()
+            )
+        }
+
+        TensorValue::Matrix2D(m) => {
+            TensorValue::Matrix2D(
+                m.iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|x| x.max(T::zero()))
+                            .collect()
+                    })
+                    .collect()
+            )
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => input.map(|x| x.max(T::zero())),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, input: &TensorValue<T>) {
+    if !out.map_into(input, |x| x.max(T::zero())) {
+        *out = forward(input);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("ReLU operation requires exactly 1 dependency");
     }
 
-    let x = &dependencies[0];
     let relu_output = match &data.value {
         TensorValue::Scalar(s) => TensorValue::Scalar(*s),
         TensorValue::Vector1D(v) => TensorValue::Vector1D(v.clone()),
         TensorValue::Matrix2D(m) => TensorValue::Matrix2D(m.clone()),
-        TensorValue::Tensor3D(_) => todo!()
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => data.value.clone(),
     };
 
-    match (&data.grad, &relu_output) {
+    let grad_x = match (grad, &relu_output) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(s)) => {
-            let grad_x = grad * if *s > 0.0 { 1.0 } else { 0.0 };
-            x.data.borrow_mut().add_grad_scalar(grad_x)?;
+            let grad_x = *grad * if *s > T::zero() { T::one() } else { T::zero() };
+            TensorValue::Scalar(grad_x)
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(s)) => {
             let grad_x: Vec<_> = grad.iter()
                 .zip(s)
-                .map(|(g, s)| g * if *s > 0.0 { 1.0 } else { 0.0 })
+                .map(|(g, s)| *g * if *s > T::zero() { T::one() } else { T::zero() })
                 .collect();
-            x.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_x))?;
+            TensorValue::Vector1D(grad_x)
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(s)) => {
             let grad_x: Vec<Vec<_>> = grad.iter()
@@ -78,13 +91,27 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
                 .map(|(g_row, s_row)| {
                     g_row.iter()
                         .zip(s_row)
-                        .map(|(g, s)| g * if *s > 0.0 { 1.0 } else { 0.0 })
+                        .map(|(g, s)| *g * if *s > T::zero() { T::one() } else { T::zero() })
                         .collect()
                 })
                 .collect();
-            x.data.borrow_mut().add_grad(TensorValue::Matrix2D(grad_x))?;
+            TensorValue::Matrix2D(grad_x)
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            grad.zip_map(&relu_output, |g, s| if s > T::zero() { g } else { T::zero() })
         }
         _ => panic!("Invalid relu gradient combination"),
-    }
-    Ok(())
-}
\ No newline at end of file
+    };
+    Ok(vec![grad_x])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    // The mask is piecewise constant, so it does not need to be differentiable.
+    let mask = tensor.data.borrow().value.map(|s| if s > T::zero() { T::one() } else { T::zero() });
+    Ok(vec![grad.multiply(&Tensor::from_value(mask))?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    tangents[0].zip_map(&data.value, |t, y| if y > T::zero() { t } else { T::zero() })
+}
@@ -0,0 +1,68 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::operation::Operation;
+use super::super::{Tensor, TensorValue};
+use anyhow::Result;
+use super::shape_of;
+
+impl<T: Float> Tensor<T> {
+    /// The same elements in row-major order, laid out as `shape`, e.g. a
+    /// `[batch, channels, length]` feature map flattened to
+    /// `[batch, channels * length]` for a `Linear` layer.
+    #[track_caller]
+    pub fn reshape(&self, shape: &[usize]) -> Result<Tensor<T>> {
+        let data = self.data.borrow();
+        let numel: usize = data.value.shape().iter().product();
+        if numel != shape.iter().product::<usize>() {
+            panic!("Cannot reshape a tensor of shape {:?} to {:?}", data.value.shape(), shape);
+        }
+
+        let result_value = forward(&data.value, shape);
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::Reshape(shape.to_vec());
+            res_data.dependencies = vec![self.clone()];
+        }
+
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, shape: &[usize]) -> TensorValue<T> {
+    TensorValue::from_flat(shape, &input.flatten())
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let dependencies = &data.dependencies;
+    if dependencies.len() != 1 {
+        panic!("Reshape operation requires exactly 1 dependency");
+    }
+
+    Ok(vec![forward(grad, &shape_of(&dependencies[0]))])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    Ok(vec![grad.reshape(&shape_of(&data.dependencies[0]))?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], shape: &[usize]) -> TensorValue<T> {
+    forward(&tangents[0], shape)
+}
+
+#[test]
+fn reshape_round_trips_gradients() -> Result<()> {
+    let x = Tensor::tensor3d(vec![
+        vec![vec![1.0, 2.0], vec![3.0, 4.0]],
+        vec![vec![5.0, 6.0], vec![7.0, 8.0]],
+    ]);
+    let flat = x.reshape(&[2, 4])?;
+    assert_eq!(flat.to_matrix()?, vec![vec![1.0, 2.0, 3.0, 4.0], vec![5.0, 6.0, 7.0, 8.0]]);
+
+    flat.multiply(&flat)?.sum()?.backward()?;
+    assert_eq!(x.grad().unwrap().value().flatten(), vec![2.0, 4.0, 6.0, 8.0, 10.0, 12.0, 14.0, 16.0]);
+    Ok(())
+}
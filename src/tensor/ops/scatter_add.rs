@@ -0,0 +1,74 @@
+use crate::tensor::dtype::Float;
+use std::rc::Rc;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::index::{check_gather_index, gather_offsets, IntTensor};
+use crate::tensor::operation::Operation;
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+impl<T: Float> Tensor<T> {
+    /// Adds the elements of `src` into a copy of `self` at the positions
+    /// `gather` with the same `dim` and `index` would read from, so
+    /// `scatter_add` is the adjoint of `gather`. `src` has the shape of `index`.
+    #[track_caller]
+    pub fn scatter_add(&self, dim: usize, index: &IntTensor, src: &Tensor<T>) -> Result<Tensor<T>> {
+        let a = self.data.borrow();
+        let b = src.data.borrow();
+        check_gather_index(&a.value.shape(), dim, index)?;
+        if b.value.shape() != index.shape() {
+            bail!("scatter_add source of shape {:?} must have the shape of the index {:?}", b.value.shape(), index.shape());
+        }
+        let result_value = forward(&a.value, &b.value, dim, index);
+        let result = Self::from_value(result_value);
+        {
+            let mut res_data = result.data.borrow_mut();
+            res_data.operation = Operation::ScatterAdd(dim, Rc::new(index.clone()));
+            res_data.dependencies = vec![self.clone(), src.clone()];
+        }
+
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+pub fn forward<T: Float>(input: &TensorValue<T>, src: &TensorValue<T>, dim: usize, index: &IntTensor) -> TensorValue<T> {
+    let mut values = input.flatten();
+    for (offset, x) in gather_offsets(&input.shape(), dim, index).into_iter().zip(src.flatten()) {
+        values[offset] += x;
+    }
+    TensorValue::from_flat(&input.shape(), &values)
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, dim: usize, index: &IntTensor) -> Result<Vec<TensorValue<T>>>{
+    let data = tensor.data.borrow();
+    let dependencies = &data.dependencies;
+    if dependencies.len() != 2 {
+        panic!("ScatterAdd operation requires exactly 2 dependencies");
+    }
+
+    Ok(vec![grad.clone(), super::gather::forward(grad, dim, index)])
+}
+
+pub fn backward_graph<T: Float>(grad: &Tensor<T>, dim: usize, index: &IntTensor) -> Result<Vec<Tensor<T>>>{
+    Ok(vec![grad.clone(), grad.gather(dim, index)?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], dim: usize, index: &IntTensor) -> TensorValue<T> {
+    forward(&tangents[0], &tangents[1], dim, index)
+}
+
+#[test]
+fn scatter_add_accumulates_repeated_indices() -> Result<()> {
+    let base = Tensor::vector(vec![1.0, 1.0, 1.0]);
+    let src = Tensor::vector(vec![2.0, 3.0, 4.0]);
+    let out = base.scatter_add(0, &IntTensor::vector(vec![2, 0, 2]), &src)?;
+    assert_eq!(out.to_vec()?, vec![4.0, 1.0, 7.0]);
+
+    out.multiply(&Tensor::vector(vec![1.0, 2.0, 3.0]))?.sum()?.backward()?;
+    assert_eq!(base.grad().unwrap().to_vec()?, vec![1.0, 2.0, 3.0]);
+    assert_eq!(src.grad().unwrap().to_vec()?, vec![3.0, 1.0, 3.0]);
+
+    assert!(base.scatter_add(0, &IntTensor::vector(vec![0, 1]), &src).is_err());
+    assert!(base.scatter_add(0, &IntTensor::vector(vec![0, 1, 3]), &src).is_err());
+    Ok(())
+}
@@ -1,30 +1,15 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn sigmoid(&self) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn sigmoid(&self) -> Result<Tensor<T>> {
         let a = self.data.borrow();
 
-        let result_value = match &a.value {
-            TensorValue::Scalar(v) => {
-                let s = 1.0 / (1.0 + (-v).exp());
-                TensorValue::Scalar(s)
-            }
-            TensorValue::Vector1D(v) => {
-                TensorValue::Vector1D(
-                    v.iter().map(|x| 1.0 / (1.0 + (-x).exp())).collect()
-                )
-            }
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| {
-                        row.iter().map(|x| 1.0 / (1.0 + (-x).exp())).collect()
-                    }).collect()
-                )
-            }
-            TensorValue::Tensor3D(_) => todo!()
-        };
+        let result_value = forward(&a.value);
 
         let result = Self::from_value(result_value);
         {
@@ -32,36 +17,64 @@ impl Tensor {
             res_data.operation = Operation::Sigmoid;
             res_data.dependencies = vec![self.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(v) => {
+            let s = T::one() / (T::one() + (-*v).exp());
+            TensorValue::Scalar(s)
+        }
+        TensorValue::Vector1D(v) => {
+            TensorValue::Vector1D(
+                v.iter().map(|x| T::one() / (T::one() + (-*x).exp())).collect()
+            )
+        }
+        TensorValue::Matrix2D(m) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| {
+                    row.iter().map(|x| T::one() / (T::one() + (-*x).exp())).collect()
+                }).collect()
+            )
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => input.map(|x| T::one() / (T::one() + (-x).exp())),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, input: &TensorValue<T>) {
+    if !out.map_into(input, |x| T::one() / (T::one() + (-x).exp())) {
+        *out = forward(input);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("Sigmoid operation requires exactly 1 dependency");
     }
 
-    let x = &dependencies[0];
     let sigmoid_output = match &data.value {
         TensorValue::Scalar(s) => TensorValue::Scalar(*s),
         TensorValue::Vector1D(v) => TensorValue::Vector1D(v.clone()),
         TensorValue::Matrix2D(m) => TensorValue::Matrix2D(m.clone()),
-        TensorValue::Tensor3D(_) => todo!()
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => data.value.clone(),
     };
 
-    match (&data.grad, &sigmoid_output) {
+    let grad_x = match (grad, &sigmoid_output) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(s)) => {
-            let grad_x = grad * s * (1.0 - s);
-            x.data.borrow_mut().add_grad_scalar(grad_x)?;
+            let grad_x = *grad * *s * (T::one() - *s);
+            TensorValue::Scalar(grad_x)
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(s)) => {
             let grad_x: Vec<_> = grad.iter()
                 .zip(s)
-                .map(|(g, s_)| g * s_ * (1.0 - s_))
+                .map(|(g, s_)| *g * *s_ * (T::one() - *s_))
                 .collect();
-            x.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_x))?;
+            TensorValue::Vector1D(grad_x)
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(s)) => {
             let grad_x: Vec<Vec<_>> = grad.iter()
@@ -69,13 +82,27 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
                 .map(|(g_row, s_row)| {
                     g_row.iter()
                         .zip(s_row)
-                        .map(|(g, s_)| g * s_ * (1.0 - s_))
+                        .map(|(g, s_)| *g * *s_ * (T::one() - *s_))
                         .collect()
                 })
                 .collect();
-            x.data.borrow_mut().add_grad(TensorValue::Matrix2D(grad_x))?;
+            TensorValue::Matrix2D(grad_x)
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            grad.zip_map(&sigmoid_output, |g, s| g * s * (T::one() - s))
         }
         _ => panic!("Invalid sigmoid gradient combination"),
-    }
-    Ok(())
-}
\ No newline at end of file
+    };
+    Ok(vec![grad_x])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    // sigmoid'(x) = s * (1 - s), expressed through the output node itself.
+    let one_minus = Tensor::scalar(T::one()).sub(tensor)?;
+    Ok(vec![grad.multiply(&tensor.multiply(&one_minus)?)?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    tangents[0].zip_map(&data.value, |t, s| t * s * (T::one() - s))
+}
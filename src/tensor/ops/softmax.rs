@@ -1,47 +1,16 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use crate::tensor::utils::transpose;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
+use super::{reduce_grad, shape_of};
 
-impl Tensor {
-    pub fn softmax(&self) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn softmax(&self) -> Result<Tensor<T>> {
         let data = self.data.borrow();
-        let result_value = match &data.value {
-            TensorValue::Vector1D(v) => {
-                let max = v.iter().fold(f64::MIN, |a, &b| a.max(b));
-                let exps: Vec<f64> = v.iter().map(|x| (x - max).exp()).collect();
-
-                let sum: f64 = exps.iter().sum();
-                TensorValue::Vector1D(
-                    exps.into_iter().map(|x| x / sum).collect()
-                )
-            }
-            TensorValue::Matrix2D(m) => {
-
-                assert!(!m.is_empty(), "Matrix is empty");
-                let cols = m[0].len();
-                assert!(
-                    m.iter().all(|row| row.len() == cols),
-                    "Inconsistent matrix columns"
-                );
-
-                let transposed = transpose(m);
-                let processed_transposed: Vec<Vec<f64>> = transposed
-                    .into_iter()
-                    .map(|col| {
-                        let max = col.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-                        let exps: Vec<f64> = col.iter().map(|&x| (x - max).exp()).collect();
-
-                        let sum_exps: f64 = exps.iter().sum();
-                        exps.into_iter().map(|exp| exp / sum_exps).collect()
-                    }).collect();
-
-                let result = transpose(&processed_transposed);
-
-                TensorValue::Matrix2D(result)
-            }
-            _ => panic!("softmax only supported for 1D/2D"),
-        };
+        let result_value = forward(&data.value);
         let result = Self::from_value(result_value);
         {
             let mut res_data = result.data.borrow_mut();
@@ -49,17 +18,56 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()> {
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Vector1D(v) => {
+            let max = v.iter().fold(T::min_value(), |a, &b| a.max(b));
+            let exps: Vec<T> = v.iter().map(|x| (*x - max).exp()).collect();
+
+            let sum: T = exps.iter().copied().sum();
+            TensorValue::Vector1D(
+                exps.into_iter().map(|x| x / sum).collect()
+            )
+        }
+        TensorValue::Matrix2D(m) => {
+
+            assert!(!m.is_empty(), "Matrix is empty");
+            let cols = m[0].len();
+            assert!(
+                m.iter().all(|row| row.len() == cols),
+                "Inconsistent matrix columns"
+            );
+
+            let transposed = transpose(m);
+            let processed_transposed: Vec<Vec<T>> = transposed
+                .into_iter()
+                .map(|col| {
+                    let max = col.iter().fold(T::neg_infinity(), |a, &b| a.max(b));
+                    let exps: Vec<T> = col.iter().map(|&x| (x - max).exp()).collect();
+
+                    let sum_exps: T = exps.iter().copied().sum();
+                    exps.into_iter().map(|exp| exp / sum_exps).collect()
+                }).collect();
+
+            let result = transpose(&processed_transposed);
+
+            TensorValue::Matrix2D(result)
+        }
+        _ => panic!("softmax only supported for 1D/2D"),
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>> {
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("Softmax backward requires exactly 1 dependency");
     }
-    let input = &dependencies[0];
 
     let s = {
         let forward_data = tensor.data.borrow();
@@ -70,7 +78,7 @@ pub fn backward(tensor: &Tensor) -> Result<()> {
         }
     };
 
-    let dy = match &data.grad {
+    let dy = match grad {
         TensorValue::Vector1D(grad) => TensorValue::Vector1D(grad.clone()),
         TensorValue::Matrix2D(grad) => TensorValue::Matrix2D(grad.clone()),
         _ => panic!("Softmax gradient must match output shape"),
@@ -79,8 +87,8 @@ pub fn backward(tensor: &Tensor) -> Result<()> {
     let dz = match (&s, &dy) {
         (TensorValue::Vector1D(s_vec), TensorValue::Vector1D(dy_vec)) => {
             assert_eq!(s_vec.len(), dy_vec.len(), "Gradient shape mismatch");
-            let sum_s_dy: f64 = s_vec.iter().zip(dy_vec).map(|(&s_i, &dy_i)| s_i * dy_i).sum();
-            let dz_vec: Vec<f64> = s_vec.iter()
+            let sum_s_dy: T = s_vec.iter().zip(dy_vec).map(|(&s_i, &dy_i)| s_i * dy_i).sum();
+            let dz_vec: Vec<T> = s_vec.iter()
                 .zip(dy_vec)
                 .map(|(&s_i, &dy_i)| s_i * (dy_i - sum_s_dy))
                 .collect();
@@ -92,10 +100,10 @@ pub fn backward(tensor: &Tensor) -> Result<()> {
             let s_transposed = transpose(s_mat);
             let dy_transposed = transpose(dy_mat);
 
-            let dz_transposed: Vec<Vec<f64>> = s_transposed.into_iter()
+            let dz_transposed: Vec<Vec<T>> = s_transposed.into_iter()
                 .zip(dy_transposed.into_iter())
                 .map(|(s_col, dy_col)| {
-                    let sum_s_dy: f64 = s_col.iter()
+                    let sum_s_dy: T = s_col.iter()
                         .zip(&dy_col)
                         .map(|(&s, &dy)| s * dy)
                         .sum();
@@ -112,8 +120,30 @@ pub fn backward(tensor: &Tensor) -> Result<()> {
         }
         _ => panic!("Softmax gradient and output type mismatch"),
     };
-    input.data.borrow_mut().add_grad(dz)?;
-    Ok(())
+    Ok(vec![dz])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    // dz = s * (dy - sum(s * dy)), where the sum runs over each column of a
+    // matrix (softmax is taken column-wise) or over the whole vector.
+    let s_dy = tensor.multiply(grad)?;
+    let sum_shape = shape_of(tensor)[1..].to_vec();
+    let sum_s_dy = reduce_grad(s_dy, &sum_shape)?;
+    Ok(vec![tensor.multiply(&grad.sub(&sum_s_dy)?)?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    // ds = s * (dx - sum(s * dx)), summed per column like the forward pass.
+    let data = tensor.data.borrow();
+    let s = &data.value;
+    let s_dx = s.zip_map(&tangents[0], |s, t| s * t);
+    let sum_s_dx = match &s_dx {
+        TensorValue::Matrix2D(m) => TensorValue::Vector1D(
+            (0..m[0].len()).map(|j| m.iter().map(|row| row[j]).sum()).collect()
+        ),
+        _ => super::sum::forward(&s_dx),
+    };
+    s.zip_map(&tangents[0].zip_map(&sum_s_dx, |t, sum| t - sum), |s, t| s * t)
 }
 
 #[test]
@@ -127,7 +157,7 @@ fn softmax_backward_works() -> Result<()> {
 
     output.backward()?;
 
-    let input_grad = match &input.data.borrow().grad {
+    let input_grad = match &input.grad().unwrap().value() {
         TensorValue::Matrix2D(m) => m.clone(),
         _ => panic!("Input gradient should be Matrix2D")
     };
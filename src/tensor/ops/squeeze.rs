@@ -1,39 +1,15 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use crate::tensor::utils::transpose;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
-impl Tensor {
-    pub fn squeeze(&self, dim: usize) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn squeeze(&self, dim: usize) -> Result<Tensor<T>> {
         let data = self.data.borrow();
 
-        let result_value = match &data.value {
-            TensorValue::Vector1D(v) => match dim {
-                0 => {
-                    TensorValue::Scalar(v[0])
-                }
-
-                _ => panic!("Beyond dim")
-            }
-
-            TensorValue::Matrix2D(m) => match dim {
-                0 => {
-                    if m.len() > 1 {
-                        panic!("Error in squeeze {}. For the dim > 1", dim);
-                    }
-                    TensorValue::Vector1D(m[0].clone())
-                }
-                1 => {
-                    if m[0].len() > 1 {
-                        panic!("Error in squeeze {}. For the dim > 1", dim);
-                    }
-                    let result_ = transpose(m);
-                    TensorValue::Vector1D(result_[0].clone())
-                }
-                _ => panic!("Beyond dim")
-            }
-
-            _ => panic!("Unsqueeze operation did not match this data format!")
-        };
+        let result_value = forward(&data.value, dim);
 
         let result = Self::from_value(result_value);
         {
@@ -41,20 +17,50 @@ impl Tensor {
             res_data.operation = Operation::Squeeze(dim);
             res_data.dependencies = vec![self.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 
 }
 
-pub fn backward(tensor: &Tensor, dim: usize) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>, dim: usize) -> TensorValue<T> {
+    match input {
+        TensorValue::Vector1D(v) => match dim {
+            0 => {
+                TensorValue::Scalar(v[0])
+            }
+
+            _ => panic!("Beyond dim")
+        }
+
+        TensorValue::Matrix2D(m) => match dim {
+            0 => {
+                if m.len() > 1 {
+                    panic!("Error in squeeze {}. For the dim > 1", dim);
+                }
+                TensorValue::Vector1D(m[0].clone())
+            }
+            1 => {
+                if m[0].len() > 1 {
+                    panic!("Error in squeeze {}. For the dim > 1", dim);
+                }
+                let result_ = transpose(m);
+                TensorValue::Vector1D(result_[0].clone())
+            }
+            _ => panic!("Beyond dim")
+        }
+
+        _ => panic!("Unsqueeze operation did not match this data format!")
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, dim: usize) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("Squeeze operation requires exactly 1 dependency");
     }
 
-    let x = &dependencies[0];
-
 
     let squeeze_output = match &data.value {
         TensorValue::Scalar(s) => TensorValue::Scalar(s.clone()),
@@ -62,25 +68,33 @@ pub fn backward(tensor: &Tensor, dim: usize) -> Result<()>{
         _ => panic!("Backward unsqueeze doesn't match")
     };
 
-    match (&data.grad, &squeeze_output) {
+    let grad_x = match (grad, &squeeze_output) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(s)) => {
-            x.data.borrow_mut().add_grad(TensorValue::Scalar(grad.clone()))?;
+            TensorValue::Scalar(grad.clone())
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(s)) => match dim {
             0 => {
-                x.data.borrow_mut().add_grad(TensorValue::Matrix2D(vec![grad.clone()]))?
+                TensorValue::Matrix2D(vec![grad.clone()])
             }
 
             1 => {
-                x.data.borrow_mut().add_grad(TensorValue::Matrix2D(transpose(&vec![grad.clone()])))?
+                TensorValue::Matrix2D(transpose(&vec![grad.clone()]))
             }
             _ => panic!("Backward unsqueeze doesn't match")
         }
         _ => panic!("Invalid unsqueeze gradient combination"),
-    }
+    };
 
-    Ok(())
+    Ok(vec![grad_x])
+
+}
+
+pub fn backward_graph<T: Float>(grad: &Tensor<T>, dim: usize) -> Result<Vec<Tensor<T>>>{
+    Ok(vec![grad.unsqueeze(dim)?])
+}
 
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], dim: usize) -> TensorValue<T> {
+    forward(&tangents[0], dim)
 }
 
 #[test]
@@ -107,15 +121,15 @@ fn squeeze_works() -> Result<()>{
     output_matrix.backward()?;
     output_matrix_2.backward()?;
 
-    let vector_grad = match &inputs_vector.data.borrow().grad {
+    let vector_grad = match &inputs_vector.grad().unwrap().value() {
         TensorValue::Vector1D(v) => v.clone(),
         _ => panic!("Error in scalar")
     };
-    let matrix_grad = match &inputs_matrix.data.borrow().grad {
+    let matrix_grad = match &inputs_matrix.grad().unwrap().value() {
         TensorValue::Matrix2D(m) => m.clone(),
         _ => panic!("Error in scalar")
     };
-    let matrix_grad_2 = match &inputs_matrix_2.data.borrow().grad {
+    let matrix_grad_2 = match &inputs_matrix_2.grad().unwrap().value() {
         TensorValue::Matrix2D(m) => m.clone(),
         _ => panic!("Error in scalar")
     };
@@ -1,60 +1,17 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
+use super::{reduce_grad, shape_of};
 
-impl Tensor {
-    pub fn sub(&self, other: &Tensor) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn sub(&self, other: &Tensor<T>) -> Result<Tensor<T>> {
         let a = self.data.borrow();
         let b = other.data.borrow();
 
-        let result_value = match (&a.value, &b.value) {
-            // 标量相减
-            (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
-                TensorValue::Scalar(a_val - b_val)
-            }
-            // 向量相减
-            (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
-                assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
-                TensorValue::Vector1D(
-                    a_vec.iter().zip(b_vec).map(|(a, b)| a - b).collect()
-                )
-            }
-            // 矩阵相减
-            (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
-                assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
-                assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
-                TensorValue::Matrix2D(
-                    a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
-                        a_row.iter().zip(b_row).map(|(a, b)| a - b).collect()
-                    }).collect()
-                )
-            }
-            // 标量广播
-            (TensorValue::Scalar(s), TensorValue::Vector1D(v)) => {
-                TensorValue::Vector1D(v.iter().map(|x| s - x).collect())
-            }
-            (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
-                TensorValue::Vector1D(v.iter().map(|x| x - s).collect())
-            }
-            (TensorValue::Scalar(s), TensorValue::Matrix2D(m)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| s - x).collect()).collect()
-                )
-            }
-            (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().map(|x| x - s).collect()).collect()
-                )
-            }
-
-            // 向量广播
-            (TensorValue::Matrix2D(m), TensorValue::Vector1D(v)) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| row.iter().zip(v).map(|(a, b)| a - b).collect()).collect()
-                )
-            }
-            _ => panic!("Invalid sub operation between types"),
-        };
+        let result_value = forward(&a.value, &b.value);
 
         let result = Self::from_value(result_value);
         {
@@ -62,39 +19,115 @@ impl Tensor {
             res_data.operation = Operation::Sub;
             res_data.dependencies = vec![self.clone(), other.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(a: &TensorValue<T>, b: &TensorValue<T>) -> TensorValue<T> {
+    match (a, b) {
+        // 标量相减
+        (TensorValue::Scalar(a_val), TensorValue::Scalar(b_val)) => {
+            TensorValue::Scalar(*a_val - *b_val)
+        }
+        // 向量相减
+        (TensorValue::Vector1D(a_vec), TensorValue::Vector1D(b_vec)) => {
+            assert_eq!(a_vec.len(), b_vec.len(), "Vector length mismatch");
+            TensorValue::Vector1D(
+                a_vec.iter().zip(b_vec).map(|(a, b)| *a - *b).collect()
+            )
+        }
+        // 矩阵相减
+        (TensorValue::Matrix2D(a_mat), TensorValue::Matrix2D(b_mat)) => {
+            assert_eq!(a_mat.len(), b_mat.len(), "Matrix rows mismatch");
+            assert_eq!(a_mat[0].len(), b_mat[0].len(), "Matrix cols mismatch");
+            TensorValue::Matrix2D(
+                a_mat.iter().zip(b_mat).map(|(a_row, b_row)| {
+                    a_row.iter().zip(b_row).map(|(a, b)| *a - *b).collect()
+                }).collect()
+            )
+        }
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            assert!(a.same_shape(b), "Tensor shape mismatch");
+            a.zip_map(b, |a, b| a - b)
+        }
+        // 标量广播
+        (TensorValue::Scalar(s), TensorValue::Vector1D(v)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *s - *x).collect())
+        }
+        (TensorValue::Vector1D(v), TensorValue::Scalar(s)) => {
+            TensorValue::Vector1D(v.iter().map(|x| *x - *s).collect())
+        }
+        (TensorValue::Scalar(s), TensorValue::Matrix2D(m)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *s - *x).collect()).collect()
+            )
+        }
+        (TensorValue::Matrix2D(m), TensorValue::Scalar(s)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| *x - *s).collect()).collect()
+            )
+        }
+        (TensorValue::Scalar(_), TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_)) | (TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_), TensorValue::Scalar(_)) => {
+            a.zip_map(b, |a, b| a - b)
+        }
+
+        // 向量广播
+        (TensorValue::Matrix2D(m), TensorValue::Vector1D(v)) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().zip(v).map(|(a, b)| *a - *b).collect()).collect()
+            )
+        }
+        _ => panic!("Invalid sub operation between types"),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, a: &TensorValue<T>, b: &TensorValue<T>) {
+    if !out.zip_map_into(a, b, |a, b| a - b) {
+        *out = forward(a, b);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 2 {
         panic!("Sub operation requires exactly 2 dependencies");
     }
 
-    let a = &dependencies[0];
-    let b = &dependencies[1];
-    match &data.grad {
+    let (a_grad, b_grad) = match grad {
         TensorValue::Scalar(grad) => {
-            a.data.borrow_mut().add_grad_scalar(*grad)?;
-            b.data.borrow_mut().add_grad_scalar(-*grad)?;
+            (TensorValue::Scalar(*grad), TensorValue::Scalar(-*grad))
         }
         TensorValue::Vector1D(grad_vec) => {
-            a.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_vec.clone()))?;
-            b.data.borrow_mut().add_grad(TensorValue::Vector1D(
-                grad_vec.iter().map(|g| -g).collect()
-            ))?;
+            (TensorValue::Vector1D(grad_vec.clone()), TensorValue::Vector1D(
+                grad_vec.iter().map(|g| -*g).collect()
+            ))
         }
         TensorValue::Matrix2D(grad_mat) => {
-            a.data.borrow_mut().add_grad(TensorValue::Matrix2D(grad_mat.clone()))?;
-            b.data.borrow_mut().add_grad(TensorValue::Matrix2D(
+            (TensorValue::Matrix2D(grad_mat.clone()), TensorValue::Matrix2D(
                 grad_mat.iter().map(|row|
-                    row.iter().map(|g| -g).collect()
+                    row.iter().map(|g| -*g).collect()
                 ).collect()
-            ))?;
+            ))
         }
-        TensorValue::Tensor3D(_) => todo!()
-    }
-    Ok(())
-}
\ No newline at end of file
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => (grad.clone(), grad.map(|g| -g)),
+    };
+    Ok(vec![a_grad, b_grad])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let a = &data.dependencies[0];
+    let b = &data.dependencies[1];
+
+    let neg_grad = grad.multiply(&Tensor::scalar(-T::one()))?;
+    Ok(vec![
+        reduce_grad(grad.clone(), &shape_of(a))?,
+        reduce_grad(neg_grad, &shape_of(b))?,
+    ])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    forward(&tangents[0], &tangents[1])
+}
@@ -1,44 +1,14 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
-impl Tensor {
-    pub fn sum(&self) -> Result<Tensor> {
+use super::{expand_grad, shape_of};
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn sum(&self) -> Result<Tensor<T>> {
         let data = self.data.borrow();
-        let results_value = match &data.value {
-            TensorValue::Scalar(s) => {
-                TensorValue::Scalar(s.clone())
-            }
-
-            TensorValue::Vector1D(v) => {
-                let sum: f64 = v.iter().sum();
-
-                TensorValue::Scalar(
-                    sum as f64
-                )
-            }
-
-            TensorValue::Matrix2D(m) => {
-                if m.is_empty() || m[0].is_empty() {
-                    panic!("Matrix is empty");
-                }
-
-
-                let cols = m[0].len();
-                assert!(
-                    m.iter().all(|row| row.len() == cols),
-                    "Inconsistent matrix columns"
-                );
-
-                let mut sum = 0.0;
-                for row in m {
-                    for &val in row {
-                        sum += val;
-                    }
-                }
-                TensorValue::Scalar(sum)
-            }
-            _ => panic!("sum only supported for 1D / 2D"),
-        };
+        let results_value = forward(&data.value);
 
         let result = Self::from_value(results_value);
 
@@ -48,11 +18,50 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(s) => {
+            TensorValue::Scalar(s.clone())
+        }
+
+        TensorValue::Vector1D(v) => {
+            let sum: T = v.iter().copied().sum();
+
+            TensorValue::Scalar(
+                sum
+            )
+        }
+
+        TensorValue::Matrix2D(m) => {
+            if m.is_empty() || m[0].is_empty() {
+                panic!("Matrix is empty");
+            }
+
+
+            let cols = m[0].len();
+            assert!(
+                m.iter().all(|row| row.len() == cols),
+                "Inconsistent matrix columns"
+            );
+
+            let mut sum = T::zero();
+            for row in m {
+                for &val in row {
+                    sum += val;
+                }
+            }
+            TensorValue::Scalar(sum)
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => TensorValue::Scalar(input.flatten().into_iter().sum()),
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
@@ -66,7 +75,7 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
         input_data.value.shape()
     };
 
-    let grad = match &data.grad {
+    let grad = match grad {
         TensorValue::Scalar(g) => *g,
         _ => panic!("Sum gradient must be a scalar!"),
     };
@@ -77,10 +86,21 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
         0 => TensorValue::Scalar(grad_per_element),
         1 => TensorValue::Vector1D(vec![grad_per_element; input_shape[0]]),
         2 => TensorValue::Matrix2D(vec![vec![grad_per_element; input_shape[1]]; input_shape[0]]),
+        3 | 4 => TensorValue::full(&input_shape, grad_per_element),
         _ => panic!("Unsupported dimension for sum backward!"),
     };
 
-    input.data.borrow_mut().add_grad(grad_tensor)?;
-    Ok(())
+    Ok(vec![grad_tensor])
+
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    let data = tensor.data.borrow();
+    let input_shape = shape_of(&data.dependencies[0]);
+
+    Ok(vec![expand_grad(grad, &input_shape)?])
+}
 
-}
\ No newline at end of file
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    forward(&tangents[0])
+}
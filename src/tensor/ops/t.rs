@@ -1,20 +1,15 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use crate::tensor::utils::transpose;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn t(&self) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn t(&self) -> Result<Tensor<T>> {
         let data = self.data.borrow();
-        let result_value = match &data.value {
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(
-                    transpose(m)
-                )
-            }
-
-            _ => panic!("t function only can be used in 2D Matrix!")
-        };
+        let result_value = forward(&data.value);
         let result = Self::from_value(result_value);
         {
             let mut res_data = result.data.borrow_mut();
@@ -22,25 +17,43 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Matrix2D(m) => {
+            TensorValue::Matrix2D(
+                transpose(m)
+            )
+        }
+
+        _ => panic!("t function only can be used in 2D Matrix!")
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("Tanh operation requires exactly 1 dependency");
     }
 
-    let x = &dependencies[0];
-
-    let grad = match &data.grad {
+    let grad = match grad {
         TensorValue::Matrix2D(m) => TensorValue::Matrix2D(transpose(m)),
         _ => panic!("T backward only for 2D matrices")
     };
-    x.data.borrow_mut().add_grad(grad)?;
-    Ok(())
+    Ok(vec![grad])
+}
+
+pub fn backward_graph<T: Float>(grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    Ok(vec![grad.t()?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    forward(&tangents[0])
 }
 
 #[test]
@@ -51,16 +64,17 @@ fn t_backward_work() -> Result<()>{
         vec![1.0, 1.0],
     ]);
     {
-        inputs.data.borrow_mut().grad = TensorValue::Matrix2D(vec![
+        inputs.data.borrow_mut().grad = Some(TensorValue::Matrix2D(vec![
             vec![1.0, 1.0],
             vec![1.0, 1.0],
             vec![1.0, 1.0],
-        ]);
+        ]));
     }
     let hidden = inputs.t()?;
+    hidden.retain_grad();
     let output = hidden.sum()?;
     output.backward()?;
-    let grad = match &hidden.data.borrow().grad {
+    let grad = match &hidden.grad().unwrap().value() {
         TensorValue::Matrix2D(m) => m.clone(),
         _ => panic!("Error in shape")
     };
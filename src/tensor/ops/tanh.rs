@@ -1,29 +1,14 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn tanh(&self) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn tanh(&self) -> Result<Tensor<T>> {
         let data = self.data.borrow();
-        let result_value = match &data.value {
-            TensorValue::Scalar(v) => {
-                let s = (v.exp() - (-v).exp()) / (v.exp() + (-v).exp());
-                TensorValue::Scalar(s)
-            }
-            TensorValue::Vector1D(v) => {
-                TensorValue::Vector1D(
-                    v.iter().map(|x| (x.exp() - (-x).exp()) / (x.exp() + (-x).exp())).collect()
-                )
-            }
-            TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(
-                    m.iter().map(|row| {
-                        row.iter().map(|x| (x.exp() - (-x).exp()) / (x.exp() + (-x).exp())).collect()
-                    }).collect()
-                )
-            }
-            TensorValue::Tensor3D(_) => todo!()
-        };
+        let result_value = forward(&data.value);
         let result = Self::from_value(result_value);
         {
             let mut res_data = result.data.borrow_mut();
@@ -31,36 +16,68 @@ impl Tensor {
             res_data.dependencies = vec![self.clone()];
         }
 
+        check_forward(&result)?;
         Ok(result)
     }
 }
 
-pub fn backward(tensor: &Tensor) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(v) => {
+            let s = (v.exp() - (-*v).exp()) / (v.exp() + (-*v).exp());
+            TensorValue::Scalar(s)
+        }
+        TensorValue::Vector1D(v) => {
+            TensorValue::Vector1D(
+                v.iter().map(|x| (x.exp() - (-*x).exp()) / (x.exp() + (-*x).exp())).collect()
+            )
+        }
+        TensorValue::Matrix2D(m) => {
+            TensorValue::Matrix2D(
+                m.iter().map(|row| {
+                    row.iter().map(|x| (x.exp() - (-*x).exp()) / (x.exp() + (-*x).exp())).collect()
+                }).collect()
+            )
+        }
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => input.map(|x| x.tanh()),
+    }
+}
+
+pub fn forward_into<T: Float>(out: &mut TensorValue<T>, input: &TensorValue<T>) {
+    let in_place = match input {
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => out.map_into(input, |x| x.tanh()),
+        _ => out.map_into(input, |x| (x.exp() - (-x).exp()) / (x.exp() + (-x).exp())),
+    };
+    if !in_place {
+        *out = forward(input);
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("Tanh operation requires exactly 1 dependency");
     }
 
-    let x = &dependencies[0];
     let tanh_output = match &data.value {
         TensorValue::Scalar(s) => TensorValue::Scalar(*s),
         TensorValue::Vector1D(v) => TensorValue::Vector1D(v.clone()),
         TensorValue::Matrix2D(m) => TensorValue::Matrix2D(m.clone()),
-        TensorValue::Tensor3D(_) => todo!()
+        TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_) => data.value.clone(),
     };
 
-    match (&data.grad, &tanh_output) {
+    let grad_x = match (grad, &tanh_output) {
         (TensorValue::Scalar(grad), TensorValue::Scalar(s)) => {
-            let grad_x = grad * (1.0 - s.powf(2.0));
-            x.data.borrow_mut().add_grad_scalar(grad_x)?;
+            let grad_x = *grad * (T::one() - s.powf(T::from_f64(2.0)));
+            TensorValue::Scalar(grad_x)
         }
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(s)) => {
             let grad_x: Vec<_> = grad.iter()
                 .zip(s)
-                .map(|(g, s_)| g * (1.0 - s_.powf(2.0)))
+                .map(|(g, s_)| *g * (T::one() - s_.powf(T::from_f64(2.0))))
                 .collect();
-            x.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_x))?;
+            TensorValue::Vector1D(grad_x)
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(s)) => {
             let grad_x: Vec<Vec<_>> = grad.iter()
@@ -68,14 +85,49 @@ pub fn backward(tensor: &Tensor) -> Result<()>{
                 .map(|(g_row, s_row)| {
                     g_row.iter()
                         .zip(s_row)
-                        .map(|(g, s_)| g * (1.0 - s_.powf(2.0)))
+                        .map(|(g, s_)| *g * (T::one() - s_.powf(T::from_f64(2.0))))
                         .collect()
                 })
                 .collect();
-            x.data.borrow_mut().add_grad(TensorValue::Matrix2D(grad_x))?;
+            TensorValue::Matrix2D(grad_x)
         }
 
+        (TensorValue::Tensor3D(_), TensorValue::Tensor3D(_)) | (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+            grad.zip_map(&tanh_output, |g, s| g * (T::one() - s * s))
+        }
         _ => panic!("Invalid tanh gradient combination"),
+    };
+    Ok(vec![grad_x])
+}
+
+pub fn backward_graph<T: Float>(tensor: &Tensor<T>, grad: &Tensor<T>) -> Result<Vec<Tensor<T>>>{
+    // tanh'(x) = 1 - s^2, expressed through the output node itself.
+    let local = Tensor::scalar(T::one()).sub(&tensor.pow(T::from_f64(2.0))?)?;
+    Ok(vec![grad.multiply(&local)?])
+}
+
+pub fn jvp<T: Float>(tensor: &Tensor<T>, tangents: &[TensorValue<T>]) -> TensorValue<T> {
+    let data = tensor.data.borrow();
+    tangents[0].zip_map(&data.value, |t, y| t * (T::one() - y * y))
+}
+
+#[test]
+fn tanh_and_sigmoid_second_order_work_on_feature_maps() -> Result<()> {
+    let data: Vec<f64> = (0..2 * 2 * 3).map(|i| (i as f64 * 0.9).sin()).collect();
+    let x = Tensor::from_value(TensorValue::from_flat(&[2, 2, 3], &data));
+    let second_derivative = |f: fn(&Tensor) -> Result<Tensor>| -> Result<Vec<f64>> {
+        let dx = crate::tensor::autograd::grad(&[f(&x)?.sum()?], std::slice::from_ref(&x))?;
+        let d2x = crate::tensor::autograd::grad(&[dx[0].sum()?], std::slice::from_ref(&x))?;
+        Ok(d2x[0].value().flatten())
+    };
+
+    for (actual, x) in second_derivative(Tensor::tanh)?.iter().zip(&data) {
+        let t = x.tanh();
+        approx::assert_abs_diff_eq!(*actual, -2.0 * t * (1.0 - t * t), epsilon = 1e-10);
+    }
+    for (actual, x) in second_derivative(Tensor::sigmoid)?.iter().zip(&data) {
+        let s = 1.0 / (1.0 + (-x).exp());
+        approx::assert_abs_diff_eq!(*actual, s * (1.0 - s) * (1.0 - 2.0 * s), epsilon = 1e-10);
     }
     Ok(())
-}
\ No newline at end of file
+}
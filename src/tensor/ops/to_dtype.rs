@@ -0,0 +1,212 @@
+use crate::tensor::dtype::{DType, Float};
+use std::any::Any;
+use std::fmt;
+use std::rc::Rc;
+use crate::tensor::anomaly::check_forward;
+use crate::tensor::forward_ad;
+use crate::tensor::operation::Operation;
+use crate::tensor::tape::{Gradients, Tape};
+use super::super::{Tensor, TensorValue};
+use anyhow::{bail, Result};
+
+/// The source of a cast between element types, recorded as `Operation::Cast`.
+/// A graph only holds tensors of one element type, so the source graph is not
+/// part of the graph of the cast: a backward pass collects the gradients of its
+/// casts and hands them over to their sources at the end.
+pub trait CastSource<U: Float>: fmt::Debug {
+    fn dtype(&self) -> DType;
+
+    /// The current value of the source, converted to `U`.
+    fn value(&self) -> TensorValue<U>;
+
+    /// Converts the gradients of every cast in `casts` from this source's
+    /// element type back and backpropagates them in a single pass over the
+    /// source graph.
+    fn backward(&self, casts: &[PendingCast<U>]) -> Result<()>;
+
+    /// The tangent the source carries for the `forward_ad::jvp` call `id`,
+    /// converted to `U`.
+    fn tangent(&self, id: u64) -> Option<TensorValue<U>>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A gradient that reached a cast, waiting to go into the source graph.
+pub type PendingCast<U> = (Rc<dyn CastSource<U>>, TensorValue<U>);
+
+struct Source<T: Float>(Tensor<T>);
+
+impl<T: Float> fmt::Debug for Source<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cast(from {:?})", T::DTYPE)
+    }
+}
+
+impl<T: Float, U: Float> CastSource<U> for Source<T> {
+    fn dtype(&self) -> DType {
+        T::DTYPE
+    }
+
+    fn value(&self) -> TensorValue<U> {
+        self.0.value().cast()
+    }
+
+    fn backward(&self, casts: &[PendingCast<U>]) -> Result<()> {
+        let (sources, grads): (Vec<Tensor<T>>, Vec<TensorValue<T>>) = casts.iter()
+            .filter_map(|(source, grad)| {
+                let source = source.as_any().downcast_ref::<Source<T>>()?;
+                Some((source.0.clone(), grad.cast()))
+            })
+            .unzip();
+        let tape = Tape::record(&sources);
+        let seeds: Vec<(usize, &TensorValue<T>)> = sources.iter().zip(&grads)
+            .map(|(source, grad)| (tape.index_of(source).unwrap(), grad))
+            .collect();
+        tape.backward_into(&seeds, &mut Gradients::new())
+    }
+
+    fn tangent(&self, id: u64) -> Option<TensorValue<U>> {
+        Some(forward_ad::tangent(&self.0, id)?.cast())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<T: Float> Tensor<T> {
+    pub fn dtype(&self) -> DType {
+        T::DTYPE
+    }
+
+    /// Converts the elements to `U`. Converting to the element type the tensor
+    /// already has returns the tensor itself.
+    ///
+    /// `backward` and `forward_ad::jvp` flow through a conversion to another
+    /// type (the gradient or tangent is converted along with the value), but
+    /// `backward_create_graph` and `autograd::grad` cannot see across it and
+    /// return an error instead.
+    #[track_caller]
+    pub fn to_dtype<U: Float>(&self) -> Result<Tensor<U>> {
+        if let Some(same) = (self as &dyn Any).downcast_ref::<Tensor<U>>() {
+            return Ok(same.clone());
+        }
+
+        let source: Rc<dyn CastSource<U>> = Rc::new(Source(self.clone()));
+        let result = Tensor::from_value(source.value());
+        result.data.borrow_mut().operation = Operation::Cast(source);
+        check_forward(&result)?;
+        Ok(result)
+    }
+}
+
+pub fn forward<T: Float>(source: &Rc<dyn CastSource<T>>) -> TensorValue<T> {
+    source.value()
+}
+
+pub fn backward<T: Float>(grad: &TensorValue<T>, source: &Rc<dyn CastSource<T>>, grads: &mut Gradients<T>) -> Vec<TensorValue<T>>{
+    grads.add_cast(source.clone(), grad.clone());
+    vec![]
+}
+
+/// Backpropagates the gradients a pass collected for its casts, one pass per
+/// source element type.
+pub(crate) fn backward_casts<T: Float>(mut casts: Vec<PendingCast<T>>) -> Result<()> {
+    while let Some((source, _)) = casts.first() {
+        let (source, dtype) = (source.clone(), source.dtype());
+        source.backward(&casts)?;
+        casts.retain(|(source, _)| source.dtype() != dtype);
+    }
+    Ok(())
+}
+
+pub fn backward_graph<T: Float>(source: &Rc<dyn CastSource<T>>) -> Result<Vec<Tensor<T>>>{
+    bail!("Cannot build a gradient graph through a cast from {:?} to {:?}", source.dtype(), T::DTYPE)
+}
+
+#[test]
+fn to_dtype_backpropagates_across_dtypes() -> Result<()> {
+    let x = Tensor::vector(vec![0.5, -1.0, 2.0]);
+    let y = x.multiply(&x)?.to_dtype::<f32>()?;
+    assert_eq!(y.dtype(), DType::F32);
+    assert_eq!(y.to_vec()?, vec![0.25_f32, 1.0, 4.0]);
+
+    y.tanh()?.sum()?.backward()?;
+    let expected: Vec<f64> = [0.25_f64, 1.0, 4.0].iter().zip(x.to_vec()?)
+        .map(|(s, x)| (1.0 - s.tanh().powi(2)) * 2.0 * x)
+        .collect();
+    for (actual, expected) in x.grad().unwrap().to_vec()?.iter().zip(expected) {
+        approx::assert_abs_diff_eq!(*actual, expected, epsilon = 1e-6);
+    }
+    Ok(())
+}
+
+#[test]
+fn f32_training_matches_f64() -> Result<()> {
+    use crate::nn::Optimizer;
+    use crate::nn::optimizer::SGD;
+
+    fn train<T: Float>(initial: &[Tensor]) -> Result<Vec<f64>> {
+        let params: Vec<Tensor<T>> = initial.iter().map(|param| Tensor::from_value(param.value().cast())).collect();
+        let refs: Vec<&Tensor<T>> = params.iter().collect();
+        let optimizer = SGD::new(0.1);
+        let data = [([1.0, 1.0], 0.0), ([0.0, 1.0], 1.0), ([1.0, 0.0], 1.0), ([0.0, 0.0], 0.0)];
+        for _ in 0..20 {
+            for (input, target) in &data {
+                optimizer.zero_grad(&refs);
+                let x = Tensor::vector(input.iter().map(|&v| T::from_f64(v)).collect());
+                let hidden = params[0].matmul(&x)?.add(&params[1])?.tanh()?;
+                let output = params[2].matmul(&hidden)?.add(&params[3])?.sigmoid()?;
+                let target = Tensor::vector(vec![T::from_f64(*target)]);
+                crate::loss_fn::bce_loss(&output, &target)?.backward()?;
+                optimizer.step(&refs);
+            }
+        }
+        Ok(params.iter().flat_map(|param| param.value().flatten()).map(T::into_f64).collect())
+    }
+
+    let initial = [
+        Tensor::matrix(vec![vec![0.5, -0.4], vec![0.3, 0.8], vec![-0.6, 0.2], vec![0.1, -0.9]]),
+        Tensor::vector(vec![0.01, -0.02, 0.03, 0.0]),
+        Tensor::matrix(vec![vec![0.7, -0.5, 0.4, 0.2]]),
+        Tensor::vector(vec![0.0]),
+    ];
+    for (a, b) in train::<f64>(&initial)?.iter().zip(train::<f32>(&initial)?) {
+        approx::assert_abs_diff_eq!(*a, b, epsilon = 1e-4);
+    }
+    Ok(())
+}
+
+#[test]
+fn to_dtype_keeps_same_type_graphs_whole() -> Result<()> {
+    let x = Tensor::vector(vec![1.0, -2.0, 4.0]);
+    let y = x.multiply(&x)?.to_dtype::<f64>()?.sum()?;
+    let grads = crate::tensor::autograd::grad(&[y], std::slice::from_ref(&x))?;
+    assert_eq!(grads[0].to_vec()?, vec![2.0, -4.0, 8.0]);
+    assert!(x.grad().is_none());
+
+    // Across types the functional APIs refuse rather than return zeros.
+    let z = x.multiply(&x)?.to_dtype::<f32>()?.to_dtype::<f64>()?.sum()?;
+    assert!(crate::tensor::autograd::grad(&[z], std::slice::from_ref(&x)).is_err());
+    assert!(x.grad().is_none());
+    Ok(())
+}
+
+#[test]
+fn to_dtype_backpropagates_all_casts_in_one_pass() -> Result<()> {
+    let x = Tensor::vector(vec![0.5, -1.0]);
+    let hidden = x.multiply(&x)?;
+    let calls = Rc::new(std::cell::Cell::new(0));
+    let calls_in_hook = calls.clone();
+    hidden.register_hook(move |_| {
+        calls_in_hook.set(calls_in_hook.get() + 1);
+        None
+    });
+
+    let y = hidden.to_dtype::<f32>()?;
+    let z = hidden.to_dtype::<f32>()?.multiply(&Tensor::scalar(3.0))?;
+    y.add(&z)?.sum()?.backward()?;
+    assert_eq!(calls.get(), 1);
+    assert_eq!(x.grad().unwrap().to_vec()?, vec![4.0, -8.0]);
+    Ok(())
+}
@@ -1,32 +1,16 @@
+use crate::tensor::dtype::Float;
+use crate::tensor::anomaly::check_forward;
 use crate::tensor::operation::Operation;
 use crate::tensor::utils::transpose;
 use super::super::{Tensor, TensorValue};
 use anyhow::Result;
 
-impl Tensor {
-    pub fn unsqueeze(&self, dim: usize) -> Result<Tensor> {
+impl<T: Float> Tensor<T> {
+    #[track_caller]
+    pub fn unsqueeze(&self, dim: usize) -> Result<Tensor<T>> {
         let data = self.data.borrow();
 
-        let result_value = match &data.value {
-            TensorValue::Scalar(s) => match dim {
-                0 => {
-                    TensorValue::Vector1D(vec![*s])
-                }
-
-                _ => panic!("Beyond dim")
-            }
-            TensorValue::Vector1D(v) => match dim {
-                0 => {
-                    TensorValue::Matrix2D(vec![v.clone()])
-                }
-                1 => {
-                    TensorValue::Matrix2D(transpose(&vec![v.clone()]))
-                }
-                _ => panic!("Beyond dim")
-            }
-
-            _ => panic!("Unsqueeze operation did not match this data format!")
-        };
+        let result_value = forward(&data.value, dim);
 
         let result = Self::from_value(result_value);
         {
@@ -34,43 +18,74 @@ impl Tensor {
             res_data.operation = Operation::Unsqueeze(dim);
             res_data.dependencies = vec![self.clone()];
         }
+        check_forward(&result)?;
         Ok(result)
     }
 
 }
 
-pub fn backward(tensor: &Tensor, dim: usize) -> Result<()>{
+pub fn forward<T: Float>(input: &TensorValue<T>, dim: usize) -> TensorValue<T> {
+    match input {
+        TensorValue::Scalar(s) => match dim {
+            0 => {
+                TensorValue::Vector1D(vec![*s])
+            }
+
+            _ => panic!("Beyond dim")
+        }
+        TensorValue::Vector1D(v) => match dim {
+            0 => {
+                TensorValue::Matrix2D(vec![v.clone()])
+            }
+            1 => {
+                TensorValue::Matrix2D(transpose(&vec![v.clone()]))
+            }
+            _ => panic!("Beyond dim")
+        }
+
+        _ => panic!("Unsqueeze operation did not match this data format!")
+    }
+}
+
+pub fn backward<T: Float>(tensor: &Tensor<T>, grad: &TensorValue<T>, dim: usize) -> Result<Vec<TensorValue<T>>>{
     let data = tensor.data.borrow();
     let dependencies = &data.dependencies;
     if dependencies.len() != 1 {
         panic!("Unsqueeze operation requires exactly 1 dependency");
     }
 
-    let x = &dependencies[0];
     let unsqueeze_output = match &data.value {
         TensorValue::Vector1D(v) => TensorValue::Vector1D(v.clone()),
         TensorValue::Matrix2D(m) => TensorValue::Matrix2D(m.clone()),
         _ => panic!("Backward unsqueeze doesn't match")
     };
 
-    match (&data.grad, &unsqueeze_output) {
+    let grad_x = match (grad, &unsqueeze_output) {
         (TensorValue::Vector1D(grad), TensorValue::Vector1D(s)) => {
-            x.data.borrow_mut().add_grad_scalar(grad[0])?;
+            TensorValue::Scalar(grad[0])
         }
         (TensorValue::Matrix2D(grad), TensorValue::Matrix2D(s)) => match dim {
             0 => {
-                x.data.borrow_mut().add_grad(TensorValue::Vector1D(grad[0].clone()))?;
+                TensorValue::Vector1D(grad[0].clone())
             },
             1 => {
                 let grad_ = transpose(grad);
-                x.data.borrow_mut().add_grad(TensorValue::Vector1D(grad_[0].clone()))?;
+                TensorValue::Vector1D(grad_[0].clone())
             },
             _ => panic!("Invalid unsqueeze gradient combination"),
         }
         _ => panic!("Invalid unsqueeze gradient combination"),
-    }
+    };
 
-    Ok(())
+    Ok(vec![grad_x])
+}
+
+pub fn backward_graph<T: Float>(grad: &Tensor<T>, dim: usize) -> Result<Vec<Tensor<T>>>{
+    Ok(vec![grad.squeeze(dim)?])
+}
+
+pub fn jvp<T: Float>(tangents: &[TensorValue<T>], dim: usize) -> TensorValue<T> {
+    forward(&tangents[0], dim)
 }
 
 #[test]
@@ -87,11 +102,11 @@ fn unsqueeze_works() -> Result<()>{
     output_scalar.backward()?;
     output_vector.backward()?;
 
-    let scalar_grad = match &inputs_scalar.data.borrow().grad {
+    let scalar_grad = match &inputs_scalar.grad().unwrap().value() {
         TensorValue::Scalar(s) => *s,
         _ => panic!("Error in scalar")
     };
-    let vector_grad = match &inputs_vector.data.borrow().grad {
+    let vector_grad = match &inputs_vector.grad().unwrap().value() {
         TensorValue::Vector1D(v) => v.clone(),
         _ => panic!("Error in scalar")
     };
@@ -0,0 +1,119 @@
+// Graph introspection: how many nodes a tensor keeps alive and how much memory
+// their buffers hold, e.g. to log per training step and catch a graph that
+// keeps growing across iterations.
+
+use crate::tensor::dtype::Float;
+use std::collections::BTreeMap;
+use std::fmt;
+use super::value::TensorValue;
+use super::Tensor;
+
+/// Node count and buffer sizes of one kind of op, see `GraphStats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpStats {
+    pub nodes: usize,
+    pub value_bytes: usize,
+    pub grad_bytes: usize,
+}
+
+/// Size of the graph reachable from a tensor, see `Tensor::graph_stats`.
+/// Bytes only count the elements of `value` and `grad`, not the node
+/// bookkeeping around them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub value_bytes: usize,
+    pub grad_bytes: usize,
+    /// Breakdown by `Operation::name`; leaves are listed under `None`.
+    pub by_operation: BTreeMap<String, OpStats>,
+}
+
+impl GraphStats {
+    pub fn total_bytes(&self) -> usize {
+        self.value_bytes + self.grad_bytes
+    }
+}
+
+impl fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} nodes, {} bytes in values, {} bytes in grads", self.nodes, self.value_bytes, self.grad_bytes)?;
+        for (name, op) in &self.by_operation {
+            writeln!(f, "  {:<12} {:>6} nodes {:>10} B values {:>10} B grads", name, op.nodes, op.value_bytes, op.grad_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+fn bytes<T: Float>(value: &TensorValue<T>) -> usize {
+    value.flatten().len() * std::mem::size_of::<T>()
+}
+
+impl<T: Float> Tensor<T> {
+    /// Counts the nodes reachable from this tensor (itself included, shared
+    /// nodes once) and the memory held by their values and gradients.
+    pub fn graph_stats(&self) -> GraphStats {
+        let tape = self._build_topo();
+
+        let mut stats = GraphStats::default();
+        for node in tape.nodes() {
+            let data = node.data.borrow();
+            let value_bytes = bytes(&data.value);
+            let grad_bytes = data.grad.as_ref().map_or(0, bytes);
+
+            stats.nodes += 1;
+            stats.value_bytes += value_bytes;
+            stats.grad_bytes += grad_bytes;
+            let op = stats.by_operation.entry(data.operation.name()).or_default();
+            op.nodes += 1;
+            op.value_bytes += value_bytes;
+            op.grad_bytes += grad_bytes;
+        }
+        stats
+    }
+
+    /// Number of nodes reachable from this tensor, itself included.
+    pub fn graph_size(&self) -> usize {
+        self._build_topo().len()
+    }
+}
+
+#[test]
+fn graph_stats_counts_nodes_and_bytes() -> anyhow::Result<()> {
+    let w = Tensor::matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+    let x = Tensor::vector(vec![1.0, -1.0]);
+    let hidden = w.matmul(&x)?;
+    let loss = hidden.multiply(&hidden)?.pow(2.0)?.sum()?;
+    loss.backward()?;
+
+    let stats = loss.graph_stats();
+    assert_eq!(stats.nodes, 6);
+    assert_eq!(loss.graph_size(), 6);
+    // w, x, matmul, multiply, pow and sum values: 6 + 2 + 3 + 3 + 3 + 1 elements.
+    assert_eq!(stats.value_bytes, 18 * 8);
+    // Only the leaves keep their gradients.
+    assert_eq!(stats.grad_bytes, 8 * 8);
+    assert_eq!(stats.total_bytes(), 26 * 8);
+
+    assert_eq!(stats.by_operation["None"], OpStats { nodes: 2, value_bytes: 64, grad_bytes: 64 });
+    assert_eq!(stats.by_operation["Pow"], OpStats { nodes: 1, value_bytes: 24, grad_bytes: 0 });
+    assert_eq!(stats.by_operation.len(), 5);
+    assert!(stats.to_string().starts_with("6 nodes, 144 bytes in values, 64 bytes in grads\n"));
+    Ok(())
+}
+
+#[test]
+fn graph_stats_catches_retention_across_iterations() -> anyhow::Result<()> {
+    let w = Tensor::vector(vec![0.5, -0.5]);
+    let x = Tensor::vector(vec![1.0, 2.0]);
+
+    // Summing the losses as tensors keeps every iteration's graph alive.
+    let mut total = Tensor::scalar(0.0);
+    let mut sizes = vec![];
+    for _ in 0..3 {
+        let loss = w.multiply(&x)?.sum()?;
+        total = total.add(&loss)?;
+        sizes.push(total.graph_size());
+    }
+    assert_eq!(sizes, vec![6, 9, 12]);
+    Ok(())
+}
@@ -0,0 +1,411 @@
+// A topological index of the graph behind a set of outputs, built at the start
+// of each pass over it (backward, jvp, tracing, `to_dot`). The tape lists the
+// arena nodes of the graph in order and stores each node's dependencies as
+// positions in that list, so a pass keeps its per-node state (gradients,
+// tangents) in plain `Vec`s. While recording, each node's position is marked
+// on its arena slot, which is how shared nodes are found again.
+
+use crate::tensor::dtype::Float;
+use std::cell::Cell;
+use super::ops::to_dtype::{self, CastSource, PendingCast};
+use super::value::TensorValue;
+use std::rc::Rc;
+use super::{ops, Tensor};
+use anyhow::Result;
+
+pub struct Tape<T: Float = f64> {
+    nodes: Vec<Tensor<T>>,
+    // The dependencies of node `i` are `dependencies[offsets[i]..offsets[i + 1]]`.
+    dependencies: Vec<usize>,
+    offsets: Vec<usize>,
+    // Marks this tape leaves on the slots of its nodes; unique per thread.
+    id: u64,
+}
+
+thread_local! {
+    // Zero is the mark of a slot no tape has recorded.
+    static NEXT_TAPE: Cell<u64> = const { Cell::new(1) };
+}
+
+/// Per-node gradients of a reverse pass over a tape, indexed like its nodes.
+/// A new pass empties the slots but keeps their buffers, so passes over the
+/// same tape (as `Trace` replays) accumulate into the same allocations.
+pub struct Gradients<T: Float = f64> {
+    slots: Vec<Option<TensorValue<T>>>,
+    // Whether the slot holds a gradient of the current pass.
+    live: Vec<bool>,
+    // Gradients reaching casts from other element types, propagated into
+    // their graphs once this pass is done.
+    casts: Vec<PendingCast<T>>,
+}
+
+impl<T: Float> Gradients<T> {
+    pub fn new() -> Self {
+        Gradients { slots: Vec::new(), live: Vec::new(), casts: Vec::new() }
+    }
+
+    fn reset(&mut self, len: usize) {
+        self.slots.resize(len, None);
+        self.live.clear();
+        self.live.resize(len, false);
+        self.casts.clear();
+    }
+
+    /// The gradient node `index` received in the last pass, if any.
+    pub fn get(&self, index: usize) -> Option<&TensorValue<T>> {
+        self.slots[index].as_ref().filter(|_| self.live[index])
+    }
+
+    /// Adds a copy of `grad` to the gradient of node `index`.
+    fn seed(&mut self, index: usize, grad: &TensorValue<T>) -> Result<()> {
+        let slot = &mut self.slots[index];
+        if self.live[index] {
+            return slot.as_mut().unwrap().accumulate(grad);
+        }
+        if !slot.as_mut().is_some_and(|buffer| buffer.map_into(grad, |g| g)) {
+            *slot = Some(grad.clone());
+        }
+        self.live[index] = true;
+        Ok(())
+    }
+
+    /// Queues `grad` to be propagated through the cast from `source`.
+    pub(crate) fn add_cast(&mut self, source: Rc<dyn CastSource<T>>, grad: TensorValue<T>) {
+        self.casts.push((source, grad));
+    }
+
+    /// Adds `delta` to the gradient of node `index`, whose value is `value`.
+    /// `delta` may be broadcast against `value`.
+    pub(crate) fn add(&mut self, index: usize, delta: TensorValue<T>, value: &TensorValue<T>) -> Result<()> {
+        let live = std::mem::replace(&mut self.live[index], true);
+        let slot = &mut self.slots[index];
+        match slot {
+            Some(total) if live => return total.accumulate(&delta),
+            Some(buffer) if buffer.same_shape(value) => {
+                if !buffer.map_into(&delta, |g| g) {
+                    buffer.fill(T::zero());
+                    buffer.accumulate(&delta)?;
+                }
+            }
+            _ if delta.same_shape(value) => *slot = Some(delta),
+            _ => {
+                let mut total = TensorValue::full(&value.shape(), T::zero());
+                total.accumulate(&delta)?;
+                *slot = Some(total);
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the gradient of node `index` out for processing; `restore` puts
+    /// it back once it has been propagated.
+    pub(crate) fn take(&mut self, index: usize) -> Option<TensorValue<T>> {
+        if self.live[index] { self.slots[index].take() } else { None }
+    }
+
+    pub(crate) fn restore(&mut self, index: usize, grad: TensorValue<T>) {
+        self.slots[index] = Some(grad);
+    }
+}
+
+impl<T: Float> Default for Gradients<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Recording is on the hot path of every training step; growing the buffers
+// from empty costs more than the walk itself for small graphs.
+const INITIAL_CAPACITY: usize = 64;
+
+impl<T: Float> Tape<T> {
+    /// Records every node `outputs` depend on, dependencies before the nodes
+    /// using them.
+    pub fn record(outputs: &[Tensor<T>]) -> Tape<T> {
+        let mut tape = Tape {
+            nodes: Vec::with_capacity(INITIAL_CAPACITY),
+            dependencies: Vec::with_capacity(INITIAL_CAPACITY),
+            offsets: Vec::with_capacity(INITIAL_CAPACITY + 1),
+            id: NEXT_TAPE.with(|next| next.replace(next.get() + 1)),
+        };
+        tape.offsets.push(0);
+
+        // Iterative post-order walk, so deep graphs cannot overflow the stack.
+        let mut stack: Vec<(Tensor<T>, bool)> = outputs.iter().rev().map(|output| (output.clone(), false)).collect();
+        while let Some((tensor, expanded)) = stack.pop() {
+            if tensor.data.position_on(tape.id).is_some() {
+                continue;
+            }
+
+            let data = tensor.data.borrow();
+            if expanded {
+                for dep in &data.dependencies {
+                    tape.dependencies.push(dep.data.position_on(tape.id).unwrap());
+                }
+                drop(data);
+                tape.offsets.push(tape.dependencies.len());
+                tensor.data.mark(tape.id, tape.nodes.len());
+                tape.nodes.push(tensor);
+            } else {
+                stack.push((tensor.clone(), true));
+                stack.extend(data.dependencies.iter().rev()
+                    .filter(|dep| dep.data.position_on(tape.id).is_none())
+                    .map(|dep| (dep.clone(), false)));
+            }
+        }
+        tape
+    }
+
+    /// The recorded nodes in topological order.
+    pub fn nodes(&self) -> &[Tensor<T>] {
+        &self.nodes
+    }
+
+    /// Indices of the dependencies of node `index`, in operand order.
+    pub fn dependencies(&self, index: usize) -> &[usize] {
+        &self.dependencies[self.offsets[index]..self.offsets[index + 1]]
+    }
+
+    pub fn index_of(&self, tensor: &Tensor<T>) -> Option<usize> {
+        // A later tape may have marked a shared node over this one's mark.
+        tensor.data.position_on(self.id)
+            .or_else(|| self.nodes.iter().position(|node| node.data.ptr_eq(&tensor.data)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Reverse mode over the tape, seeding node `output` with `seed`.
+    /// Intermediate gradients live in an arena indexed like the nodes; leaves
+    /// accumulate theirs into `grad`.
+    pub fn backward(&self, output: usize, seed: TensorValue<T>) -> Result<()> {
+        self.backward_into(&[(output, &seed)], &mut Gradients::new())
+    }
+
+    /// `backward` from several `(node, seed)` pairs at once, with the
+    /// intermediate gradients kept in `gradients`, reusing its buffers from
+    /// earlier passes over this tape.
+    ///
+    /// Casts from another element type are edges into a graph of that type,
+    /// which cannot share this tape: once every node here is done, the
+    /// gradients of all of them go into that graph together in one more pass.
+    pub fn backward_into(&self, seeds: &[(usize, &TensorValue<T>)], gradients: &mut Gradients<T>) -> Result<()> {
+        gradients.reset(self.nodes.len());
+        for &(output, seed) in seeds {
+            gradients.seed(output, seed)?;
+        }
+        for index in (0..self.nodes.len()).rev() {
+            ops::_backward(self, index, gradients)?;
+        }
+        to_dtype::backward_casts(std::mem::take(&mut gradients.casts))
+    }
+}
+
+impl<T: Float> Tensor<T> {
+    /// Records the graph leading to this tensor. It is the last node of the tape.
+    pub(crate) fn _build_topo(&self) -> Tape<T> {
+        Tape::record(std::slice::from_ref(self))
+    }
+}
+
+#[test]
+fn tape_records_shared_nodes_once() -> Result<()> {
+    let x = Tensor::vector(vec![1.0, 2.0]);
+    let squared = x.multiply(&x)?;
+    let y = squared.add(&x)?.sum()?;
+
+    let tape = y._build_topo();
+    assert_eq!(tape.len(), 4);
+    assert_eq!(tape.index_of(&x), Some(0));
+    assert_eq!(tape.dependencies(1), &[0, 0]);
+    assert_eq!(tape.dependencies(2), &[1, 0]);
+    assert_eq!(tape.index_of(&y), Some(3));
+    Ok(())
+}
+
+#[test]
+fn tape_handles_deep_graphs() -> Result<()> {
+    let x = Tensor::scalar(0.5);
+    let mut y = x.clone();
+    for _ in 0..20_000 {
+        y = y.multiply(&Tensor::scalar(1.0))?;
+    }
+    y.backward()?;
+    assert_eq!(x.grad().unwrap().value().flatten(), vec![1.0]);
+
+    // Dropping a long chain of `Rc`s is itself recursive; unwind it by hand.
+    while !y.is_leaf() {
+        let next = y.data.borrow().dependencies[0].clone();
+        y = next;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn count_ops(loss: &Tensor) -> usize {
+    loss._build_topo().nodes().iter().filter(|node| !node.is_leaf()).count()
+}
+
+/// Average time per op of `step`, in nanoseconds.
+#[cfg(test)]
+fn time_per_op<F>(steps: usize, step: F) -> Result<f64>
+where
+    F: Fn() -> Result<Tensor>,
+{
+    // Warm up the allocator before timing.
+    let mut loss = step()?;
+    for _ in 0..steps / 10 {
+        loss = step()?;
+    }
+    let ops = count_ops(&loss) * steps;
+    let start = std::time::Instant::now();
+    for _ in 0..steps {
+        step()?;
+    }
+    Ok(start.elapsed().as_secs_f64() * 1e9 / ops as f64)
+}
+
+/// Per-op cost of a full training step (forward, backward, SGD update), held
+/// to a loose bound so that a regression in the ops themselves fails; the
+/// graph bookkeeping is compared against `Rc` nodes by
+/// `arena_bookkeeping_beats_rc_nodes`.
+/// Run with `cargo test --release -- --ignored bench_training_step`.
+#[test]
+#[ignore]
+fn bench_training_step() -> Result<()> {
+    use crate::nn::{Module, Optimizer};
+    use crate::nn::layer::Linear;
+    use crate::nn::optimizer::SGD;
+
+    let optimizer = SGD::new(0.01);
+    let xor = [Linear::new(2, 4), Linear::new(4, 1)];
+    let x = Tensor::vector(vec![1.0, 0.0]);
+    let target = Tensor::vector(vec![1.0]);
+    let xor_ns = time_per_op(20_000, || {
+        let params: Vec<&Tensor> = xor.iter().flat_map(|layer| layer.parameters()).collect();
+        optimizer.zero_grad(&params);
+        let hidden = xor[0].forward(&x)?.tanh()?;
+        let output = xor[1].forward(&hidden)?.sigmoid()?;
+        let loss = crate::loss_fn::bce_loss(&output, &target)?;
+        loss.backward()?;
+        optimizer.step(&params);
+        Ok(loss)
+    })?;
+
+    let mlp: Vec<Linear> = [(16, 32), (32, 32), (32, 32), (32, 32), (32, 4)].iter()
+        .map(|&(input, output)| Linear::new(input, output))
+        .collect();
+    let x = Tensor::vector((0..16).map(|i| (i as f64 * 0.37).sin()).collect());
+    let target = Tensor::vector(vec![0.1, 0.2, 0.3, 0.4]);
+    let mlp_ns = time_per_op(2_000, || {
+        let params: Vec<&Tensor> = mlp.iter().flat_map(|layer| layer.parameters()).collect();
+        optimizer.zero_grad(&params);
+        let mut hidden = x.clone();
+        for layer in &mlp {
+            hidden = layer.forward(&hidden)?.tanh()?;
+        }
+        let loss = hidden.sub(&target)?.pow(2.0)?.sum()?;
+        loss.backward()?;
+        optimizer.step(&params);
+        Ok(loss)
+    })?;
+
+    // About 1 us/op for XOR and 10 us/op for the matmul-bound MLP when written.
+    assert!(xor_ns < 5_000.0 && mlp_ns < 50_000.0, "xor: {:.0} ns/op, mlp: {:.0} ns/op", xor_ns, mlp_ns);
+    Ok(())
+}
+
+/// A node as it was stored before the arena, with its own `Rc` allocation and
+/// found again through a set of node pointers.
+#[cfg(test)]
+#[derive(Clone)]
+struct RcNode(std::rc::Rc<std::cell::RefCell<(super::node::NodeData, Vec<RcNode>)>>);
+
+#[cfg(test)]
+fn rc_build_topo(node: &RcNode, nodes: &mut Vec<RcNode>, visited: &mut std::collections::HashSet<*const ()>) {
+    if !visited.insert(node.0.as_ptr() as *const ()) {
+        return;
+    }
+    for dep in &node.0.borrow().1 {
+        rc_build_topo(dep, nodes, visited);
+    }
+    nodes.push(node.clone());
+}
+
+/// Nanoseconds per node spent on graph bookkeeping alone (creating the nodes
+/// of an MLP-shaped graph, recording it, walking it backward and dropping it)
+/// with `build(dependencies)` making a node and `pass(output)` the rest.
+#[cfg(test)]
+fn bookkeeping_ns<N: Clone>(build: impl Fn(Vec<N>) -> N, pass: impl Fn(&N)) -> f64 {
+    let layers = 50;
+    let params: Vec<(N, N)> = (0..layers).map(|_| (build(vec![]), build(vec![]))).collect();
+    let step = || {
+        let mut hidden = build(vec![]);
+        for (weight, bias) in &params {
+            let product = build(vec![weight.clone(), hidden]);
+            let shifted = build(vec![product, bias.clone()]);
+            hidden = build(vec![shifted]);
+        }
+        pass(&hidden);
+    };
+    // Fastest of several rounds, so that a busy machine does not decide it.
+    (0..5).map(|_| {
+        let start = std::time::Instant::now();
+        for _ in 0..200 {
+            step();
+        }
+        start.elapsed().as_secs_f64() * 1e9 / (200 * (3 * layers + 1)) as f64
+    }).fold(f64::INFINITY, f64::min)
+}
+
+#[test]
+fn arena_bookkeeping_beats_rc_nodes() {
+    use super::arena::NodeRef;
+    use super::node::NodeData;
+    use super::operation::Operation;
+
+    let node = |dependencies| NodeData {
+        value: TensorValue::Scalar(0.0),
+        grad: None,
+        retain_grad: false,
+        grad_graph: None,
+        operation: Operation::Tanh,
+        dependencies,
+        hooks: vec![],
+        created_at: None,
+        tangent: None,
+    };
+
+    let rc_ns = bookkeeping_ns(
+        |dependencies| RcNode(std::rc::Rc::new(std::cell::RefCell::new((node(vec![]), dependencies)))),
+        |output| {
+            let mut nodes = Vec::new();
+            rc_build_topo(output, &mut nodes, &mut std::collections::HashSet::new());
+            output.0.borrow_mut().0.grad = Some(TensorValue::Scalar(1.0));
+            for node in nodes.iter().rev() {
+                let data = node.0.borrow();
+                let grad = data.0.grad.clone();
+                for dep in &data.1 {
+                    dep.0.borrow_mut().0.grad = grad.clone();
+                }
+            }
+        },
+    );
+    let arena_ns = bookkeeping_ns(
+        |dependencies| Tensor { data: NodeRef::new(node(dependencies)) },
+        |output| {
+            let tape = output._build_topo();
+            let mut grads = vec![None; tape.len()];
+            grads[tape.len() - 1] = Some(TensorValue::Scalar(1.0));
+            for index in (0..tape.len()).rev() {
+                let _data = tape.nodes()[index].data.borrow();
+                let grad = grads[index].clone();
+                for &dep in tape.dependencies(index) {
+                    grads[dep] = grad.clone();
+                }
+            }
+        },
+    );
+    assert!(arena_ns < rc_ns, "arena: {:.0} ns/node, Rc: {:.0} ns/node", arena_ns, rc_ns);
+}
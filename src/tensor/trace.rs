@@ -0,0 +1,264 @@
+// Graph tracing: record the op sequence of a forward pass once, then replay
+// forward and backward on new data without rebuilding the graph.
+
+use crate::tensor::dtype::Float;
+use super::tape::{Gradients, Tape};
+use super::value::TensorValue;
+use super::{ops, Tensor};
+use anyhow::Result;
+
+/// A recorded graph of `f`, replayed by `forward` and `backward`.
+///
+/// Replaying reuses the recorded nodes, their dependency lists, the buffers
+/// intermediate gradients are accumulated into and, for the element-wise ops
+/// and matmul, their value buffers, so a training step no longer allocates a
+/// node per op. Parameters
+/// captured by `f` are shared with the recorded graph, so optimizer updates are
+/// picked up by the next replay.
+///
+/// `f` must build the same graph for inputs of the same shapes: tensors it
+/// creates from data (constants) are frozen at recording time, and control flow
+/// that depends on values is not re-evaluated. When the input shapes change,
+/// `forward` falls back to running `f` eagerly and records the new graph.
+pub struct Trace<F, T: Float = f64>
+where
+    F: Fn(&[Tensor<T>]) -> Result<Tensor<T>>,
+{
+    f: F,
+    inputs: Vec<Tensor<T>>,
+    output: Tensor<T>,
+    tape: Tape<T>,
+    gradients: Gradients<T>,
+    seed: TensorValue<T>,
+}
+
+impl<F, T: Float> Trace<F, T>
+where
+    F: Fn(&[Tensor<T>]) -> Result<Tensor<T>>,
+{
+    /// Runs `f` eagerly on placeholders holding the values of `inputs` and
+    /// records the resulting graph.
+    pub fn record(f: F, inputs: &[Tensor<T>]) -> Result<Self> {
+        let placeholders: Vec<Tensor<T>> = inputs.iter()
+            .map(|input| Tensor::from_value(input.value()))
+            .collect();
+        let output = f(&placeholders)?;
+        let tape = output._build_topo();
+        let seed = output.data.borrow().value.ones_like();
+
+        Ok(Trace { f, inputs: placeholders, output, tape, gradients: Gradients::new(), seed })
+    }
+
+    /// Replays the recorded graph on the values of `inputs` and returns the
+    /// (recorded) output tensor.
+    pub fn forward(&mut self, inputs: &[Tensor<T>]) -> Result<Tensor<T>> {
+        let same_shapes = inputs.len() == self.inputs.len()
+            && inputs.iter().zip(&self.inputs).all(|(input, placeholder)| {
+                input.shape() == placeholder.shape()
+            });
+        if !same_shapes {
+            let placeholders: Vec<Tensor<T>> = inputs.iter()
+                .map(|input| Tensor::from_value(input.value()))
+                .collect();
+            self.output = (self.f)(&placeholders)?;
+            self.inputs = placeholders;
+            self.tape = self.output._build_topo();
+            self.seed = self.output.data.borrow().value.ones_like();
+            return Ok(self.output.clone());
+        }
+
+        for (placeholder, input) in self.inputs.iter().zip(inputs) {
+            let value = input.value();
+            placeholder.data.borrow_mut().value = value;
+        }
+        for node in self.tape.nodes() {
+            if node.is_leaf() {
+                continue;
+            }
+            ops::_forward_into(node)?;
+        }
+        Ok(self.output.clone())
+    }
+
+    /// Backward through the recorded graph. Gradients of the inputs are reset
+    /// first; parameter gradients accumulate as with `Tensor::backward`.
+    pub fn backward(&mut self) -> Result<()> {
+        for input in &self.inputs {
+            input.zero_grad();
+        }
+        self.tape.backward_into(&[(self.tape.len() - 1, &self.seed)], &mut self.gradients)
+    }
+
+    /// The placeholders standing in for the inputs; their `grad` holds the
+    /// input gradients after `backward`.
+    pub fn inputs(&self) -> &[Tensor<T>] {
+        &self.inputs
+    }
+
+    pub fn output(&self) -> &Tensor<T> {
+        &self.output
+    }
+}
+
+#[cfg(test)]
+fn xor_parameters() -> Vec<Tensor> {
+    vec![
+        Tensor::matrix(vec![vec![0.5, -0.4], vec![0.3, 0.8], vec![-0.6, 0.2], vec![0.1, -0.9]]),
+        Tensor::vector(vec![0.01, -0.02, 0.03, 0.0]),
+        Tensor::matrix(vec![vec![0.7, -0.5, 0.4, 0.2]]),
+        Tensor::vector(vec![0.0]),
+    ]
+}
+
+#[cfg(test)]
+fn xor_loss(params: &[Tensor], x: &[Tensor]) -> Result<Tensor> {
+    let hidden = params[0].matmul(&x[0])?.add(&params[1])?.tanh()?;
+    let outputs = params[2].matmul(&hidden)?.add(&params[3])?.sigmoid()?;
+    crate::loss_fn::bce_loss(&outputs, &x[1])
+}
+
+#[test]
+fn trace_replay_matches_eager_training() -> Result<()> {
+    use crate::nn::Optimizer;
+    use crate::nn::optimizer::SGD;
+
+    let data = [
+        (vec![1.0, 1.0], 0.0),
+        (vec![0.0, 1.0], 1.0),
+        (vec![1.0, 0.0], 1.0),
+        (vec![0.0, 0.0], 0.0),
+    ];
+    let optimizer = SGD::new(0.1);
+
+    let eager = xor_parameters();
+    let eager_refs: Vec<&Tensor> = eager.iter().collect();
+    for _ in 0..50 {
+        for (input, target) in &data {
+            optimizer.zero_grad(&eager_refs);
+            let loss = xor_loss(&eager, &[Tensor::vector(input.clone()), Tensor::vector(vec![*target])])?;
+            loss.backward()?;
+            optimizer.step(&eager_refs);
+        }
+    }
+
+    let traced = xor_parameters();
+    let traced_refs: Vec<&Tensor> = traced.iter().collect();
+    let example = [Tensor::vector(vec![0.0, 0.0]), Tensor::vector(vec![0.0])];
+    let mut trace = Trace::record(|x| xor_loss(&traced, x), &example)?;
+    let recorded_output = trace.output().clone();
+    for _ in 0..50 {
+        for (input, target) in &data {
+            optimizer.zero_grad(&traced_refs);
+            let loss = trace.forward(&[Tensor::vector(input.clone()), Tensor::vector(vec![*target])])?;
+            assert!(loss.data.ptr_eq(&recorded_output.data));
+            trace.backward()?;
+            optimizer.step(&traced_refs);
+        }
+    }
+
+    for (eager_param, traced_param) in eager.iter().zip(&traced) {
+        assert_eq!(eager_param.value().flatten(), traced_param.value().flatten());
+    }
+    Ok(())
+}
+
+#[test]
+fn trace_falls_back_to_eager_on_shape_change() -> Result<()> {
+    let longer = Tensor::vector(vec![1.0, 2.0, 3.0]);
+    let mut trace = Trace::record(|x| x[0].multiply(&x[0])?.sum(), &[Tensor::vector(vec![1.0, 2.0])])?;
+
+    assert_eq!(trace.forward(&[Tensor::vector(vec![3.0, 4.0])])?.to_scalar()?, 25.0);
+    let retraced = trace.forward(std::slice::from_ref(&longer))?;
+    assert_eq!(retraced.to_scalar()?, 14.0);
+
+    // The new shape is now the recorded one and replays in place.
+    let replayed = trace.forward(&[Tensor::vector(vec![0.0, 1.0, 1.0])])?;
+    assert!(retraced.data.ptr_eq(&replayed.data));
+    assert_eq!(replayed.to_scalar()?, 2.0);
+
+    trace.backward()?;
+    assert_eq!(trace.inputs()[0].grad().unwrap().value().flatten(), vec![0.0, 2.0, 2.0]);
+    assert!(longer.grad().is_none());
+    Ok(())
+}
+
+#[test]
+fn trace_backward_resets_intermediate_grads() -> Result<()> {
+    let w = Tensor::vector(vec![2.0, -1.0]);
+    let mut trace = Trace::record(|x| x[0].multiply(&w)?.tanh()?.sum(), &[Tensor::vector(vec![0.5, 0.5])])?;
+
+    let x = Tensor::vector(vec![0.3, -0.2]);
+    for _ in 0..3 {
+        w.data.borrow_mut().grad = None;
+        trace.forward(std::slice::from_ref(&x))?;
+        trace.backward()?;
+    }
+
+    let eager_w = Tensor::vector(vec![2.0, -1.0]);
+    x.multiply(&eager_w)?.tanh()?.sum()?.backward()?;
+    assert_eq!(w.grad().unwrap().value().flatten(), eager_w.grad().unwrap().value().flatten());
+    Ok(())
+}
+
+#[test]
+fn trace_replay_reuses_value_buffers() -> Result<()> {
+    let w = Tensor::matrix(vec![vec![0.5, -0.4], vec![0.3, 0.8]]);
+    let b = Tensor::vector(vec![0.1, -0.2]);
+    let mut trace = Trace::record(|x| w.matmul(&x[0])?.add(&b)?.tanh(), &[Tensor::vector(vec![0.0, 0.0])])?;
+
+    let buffer = |tensor: &Tensor| match &tensor.data.borrow().value {
+        super::value::TensorValue::Vector1D(v) => v.as_ptr(),
+        value => panic!("Expected a vector, got {:?}", value),
+    };
+    let before = buffer(trace.output());
+    let x = Tensor::vector(vec![0.7, -1.3]);
+    let output = trace.forward(std::slice::from_ref(&x))?;
+    assert_eq!(buffer(&output), before);
+    assert_eq!(output.value().flatten(), w.matmul(&x)?.add(&b)?.tanh()?.value().flatten());
+    Ok(())
+}
+
+#[test]
+fn trace_backward_reuses_gradient_buffers() -> Result<()> {
+    let w = Tensor::matrix(vec![vec![0.5, -0.4], vec![0.3, 0.8]]);
+    let b = Tensor::vector(vec![0.1, -0.2]);
+    let mut trace = Trace::record(|x| w.matmul(&x[0])?.add(&b)?.tanh()?.multiply(&x[0])?.sum(), &[Tensor::vector(vec![0.0, 0.0])])?;
+
+    let buffers = |trace: &Trace<_>| -> Vec<*const f64> {
+        let mut buffers: Vec<*const f64> = (0..trace.tape.len())
+            .filter_map(|index| match trace.gradients.get(index)? {
+                TensorValue::Vector1D(v) => Some(v.as_ptr()),
+                TensorValue::Matrix2D(m) => Some(m[0].as_ptr()),
+                _ => None,
+            })
+            .collect();
+        for tensor in [&w, &b, &trace.inputs()[0]] {
+            buffers.push(match &tensor.data.borrow().grad {
+                Some(TensorValue::Vector1D(v)) => v.as_ptr(),
+                Some(TensorValue::Matrix2D(m)) => m[0].as_ptr(),
+                grad => panic!("Unexpected gradient {:?}", grad),
+            });
+        }
+        buffers
+    };
+    trace.forward(&[Tensor::vector(vec![0.7, -1.3])])?;
+    trace.backward()?;
+    let before = buffers(&trace);
+    assert!(before.len() > 3);
+
+    let x = Tensor::vector(vec![-0.4, 0.9]);
+    for tensor in [&w, &b] {
+        tensor.zero_grad();
+    }
+    trace.forward(std::slice::from_ref(&x))?;
+    trace.backward()?;
+    assert_eq!(buffers(&trace), before);
+
+    let (eager_w, eager_b) = (Tensor::matrix(w.to_matrix()?), Tensor::vector(b.value().flatten()));
+    let eager_x = Tensor::vector(x.value().flatten());
+    eager_w.matmul(&eager_x)?.add(&eager_b)?.tanh()?.multiply(&eager_x)?.sum()?.backward()?;
+    for (traced, eager) in [(&w, &eager_w), (&b, &eager_b), (&trace.inputs()[0], &eager_x)] {
+        assert_eq!(traced.grad().unwrap().value().flatten(), eager.grad().unwrap().value().flatten());
+    }
+    Ok(())
+}
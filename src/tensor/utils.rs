@@ -1,8 +1,10 @@
-pub fn transpose(matrix: &Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+use crate::tensor::dtype::Float;
+
+pub fn transpose<T: Float>(matrix: &Vec<Vec<T>>) -> Vec<Vec<T>> {
     let rows = matrix.len();
     let cols = matrix[0].len();
 
-    let mut result = vec![vec![0.0; rows]; cols];
+    let mut result = vec![vec![T::zero(); rows]; cols];
 
     for i in 0..rows {
         for j in 0..cols{
@@ -12,10 +14,15 @@ pub fn transpose(matrix: &Vec<Vec<f64>>) -> Vec<Vec<f64>> {
     result
 }
 
-pub fn matrix_multiply(a_mat: &Vec<Vec<f64>>, b_mat: &Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+pub fn matrix_multiply<T: Float>(a_mat: &Vec<Vec<T>>, b_mat: &Vec<Vec<T>>) -> Vec<Vec<T>> {
 
-    let mut result = vec![vec![0.0; b_mat[0].len()]; a_mat.len()];
+    let mut result = vec![vec![T::zero(); b_mat[0].len()]; a_mat.len()];
+    matrix_multiply_into(&mut result, a_mat, b_mat);
+    result
+}
 
+/// `matrix_multiply` writing into a zeroed `result` of the right shape.
+pub fn matrix_multiply_into<T: Float>(result: &mut [Vec<T>], a_mat: &[Vec<T>], b_mat: &[Vec<T>]) {
     for i in 0..a_mat.len() {
         for j in 0..b_mat[0].len() {
             for k in 0..a_mat[0].len() {
@@ -23,5 +30,4 @@ pub fn matrix_multiply(a_mat: &Vec<Vec<f64>>, b_mat: &Vec<Vec<f64>>) -> Vec<Vec<
             }
         }
     }
-    result
-}
\ No newline at end of file
+}
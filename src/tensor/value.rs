@@ -1,22 +1,294 @@
+use super::dtype::Float;
+use anyhow::{bail, Result};
+
 #[derive(Debug, Clone)]
-pub enum TensorValue {
-    Scalar(f64),
-    Vector1D(Vec<f64>),
-    Matrix2D(Vec<Vec<f64>>),
-    Tensor3D(Vec<Vec<Vec<f64>>>),
+pub enum TensorValue<T = f64> {
+    Scalar(T),
+    Vector1D(Vec<T>),
+    Matrix2D(Vec<Vec<T>>),
+    Tensor3D(Vec<Vec<Vec<T>>>),
+    Tensor4D(Vec<Vec<Vec<Vec<T>>>>),
 }
 
-impl TensorValue {
+impl<T: Float> TensorValue<T> {
     fn zeros_like(&self) -> Self {
         match self {
-            TensorValue::Scalar(_) => TensorValue::Scalar(0.0),
-            TensorValue::Vector1D(v) => TensorValue::Vector1D(vec![0.0; v.len()]),
+            TensorValue::Scalar(_) => TensorValue::Scalar(T::zero()),
+            TensorValue::Vector1D(v) => TensorValue::Vector1D(vec![T::zero(); v.len()]),
             TensorValue::Matrix2D(m) => {
-                TensorValue::Matrix2D(vec![vec![0.0; m[0].len()]; m.len()])
+                TensorValue::Matrix2D(vec![vec![T::zero(); m[0].len()]; m.len()])
             }
             TensorValue::Tensor3D(t) => {
-                TensorValue::Tensor3D(vec![vec![vec![0.0; t[0][0].len()]; t[0].len()]; t.len()])
+                TensorValue::Tensor3D(vec![vec![vec![T::zero(); t[0][0].len()]; t[0].len()]; t.len()])
+            }
+            TensorValue::Tensor4D(_) => Self::full(&self.shape(), T::zero()),
+        }
+    }
+
+    pub fn full(shape: &[usize], value: T) -> Self {
+        match shape.len() {
+            0 => TensorValue::Scalar(value),
+            1 => TensorValue::Vector1D(vec![value; shape[0]]),
+            2 => TensorValue::Matrix2D(vec![vec![value; shape[1]]; shape[0]]),
+            3 => TensorValue::Tensor3D(vec![vec![vec![value; shape[2]]; shape[1]]; shape[0]]),
+            4 => TensorValue::Tensor4D(vec![vec![vec![vec![value; shape[3]]; shape[2]]; shape[1]]; shape[0]]),
+            _ => panic!("Unsupported shape {:?}", shape),
+        }
+    }
+
+    pub fn ones_like(&self) -> Self {
+        Self::full(&self.shape(), T::one())
+    }
+
+    /// Overwrites every element in place, keeping the allocation.
+    pub fn fill(&mut self, value: T) {
+        match self {
+            TensorValue::Scalar(s) => *s = value,
+            TensorValue::Vector1D(v) => v.fill(value),
+            TensorValue::Matrix2D(m) => m.iter_mut().for_each(|row| row.fill(value)),
+            TensorValue::Tensor3D(t) => t.iter_mut()
+                .for_each(|m| m.iter_mut().for_each(|row| row.fill(value))),
+            TensorValue::Tensor4D(t) => t.iter_mut().flatten()
+                .for_each(|m| m.iter_mut().for_each(|row| row.fill(value))),
+        }
+    }
+
+    pub fn map(&self, f: impl Fn(T) -> T) -> Self {
+        match self {
+            TensorValue::Scalar(s) => TensorValue::Scalar(f(*s)),
+            TensorValue::Vector1D(v) => TensorValue::Vector1D(v.iter().map(|x| f(*x)).collect()),
+            TensorValue::Matrix2D(m) => TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(|x| f(*x)).collect()).collect()
+            ),
+            TensorValue::Tensor3D(t) => TensorValue::Tensor3D(
+                t.iter().map(|m| {
+                    m.iter().map(|row| row.iter().map(|x| f(*x)).collect()).collect()
+                }).collect()
+            ),
+            TensorValue::Tensor4D(t) => TensorValue::Tensor4D(
+                t.iter().map(|c| c.iter().map(|m| {
+                    m.iter().map(|row| row.iter().map(|x| f(*x)).collect()).collect()
+                }).collect()).collect()
+            ),
+        }
+    }
+
+    /// Element-wise combination of two values. A scalar is broadcast against
+    /// anything, and a vector is broadcast against every row of a matrix.
+    pub fn zip_map(&self, other: &Self, f: impl Fn(T, T) -> T) -> Self {
+        match (self, other) {
+            (TensorValue::Scalar(a), _) => other.map(|b| f(*a, b)),
+            (_, TensorValue::Scalar(b)) => self.map(|a| f(a, *b)),
+            (TensorValue::Vector1D(a), TensorValue::Vector1D(b)) => {
+                assert_eq!(a.len(), b.len(), "Vector length mismatch in zip_map");
+                TensorValue::Vector1D(a.iter().zip(b).map(|(x, y)| f(*x, *y)).collect())
+            }
+            (TensorValue::Matrix2D(a), TensorValue::Matrix2D(b)) => {
+                assert!(self.same_shape(other), "Matrix shape mismatch in zip_map");
+                TensorValue::Matrix2D(
+                    a.iter().zip(b).map(|(a_row, b_row)| {
+                        a_row.iter().zip(b_row).map(|(x, y)| f(*x, *y)).collect()
+                    }).collect()
+                )
             }
+            (TensorValue::Matrix2D(a), TensorValue::Vector1D(b)) => {
+                assert_eq!(a[0].len(), b.len(), "Matrix cols mismatch with vector length in zip_map");
+                TensorValue::Matrix2D(
+                    a.iter().map(|row| row.iter().zip(b).map(|(x, y)| f(*x, *y)).collect()).collect()
+                )
+            }
+            (TensorValue::Vector1D(a), TensorValue::Matrix2D(b)) => {
+                assert_eq!(a.len(), b[0].len(), "Matrix cols mismatch with vector length in zip_map");
+                TensorValue::Matrix2D(
+                    b.iter().map(|row| a.iter().zip(row).map(|(x, y)| f(*x, *y)).collect()).collect()
+                )
+            }
+            (TensorValue::Tensor3D(a), TensorValue::Tensor3D(b)) => {
+                assert!(self.same_shape(other), "Tensor shape mismatch in zip_map");
+                TensorValue::Tensor3D(
+                    a.iter().zip(b).map(|(a_1, b_1)| {
+                        a_1.iter().zip(b_1).map(|(a_2, b_2)| {
+                            a_2.iter().zip(b_2).map(|(x, y)| f(*x, *y)).collect()
+                        }).collect()
+                    }).collect()
+                )
+            }
+            (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => {
+                assert!(self.same_shape(other), "Tensor shape mismatch in zip_map");
+                let data: Vec<T> = self.flatten().into_iter().zip(other.flatten()).map(|(x, y)| f(x, y)).collect();
+                TensorValue::from_flat(&self.shape(), &data)
+            }
+            _ => panic!("Mismatched types in zip_map operation"),
+        }
+    }
+
+    /// Overwrites this value with `f` of every element of `src`, keeping the
+    /// allocation. Returns false, leaving it untouched, when the shapes differ.
+    pub fn map_into(&mut self, src: &Self, f: impl Fn(T) -> T) -> bool {
+        if !self.same_shape(src) {
+            return false;
+        }
+        match (self, src) {
+            (TensorValue::Scalar(out), TensorValue::Scalar(x)) => *out = f(*x),
+            (TensorValue::Vector1D(out), TensorValue::Vector1D(x)) => {
+                out.iter_mut().zip(x).for_each(|(out, x)| *out = f(*x))
+            }
+            (TensorValue::Matrix2D(out), TensorValue::Matrix2D(x)) => {
+                out.iter_mut().flatten().zip(x.iter().flatten()).for_each(|(out, x)| *out = f(*x))
+            }
+            (TensorValue::Tensor3D(out), TensorValue::Tensor3D(x)) => {
+                out.iter_mut().flatten().flatten().zip(x.iter().flatten().flatten())
+                    .for_each(|(out, x)| *out = f(*x))
+            }
+            (TensorValue::Tensor4D(out), TensorValue::Tensor4D(x)) => {
+                out.iter_mut().flatten().flatten().flatten().zip(x.iter().flatten().flatten().flatten())
+                    .for_each(|(out, x)| *out = f(*x))
+            }
+            _ => unreachable!(),
+        }
+        true
+    }
+
+    /// In-place `zip_map` of `a` and `b`, for operands of the same shape, a
+    /// scalar against anything, or a vector against every row of a matrix.
+    /// Returns false, leaving this value untouched, for anything else or when
+    /// this value does not already have the shape of the result.
+    pub fn zip_map_into(&mut self, a: &Self, b: &Self, f: impl Fn(T, T) -> T) -> bool {
+        match (a, b) {
+            (TensorValue::Scalar(a), _) => return self.map_into(b, |y| f(*a, y)),
+            (_, TensorValue::Scalar(b)) => return self.map_into(a, |x| f(x, *b)),
+            _ => {}
+        }
+        if !self.same_shape(a) {
+            return false;
+        }
+        match (self, a, b) {
+            (TensorValue::Matrix2D(out), TensorValue::Matrix2D(a), TensorValue::Vector1D(b)) if a[0].len() == b.len() => {
+                for (out_row, a_row) in out.iter_mut().zip(a) {
+                    out_row.iter_mut().zip(a_row).zip(b).for_each(|((out, x), y)| *out = f(*x, *y));
+                }
+            }
+            (out, a, b) if a.same_shape(b) => match (out, a, b) {
+                (TensorValue::Vector1D(out), TensorValue::Vector1D(a), TensorValue::Vector1D(b)) => {
+                    out.iter_mut().zip(a.iter().zip(b)).for_each(|(out, (x, y))| *out = f(*x, *y))
+                }
+                (TensorValue::Matrix2D(out), TensorValue::Matrix2D(a), TensorValue::Matrix2D(b)) => {
+                    out.iter_mut().flatten().zip(a.iter().flatten().zip(b.iter().flatten()))
+                        .for_each(|(out, (x, y))| *out = f(*x, *y))
+                }
+                (TensorValue::Tensor3D(out), TensorValue::Tensor3D(a), TensorValue::Tensor3D(b)) => {
+                    out.iter_mut().flatten().flatten()
+                        .zip(a.iter().flatten().flatten().zip(b.iter().flatten().flatten()))
+                        .for_each(|(out, (x, y))| *out = f(*x, *y))
+                }
+                (TensorValue::Tensor4D(out), TensorValue::Tensor4D(a), TensorValue::Tensor4D(b)) => {
+                    out.iter_mut().flatten().flatten().flatten()
+                        .zip(a.iter().flatten().flatten().flatten().zip(b.iter().flatten().flatten().flatten()))
+                        .for_each(|(out, (x, y))| *out = f(*x, *y))
+                }
+                _ => unreachable!(),
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Adds `delta` in place. A smaller `delta` is broadcast over this value, and
+    /// a larger one, from an op that broadcast this value, is summed down to it.
+    /// Fails, leaving this value untouched, when the shapes do not line up.
+    pub fn accumulate(&mut self, delta: &Self) -> Result<()> {
+        let fits = match (&*self, delta) {
+            (TensorValue::Scalar(_), _) | (_, TensorValue::Scalar(_)) => true,
+            (TensorValue::Matrix2D(a), TensorValue::Vector1D(b)) => a[0].len() == b.len(),
+            (TensorValue::Vector1D(a), TensorValue::Matrix2D(b)) => a.len() == b[0].len(),
+            _ => self.same_shape(delta),
+        };
+        if !fits {
+            bail!("Cannot accumulate a gradient of shape {:?} into one of shape {:?}", delta.shape(), self.shape());
+        }
+        match (&mut *self, delta) {
+            (TensorValue::Scalar(a), TensorValue::Scalar(b)) => *a += *b,
+            (TensorValue::Vector1D(a), TensorValue::Vector1D(b)) => {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += *b)
+            }
+            (TensorValue::Matrix2D(a), TensorValue::Matrix2D(b)) => {
+                a.iter_mut().zip(b).for_each(|(a_row, b_row)| {
+                    a_row.iter_mut().zip(b_row).for_each(|(a, b)| *a += *b)
+                })
+            }
+            (TensorValue::Tensor3D(a), TensorValue::Tensor3D(b)) => {
+                a.iter_mut().flatten().flatten().zip(b.iter().flatten().flatten())
+                    .for_each(|(a, b)| *a += *b)
+            }
+            (TensorValue::Tensor4D(a), TensorValue::Tensor4D(b)) => {
+                a.iter_mut().flatten().flatten().flatten().zip(b.iter().flatten().flatten().flatten())
+                    .for_each(|(a, b)| *a += *b)
+            }
+
+            // 梯度广播
+            (TensorValue::Vector1D(a), TensorValue::Scalar(b)) => {
+                a.iter_mut().for_each(|a| *a += *b)
+            }
+            (TensorValue::Matrix2D(a), TensorValue::Vector1D(b)) => {
+                a.iter_mut().for_each(|a_row| {
+                    a_row.iter_mut().zip(b).for_each(|(a, b_val)| *a += *b_val)
+                })
+            }
+            (TensorValue::Matrix2D(a), TensorValue::Scalar(b)) => {
+                a.iter_mut().for_each(|a_row| {
+                    a_row.iter_mut().for_each(|a| *a += *b)
+                })
+            }
+
+            // 广播梯度求和
+            (TensorValue::Scalar(a), TensorValue::Vector1D(b)) => {
+                *a += b.iter().copied().sum::<T>()
+            }
+            (TensorValue::Scalar(a), TensorValue::Matrix2D(b)) => {
+                *a += b.iter().flatten().copied().sum::<T>()
+            }
+            (TensorValue::Scalar(a), TensorValue::Tensor3D(_) | TensorValue::Tensor4D(_)) => {
+                *a += delta.flatten().into_iter().sum::<T>()
+            }
+            (TensorValue::Vector1D(a), TensorValue::Matrix2D(b)) => {
+                b.iter().for_each(|b_row| {
+                    a.iter_mut().zip(b_row).for_each(|(a, b)| *a += *b)
+                })
+            }
+
+            _ => bail!("Cannot accumulate a gradient of shape {:?} into one of shape {:?}", delta.shape(), self.shape()),
+        }
+        Ok(())
+    }
+
+    /// Inverse of `flatten`: rebuilds a value of `shape` from row-major data.
+    pub fn from_flat(shape: &[usize], data: &[T]) -> Self {
+        assert_eq!(shape.iter().product::<usize>(), data.len(), "Data length does not match shape {:?}", shape);
+        match shape.len() {
+            0 => TensorValue::Scalar(data[0]),
+            1 => TensorValue::Vector1D(data.to_vec()),
+            2 => TensorValue::Matrix2D(data.chunks(shape[1]).map(|row| row.to_vec()).collect()),
+            3 => TensorValue::Tensor3D(
+                data.chunks(shape[1] * shape[2])
+                    .map(|m| m.chunks(shape[2]).map(|row| row.to_vec()).collect())
+                    .collect()
+            ),
+            4 => TensorValue::Tensor4D(
+                data.chunks(shape[1] * shape[2] * shape[3])
+                    .map(|t| Self::from_flat(&shape[1..], t).into_tensor3d())
+                    .collect()
+            ),
+            _ => panic!("Unsupported shape {:?}", shape),
+        }
+    }
+
+    pub fn flatten(&self) -> Vec<T> {
+        match self {
+            TensorValue::Scalar(s) => vec![*s],
+            TensorValue::Vector1D(v) => v.clone(),
+            TensorValue::Matrix2D(m) => m.iter().flatten().copied().collect(),
+            TensorValue::Tensor3D(t) => t.iter().flatten().flatten().copied().collect(),
+            TensorValue::Tensor4D(t) => t.iter().flatten().flatten().flatten().copied().collect(),
         }
     }
 
@@ -26,16 +298,33 @@ impl TensorValue {
             TensorValue::Vector1D(v) => vec![v.len()],
             TensorValue::Matrix2D(m) => vec![m.len(), m[0].len()],
             TensorValue::Tensor3D(t) => vec![t.len(), t[0].len(), t[0][0].len()],
+            TensorValue::Tensor4D(t) => vec![t.len(), t[0].len(), t[0][0].len(), t[0][0][0].len()],
+        }
+    }
+
+    /// `self.shape() == other.shape()` without allocating either shape.
+    pub fn same_shape(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TensorValue::Scalar(_), TensorValue::Scalar(_)) => true,
+            (TensorValue::Vector1D(a), TensorValue::Vector1D(b)) => a.len() == b.len(),
+            (TensorValue::Matrix2D(a), TensorValue::Matrix2D(b)) => {
+                a.len() == b.len() && a[0].len() == b[0].len()
+            }
+            (TensorValue::Tensor3D(a), TensorValue::Tensor3D(b)) => {
+                a.len() == b.len() && a[0].len() == b[0].len() && a[0][0].len() == b[0][0].len()
+            }
+            (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => self.shape() == other.shape(),
+            _ => false,
         }
     }
 
     pub fn sub(&self, other: &Self) -> Self {
         match (self, other) {
-            (TensorValue::Scalar(a), TensorValue::Scalar(b)) => TensorValue::Scalar(a - b),
+            (TensorValue::Scalar(a), TensorValue::Scalar(b)) => TensorValue::Scalar(*a - *b),
             (TensorValue::Vector1D(a), TensorValue::Vector1D(b)) => {
                 assert_eq!(a.len(), b.len(), "Vector length mismatch in sub");
                 TensorValue::Vector1D(
-                    a.iter().zip(b).map(|(x, y)| x - y).collect()
+                    a.iter().zip(b).map(|(x, y)| *x - *y).collect()
                 )
             }
             (TensorValue::Matrix2D(a), TensorValue::Matrix2D(b)) => {
@@ -43,7 +332,7 @@ impl TensorValue {
                 assert_eq!(a[0].len(), b[0].len(), "Matrix column mismatch in sub");
                 TensorValue::Matrix2D(
                     a.iter().zip(b).map(|(a_row, b_row)| {
-                        a_row.iter().zip(b_row).map(|(x, y)| x - y).collect()
+                        a_row.iter().zip(b_row).map(|(x, y)| *x - *y).collect()
                     }).collect()
                 )
             }
@@ -55,12 +344,50 @@ impl TensorValue {
                 TensorValue::Tensor3D(
                     a.iter().zip(b).map(|(a_1, b_1)| {
                         a_1.iter().zip(b_1).map(|(a_2, b_2)| {
-                            a_2.iter().zip(b_2).map(|(a_3, b_3)| a_3 - b_3).collect()
+                            a_2.iter().zip(b_2).map(|(a_3, b_3)| *a_3 - *b_3).collect()
                         }).collect()
                     }).collect()
                 )
             }
+            (TensorValue::Tensor4D(_), TensorValue::Tensor4D(_)) => self.zip_map(other, |a, b| a - b),
             _ => panic!("Mismatched types in sub operation"),
         }
     }
-}
\ No newline at end of file
+
+    /// Converts every element to another float type.
+    pub fn cast<U: Float>(&self) -> TensorValue<U> {
+        let cast = |x: &T| U::from_f64(x.into_f64());
+        match self {
+            TensorValue::Scalar(s) => TensorValue::Scalar(cast(s)),
+            TensorValue::Vector1D(v) => TensorValue::Vector1D(v.iter().map(cast).collect()),
+            TensorValue::Matrix2D(m) => TensorValue::Matrix2D(
+                m.iter().map(|row| row.iter().map(cast).collect()).collect()
+            ),
+            TensorValue::Tensor3D(t) => TensorValue::Tensor3D(
+                t.iter().map(|m| m.iter().map(|row| row.iter().map(cast).collect()).collect()).collect()
+            ),
+            TensorValue::Tensor4D(_) => TensorValue::from_flat(&self.shape(), &self.flatten().iter().map(cast).collect::<Vec<U>>()),
+        }
+    }
+
+    fn into_tensor3d(self) -> Vec<Vec<Vec<T>>> {
+        match self {
+            TensorValue::Tensor3D(t) => t,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn accumulate_rejects_mismatched_shapes() {
+    let mut total = TensorValue::full(&[2, 3, 4], 1.0);
+    assert!(total.accumulate(&TensorValue::full(&[2, 3, 2], 1.0)).is_err());
+    assert!(total.accumulate(&TensorValue::full(&[2, 4, 3], 1.0)).is_err());
+    assert_eq!(total.flatten(), vec![1.0; 24]);
+
+    let mut matrix = TensorValue::full(&[2, 3], 0.0);
+    assert!(matrix.accumulate(&TensorValue::full(&[3, 3], 1.0)).is_err());
+    assert!(matrix.accumulate(&TensorValue::full(&[2], 1.0)).is_err());
+    assert!(matrix.accumulate(&TensorValue::full(&[3], 1.0)).is_ok());
+    assert_eq!(matrix.flatten(), vec![1.0; 6]);
+}